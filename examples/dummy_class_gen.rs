@@ -17,19 +17,21 @@ use ka_pi::{
 fn main() {
   let mut writer = ClassWriter::new();
 
-  writer.visit(
-    JavaVersion::V17,
-    ClassAccessFlag::Super | ClassAccessFlag::Public,
-    "Main",
-    None,
-    "java/lang/Object",
-    &[],
-  );
+  writer
+    .visit(
+      JavaVersion::V17,
+      ClassAccessFlag::Super | ClassAccessFlag::Public,
+      "Main",
+      None,
+      "java/lang/Object",
+      &[],
+    )
+    .unwrap();
 
   writer.visit_source("Main.java");
   writer.visit_debug_extension("Debug Message");
 
-  let mut mw = writer
+  let mw = writer
     .visit_method(
       MethodAccessFlag::Public | MethodAccessFlag::Static,
       "main",
@@ -37,19 +39,22 @@ fn main() {
       None,
       &[],
     )
+    .unwrap()
     .unwrap();
 
   mw.visit_code();
 
   let mut label = Label::default();
 
-  mw.visit_jump_inst(opcodes::GOTO, &mut label);
-  mw.visit_label(&mut label);
-  mw.visit_inst(opcodes::RETURN);
+  mw.visit_jump_inst(opcodes::GOTO, &mut label).unwrap();
+  mw.visit_label(&mut label).unwrap();
+  mw.visit_simple_inst(opcodes::RETURN).unwrap();
 
   writer.visit_end();
 
-  let bytes = writer.to_bytes();
+  let bytes = writer
+    .to_bytes()
+    .expect("Unexpected error while generating class file bytecode");
 
   fs::write("output/Main.class", bytes)
     .expect("Unexpected error while writing class file bytecode");
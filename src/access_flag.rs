@@ -1,4 +1,15 @@
-use bitflags::bitflags;
+use bitflags::{
+  bitflags,
+  Flags,
+};
+
+use crate::{
+  class::JavaVersion,
+  error::{
+    KapiError,
+    KapiResult,
+  },
+};
 
 bitflags! {
     /// Access flag for [node::class::Class].
@@ -119,8 +130,494 @@ bitflags! {
     }
 }
 
-impl Default for ClassAccessFlag {
-  fn default() -> Self {
-    Self::empty()
+macro_rules! impl_empty_default {
+  ($($flag:ty),+) => {
+    $(
+      impl Default for $flag {
+        fn default() -> Self {
+          Self::empty()
+        }
+      }
+    )+
+  };
+}
+
+impl_empty_default!(
+  ClassAccessFlag,
+  FieldAccessFlag,
+  MethodAccessFlag,
+  NestedClassAccessFlag,
+  ParameterAccessFlag,
+  ModuleAccessFlag,
+  RequiresAccessFlag,
+  ExportsAccessFlag,
+  OpensAccessFlag
+);
+
+/// Formats set flags as `Final | Abstract`.
+fn flag_names<F: Flags<Bits = u16>>(flags: F) -> String {
+  let mut names = String::new();
+
+  bitflags::parser::to_writer(&flags, &mut names).expect("Writing to String never fails");
+
+  names
+}
+
+/// Fails if more than one of `exclusive` flags is set.
+fn check_exclusive<F: Flags<Bits = u16> + Copy>(
+  kind: &str,
+  access: F,
+  exclusive: F,
+) -> KapiResult<()> {
+  let conflicts = access.intersection(exclusive);
+
+  if conflicts.bits().count_ones() > 1 {
+    return Err(KapiError::ArgError(format!(
+      "{kind} access flags {} are mutually exclusive",
+      flag_names(conflicts)
+    )));
+  }
+
+  Ok(())
+}
+
+/// Fails if any of `forbidden` flags is set, `context` tells when they are
+/// forbidden.
+fn check_forbidden<F: Flags<Bits = u16> + Copy>(
+  kind: &str,
+  access: F,
+  forbidden: F,
+  context: &str,
+) -> KapiResult<()> {
+  let conflicts = access.intersection(forbidden);
+
+  if !conflicts.is_empty() {
+    return Err(KapiError::ArgError(format!(
+      "{kind} access flags {} cannot be set {context}",
+      flag_names(conflicts)
+    )));
+  }
+
+  Ok(())
+}
+
+/// Fails if any of `required` flags is missing, `context` tells when they
+/// are required.
+fn check_required<F: Flags<Bits = u16> + Copy>(
+  kind: &str,
+  access: F,
+  required: F,
+  context: &str,
+) -> KapiResult<()> {
+  let missing = required.difference(access);
+
+  if !missing.is_empty() {
+    return Err(KapiError::ArgError(format!(
+      "{kind} access flags {} must be set {context}",
+      flag_names(missing)
+    )));
+  }
+
+  Ok(())
+}
+
+/// Validates flag combinations of a class, see JVMS 4.1.
+pub fn validate_class_flags(access: ClassAccessFlag, version: JavaVersion) -> KapiResult<()> {
+  const KIND: &str = "Class";
+
+  if access.contains(ClassAccessFlag::Module) {
+    if version.major_version() < 53 {
+      return Err(KapiError::ArgError(format!(
+        "Class access flag Module requires class file version 53 or above, but got {}",
+        version.major_version()
+      )));
+    }
+
+    return check_forbidden(
+      KIND,
+      access,
+      ClassAccessFlag::Module.complement(),
+      "along with Module",
+    );
+  }
+
+  if access.contains(ClassAccessFlag::Interface) {
+    check_required(KIND, access, ClassAccessFlag::Abstract, "on interface")?;
+    check_forbidden(
+      KIND,
+      access,
+      ClassAccessFlag::Final | ClassAccessFlag::Super | ClassAccessFlag::Enum,
+      "on interface",
+    )
+  } else {
+    check_forbidden(
+      KIND,
+      access,
+      ClassAccessFlag::Annotation,
+      "on non-interface class",
+    )?;
+    check_exclusive(
+      KIND,
+      access,
+      ClassAccessFlag::Final | ClassAccessFlag::Abstract,
+    )
+  }
+}
+
+/// Validates flag combinations of a field declared in class with
+/// `class_access`, see JVMS 4.5.
+pub fn validate_field_flags(
+  access: FieldAccessFlag,
+  class_access: ClassAccessFlag,
+) -> KapiResult<()> {
+  const KIND: &str = "Field";
+
+  check_exclusive(
+    KIND,
+    access,
+    FieldAccessFlag::Public | FieldAccessFlag::Private | FieldAccessFlag::Protected,
+  )?;
+  check_exclusive(
+    KIND,
+    access,
+    FieldAccessFlag::Final | FieldAccessFlag::Volatile,
+  )?;
+
+  if class_access.contains(ClassAccessFlag::Interface) {
+    check_required(
+      KIND,
+      access,
+      FieldAccessFlag::Public | FieldAccessFlag::Static | FieldAccessFlag::Final,
+      "in interface",
+    )?;
+    check_forbidden(
+      KIND,
+      access,
+      FieldAccessFlag::Volatile | FieldAccessFlag::Transient | FieldAccessFlag::Enum,
+      "in interface",
+    )?;
+  }
+
+  Ok(())
+}
+
+/// Validates flag combinations of a method named `name` declared in class
+/// with `class_access`, see JVMS 4.6.
+pub fn validate_method_flags(
+  access: MethodAccessFlag,
+  name: &str,
+  class_access: ClassAccessFlag,
+  version: JavaVersion,
+) -> KapiResult<()> {
+  const KIND: &str = "Method";
+
+  let major = version.major_version();
+
+  // Class initialization methods ignore all flags but Static
+  if name == "<clinit>" {
+    return if major >= 51 {
+      check_required(
+        KIND,
+        access,
+        MethodAccessFlag::Static,
+        "on <clinit> since class file version 51",
+      )
+    } else {
+      Ok(())
+    };
+  }
+
+  check_exclusive(
+    KIND,
+    access,
+    MethodAccessFlag::Public | MethodAccessFlag::Private | MethodAccessFlag::Protected,
+  )?;
+
+  if class_access.contains(ClassAccessFlag::Interface) {
+    check_forbidden(
+      KIND,
+      access,
+      MethodAccessFlag::Protected
+        | MethodAccessFlag::Final
+        | MethodAccessFlag::Synchronized
+        | MethodAccessFlag::Native,
+      "in interface",
+    )?;
+
+    if major < 52 {
+      check_required(
+        KIND,
+        access,
+        MethodAccessFlag::Public | MethodAccessFlag::Abstract,
+        "in interface before class file version 52",
+      )?;
+    } else if !access.intersects(MethodAccessFlag::Public | MethodAccessFlag::Private) {
+      return Err(KapiError::ArgError(String::from(
+        "Method access flags Public or Private must be set in interface",
+      )));
+    }
+  }
+
+  if name == "<init>" {
+    check_forbidden(
+      KIND,
+      access,
+      MethodAccessFlag::Static
+        | MethodAccessFlag::Final
+        | MethodAccessFlag::Synchronized
+        | MethodAccessFlag::Bridge
+        | MethodAccessFlag::Native
+        | MethodAccessFlag::Abstract,
+      "on <init>",
+    )?;
+  }
+
+  if access.contains(MethodAccessFlag::Abstract) {
+    let mut forbidden = MethodAccessFlag::Private
+      | MethodAccessFlag::Static
+      | MethodAccessFlag::Final
+      | MethodAccessFlag::Synchronized
+      | MethodAccessFlag::Native;
+
+    // Strict is implied for all methods since class file version 61
+    if (46..=60).contains(&major) {
+      forbidden |= MethodAccessFlag::Strict;
+    }
+
+    check_forbidden(KIND, access, forbidden, "along with Abstract")?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    access_flag::{
+      validate_class_flags,
+      validate_field_flags,
+      validate_method_flags,
+      ClassAccessFlag as C,
+      FieldAccessFlag as F,
+      MethodAccessFlag as M,
+    },
+    class::JavaVersion,
+    error::{
+      KapiError,
+      KapiResult,
+    },
+  };
+
+  fn expected(error: Option<&str>) -> KapiResult<()> {
+    error.map_or(Ok(()), |error| Err(KapiError::ArgError(error.to_owned())))
+  }
+
+  #[test]
+  fn test_class_flags() {
+    let cases = [
+      (C::Public | C::Super, JavaVersion::V17, None),
+      (
+        C::Final | C::Abstract,
+        JavaVersion::V17,
+        Some("Class access flags Final | Abstract are mutually exclusive"),
+      ),
+      (
+        C::Annotation | C::Abstract,
+        JavaVersion::V17,
+        Some("Class access flags Annotation cannot be set on non-interface class"),
+      ),
+      (
+        C::Interface | C::Abstract | C::Annotation,
+        JavaVersion::V17,
+        None,
+      ),
+      (
+        C::Interface,
+        JavaVersion::V17,
+        Some("Class access flags Abstract must be set on interface"),
+      ),
+      (
+        C::Interface | C::Abstract | C::Super | C::Final,
+        JavaVersion::V17,
+        Some("Class access flags Final | Super cannot be set on interface"),
+      ),
+      (C::Module, JavaVersion::V9, None),
+      (
+        C::Module,
+        JavaVersion::V1_8,
+        Some("Class access flag Module requires class file version 53 or above, but got 52"),
+      ),
+      (
+        C::Module | C::Public,
+        JavaVersion::V9,
+        Some("Class access flags Public cannot be set along with Module"),
+      ),
+    ];
+
+    for (access, version, error) in cases {
+      assert_eq!(
+        validate_class_flags(access, version),
+        expected(error),
+        "{access:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_field_flags() {
+    let class = C::Public | C::Super;
+    let interface = C::Interface | C::Abstract;
+    let cases = [
+      (F::Private | F::Volatile, class, None),
+      (
+        F::Public | F::Protected,
+        class,
+        Some("Field access flags Public | Protected are mutually exclusive"),
+      ),
+      (
+        F::Final | F::Volatile,
+        class,
+        Some("Field access flags Final | Volatile are mutually exclusive"),
+      ),
+      (
+        F::Public | F::Static | F::Final | F::Synthetic,
+        interface,
+        None,
+      ),
+      (
+        F::Public | F::Static,
+        interface,
+        Some("Field access flags Final must be set in interface"),
+      ),
+      (
+        F::Public | F::Static | F::Final | F::Transient,
+        interface,
+        Some("Field access flags Transient cannot be set in interface"),
+      ),
+    ];
+
+    for (access, class_access, error) in cases {
+      assert_eq!(
+        validate_field_flags(access, class_access),
+        expected(error),
+        "{access:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_method_flags() {
+    let class = C::Public | C::Super;
+    let interface = C::Interface | C::Abstract;
+    let cases = [
+      (
+        "run",
+        M::Public | M::Abstract,
+        class,
+        JavaVersion::V17,
+        None,
+      ),
+      (
+        "run",
+        M::Public | M::Private,
+        class,
+        JavaVersion::V17,
+        Some("Method access flags Public | Private are mutually exclusive"),
+      ),
+      (
+        "run",
+        M::Abstract | M::Static | M::Final,
+        class,
+        JavaVersion::V17,
+        Some("Method access flags Static | Final cannot be set along with Abstract"),
+      ),
+      (
+        "run",
+        M::Abstract | M::Strict,
+        class,
+        JavaVersion::V16,
+        Some("Method access flags Strict cannot be set along with Abstract"),
+      ),
+      (
+        "run",
+        M::Abstract | M::Strict,
+        class,
+        JavaVersion::V17,
+        None,
+      ),
+      (
+        "<init>",
+        M::Private | M::Varargs,
+        class,
+        JavaVersion::V17,
+        None,
+      ),
+      (
+        "<init>",
+        M::Public | M::Static,
+        class,
+        JavaVersion::V17,
+        Some("Method access flags Static cannot be set on <init>"),
+      ),
+      (
+        "<clinit>",
+        M::Static | M::Final,
+        class,
+        JavaVersion::V17,
+        None,
+      ),
+      ("<clinit>", M::empty(), class, JavaVersion::V1_6, None),
+      (
+        "<clinit>",
+        M::empty(),
+        interface,
+        JavaVersion::V1_7,
+        Some("Method access flags Static must be set on <clinit> since class file version 51"),
+      ),
+      ("run", M::Public, interface, JavaVersion::V1_8, None),
+      (
+        "run",
+        M::Private | M::Static,
+        interface,
+        JavaVersion::V9,
+        None,
+      ),
+      (
+        "run",
+        M::Static,
+        interface,
+        JavaVersion::V1_8,
+        Some("Method access flags Public or Private must be set in interface"),
+      ),
+      (
+        "run",
+        M::Public,
+        interface,
+        JavaVersion::V1_7,
+        Some("Method access flags Abstract must be set in interface before class file version 52"),
+      ),
+      (
+        "run",
+        M::Public | M::Synchronized,
+        interface,
+        JavaVersion::V17,
+        Some("Method access flags Synchronized cannot be set in interface"),
+      ),
+      (
+        "run",
+        M::Private | M::Abstract,
+        interface,
+        JavaVersion::V17,
+        Some("Method access flags Private cannot be set along with Abstract"),
+      ),
+    ];
+
+    for (name, access, class_access, version, error) in cases {
+      assert_eq!(
+        validate_method_flags(access, name, class_access, version),
+        expected(error),
+        "{name} {access:?}"
+      );
+    }
   }
 }
@@ -0,0 +1,788 @@
+use std::{
+  cell::RefCell,
+  rc::Rc,
+};
+
+use crate::{
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    ToBytes,
+  },
+  constant::ConstantPool,
+  error::{
+    KapiError,
+    KapiResult,
+  },
+};
+
+/// Constant element value of an annotation, see JVMS 4.7.16.1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnnotationValue {
+  Byte(i8),
+  Char(u16),
+  Double(f64),
+  Float(f32),
+  Int(i32),
+  Long(i64),
+  Short(i16),
+  Boolean(bool),
+  String(String),
+}
+
+pub trait AnnotationVisitor {
+  fn inner(&mut self) -> Option<&mut dyn AnnotationVisitor> {
+    None
+  }
+
+  fn visit(&mut self, name: &str, value: AnnotationValue) {
+    if let Some(inner) = self.inner() {
+      inner.visit(name, value);
+    }
+  }
+
+  fn visit_enum(&mut self, name: &str, descriptor: &str, value: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_enum(name, descriptor, value);
+    }
+  }
+
+  fn visit_class(&mut self, name: &str, descriptor: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_class(name, descriptor);
+    }
+  }
+
+  fn visit_annotation(
+    &mut self,
+    name: &str,
+    descriptor: &str,
+  ) -> Option<&mut dyn AnnotationVisitor> {
+    if let Some(inner) = self.inner() {
+      inner.visit_annotation(name, descriptor)
+    } else {
+      None
+    }
+  }
+
+  /// Visits an array element value, names passed to returned visitor are
+  /// ignored.
+  fn visit_array(&mut self, name: &str) -> Option<&mut dyn AnnotationVisitor> {
+    if let Some(inner) = self.inner() {
+      inner.visit_array(name)
+    } else {
+      None
+    }
+  }
+
+  fn visit_end(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_end();
+    }
+  }
+}
+
+#[derive(Debug)]
+enum ElementValue {
+  Const(u8, u16),
+  // (type_name_index, const_name_index)
+  Enum(u16, u16),
+  Class(u16),
+  Annotation(AnnotationWriter),
+  // Writer of array element values, which has no type_index
+  Array(AnnotationWriter),
+}
+
+impl ElementValue {
+  fn size(&self) -> usize {
+    match self {
+      ElementValue::Const(..) => 3,
+      ElementValue::Enum(..) => 5,
+      ElementValue::Class(..) => 3,
+      ElementValue::Annotation(annotation) => 1 + annotation.compute_size(),
+      ElementValue::Array(array) => 1 + array.compute_size(),
+    }
+  }
+}
+
+impl ToBytes for ElementValue {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    match self {
+      ElementValue::Const(tag, const_value_index) => {
+        vec.push_u8(*tag).push_u16(*const_value_index);
+      }
+      ElementValue::Enum(type_name_index, const_name_index) => {
+        vec
+          .push_u8(b'e')
+          .push_u16(*type_name_index)
+          .push_u16(*const_name_index);
+      }
+      ElementValue::Class(class_info_index) => {
+        vec.push_u8(b'c').push_u16(*class_info_index);
+      }
+      ElementValue::Annotation(annotation) => {
+        vec.push_u8(b'@');
+        annotation.put_bytes(vec)?;
+      }
+      ElementValue::Array(array) => {
+        vec.push_u8(b'[');
+        array.put_bytes(vec)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub struct AnnotationWriter {
+  constant_pool: Rc<RefCell<ConstantPool>>,
+  // None if current writer writes array element values
+  type_index: Option<u16>,
+  // (element_name_index, element_value), element_name_index is 0 for array
+  // element values
+  element_value_pairs: Vec<(u16, ElementValue)>,
+}
+
+impl AnnotationWriter {
+  pub(crate) fn new(
+    constant_pool: Rc<RefCell<ConstantPool>>,
+    descriptor: &str,
+  ) -> KapiResult<Self> {
+    let type_index = constant_pool.borrow_mut().put_utf8(descriptor)?;
+
+    Ok(Self {
+      constant_pool,
+      type_index: Some(type_index),
+      element_value_pairs: Vec::new(),
+    })
+  }
+
+  fn new_array(constant_pool: Rc<RefCell<ConstantPool>>) -> Self {
+    Self {
+      constant_pool,
+      type_index: None,
+      element_value_pairs: Vec::new(),
+    }
+  }
+
+  pub(crate) fn compute_size(&self) -> usize {
+    let element_value_pairs_size = self
+      .element_value_pairs
+      .iter()
+      .map(|(_, element_value)| element_value.size())
+      .sum::<usize>();
+
+    if self.type_index.is_some() {
+      4 + 2 * self.element_value_pairs.len() + element_value_pairs_size
+    } else {
+      2 + element_value_pairs_size
+    }
+  }
+
+  /// Puts an element value, [None] if its name does not fit into the
+  /// constant pool.
+  fn put_element_value(
+    &mut self,
+    name: &str,
+    element_value: ElementValue,
+  ) -> Option<&mut ElementValue> {
+    let name_index = if self.type_index.is_some() {
+      self.constant_pool.borrow_mut().put_utf8(name).ok()?
+    } else {
+      0
+    };
+
+    self.element_value_pairs.push((name_index, element_value));
+
+    self
+      .element_value_pairs
+      .last_mut()
+      .map(|(_, element_value)| element_value)
+  }
+}
+
+impl AnnotationVisitor for AnnotationWriter {
+  fn visit(&mut self, name: &str, value: AnnotationValue) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let (tag, const_value_index) = match value {
+      AnnotationValue::Byte(byte) => (b'B', cp.put_integer(byte as i32)),
+      AnnotationValue::Char(char) => (b'C', cp.put_integer(char as i32)),
+      AnnotationValue::Double(double) => (b'D', cp.put_double(double)),
+      AnnotationValue::Float(float) => (b'F', cp.put_float(float)),
+      AnnotationValue::Int(int) => (b'I', cp.put_integer(int)),
+      AnnotationValue::Long(long) => (b'J', cp.put_long(long)),
+      AnnotationValue::Short(short) => (b'S', cp.put_integer(short as i32)),
+      AnnotationValue::Boolean(boolean) => (b'Z', cp.put_integer(boolean as i32)),
+      AnnotationValue::String(string) => (b's', cp.put_utf8(string)),
+    };
+
+    drop(cp);
+
+    if let Ok(const_value_index) = const_value_index {
+      self.put_element_value(name, ElementValue::Const(tag, const_value_index));
+    }
+  }
+
+  fn visit_enum(&mut self, name: &str, descriptor: &str, value: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let (Ok(type_name_index), Ok(const_name_index)) = (cp.put_utf8(descriptor), cp.put_utf8(value))
+    else {
+      return;
+    };
+
+    drop(cp);
+
+    self.put_element_value(name, ElementValue::Enum(type_name_index, const_name_index));
+  }
+
+  fn visit_class(&mut self, name: &str, descriptor: &str) {
+    let Ok(class_info_index) = self.constant_pool.borrow_mut().put_utf8(descriptor) else {
+      return;
+    };
+
+    self.put_element_value(name, ElementValue::Class(class_info_index));
+  }
+
+  fn visit_annotation(
+    &mut self,
+    name: &str,
+    descriptor: &str,
+  ) -> Option<&mut dyn AnnotationVisitor> {
+    let annotation = AnnotationWriter::new(self.constant_pool.clone(), descriptor).ok()?;
+
+    match self.put_element_value(name, ElementValue::Annotation(annotation))? {
+      ElementValue::Annotation(annotation) => Some(annotation),
+      _ => unreachable!(),
+    }
+  }
+
+  fn visit_array(&mut self, name: &str) -> Option<&mut dyn AnnotationVisitor> {
+    let array = AnnotationWriter::new_array(self.constant_pool.clone());
+
+    match self.put_element_value(name, ElementValue::Array(array))? {
+      ElementValue::Array(array) => Some(array),
+      _ => unreachable!(),
+    }
+  }
+}
+
+impl ToBytes for AnnotationWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    if let Some(type_index) = self.type_index {
+      vec.push_u16(type_index);
+    }
+
+    vec.push_u16(self.element_value_pairs.len() as u16);
+
+    for (name_index, element_value) in &self.element_value_pairs {
+      if self.type_index.is_some() {
+        vec.push_u16(*name_index);
+      }
+
+      element_value.put_bytes(vec)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Computes size of a RuntimeVisibleAnnotations or RuntimeInvisibleAnnotations
+/// attribute, 0 if there's no annotation to write.
+pub(crate) fn compute_annotations_size(annotations: &[AnnotationWriter]) -> usize {
+  if annotations.is_empty() {
+    0
+  } else {
+    8 + annotations
+      .iter()
+      .map(AnnotationWriter::compute_size)
+      .sum::<usize>()
+  }
+}
+
+/// Writes a RuntimeVisibleAnnotations or RuntimeInvisibleAnnotations attribute
+/// named `attribute_name`, nothing is written if there's no annotation.
+pub(crate) fn put_annotations(
+  cp: &ConstantPool,
+  vec: &mut ByteVec,
+  attribute_name: &str,
+  annotations: &[AnnotationWriter],
+) -> KapiResult<()> {
+  if annotations.is_empty() {
+    return Ok(());
+  }
+
+  vec.push_u16(cp.get_utf8(attribute_name).unwrap());
+
+  let attr_len = vec.reserve_slot(4);
+
+  vec.push_u16(annotations.len() as u16);
+
+  for annotation in annotations {
+    annotation.put_bytes(vec)?;
+  }
+
+  vec.patch_len(attr_len)
+}
+
+/// Target of a type annotation outside of code, see JVMS 4.7.20.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TargetInfo {
+  /// Type parameter declaration of generic class or interface.
+  ClassTypeParameter { index: u8 },
+  /// Type parameter declaration of generic method or constructor.
+  MethodTypeParameter { index: u8 },
+  /// Type in extends or implements clause, `index` is 65535 for superclass,
+  /// otherwise it's the index into interfaces.
+  Supertype { index: u16 },
+  /// Bound of type parameter declaration of generic class or interface.
+  ClassTypeParameterBound {
+    type_parameter_index: u8,
+    bound_index: u8,
+  },
+  /// Bound of type parameter declaration of generic method or constructor.
+  MethodTypeParameterBound {
+    type_parameter_index: u8,
+    bound_index: u8,
+  },
+  /// Type in field or record component declaration.
+  Field,
+  /// Return type of method, or type of newly constructed object.
+  MethodReturn,
+  /// Receiver type of method or constructor.
+  MethodReceiver,
+  /// Type in formal parameter declaration of method, constructor, or lambda
+  /// expression.
+  MethodFormalParameter { index: u8 },
+  /// Type in throws clause of method or constructor, `index` is the index
+  /// into exceptions.
+  Throws { index: u16 },
+}
+
+impl TargetInfo {
+  pub const fn target_type(&self) -> u8 {
+    match self {
+      TargetInfo::ClassTypeParameter { .. } => 0x00,
+      TargetInfo::MethodTypeParameter { .. } => 0x01,
+      TargetInfo::Supertype { .. } => 0x10,
+      TargetInfo::ClassTypeParameterBound { .. } => 0x11,
+      TargetInfo::MethodTypeParameterBound { .. } => 0x12,
+      TargetInfo::Field => 0x13,
+      TargetInfo::MethodReturn => 0x14,
+      TargetInfo::MethodReceiver => 0x15,
+      TargetInfo::MethodFormalParameter { .. } => 0x16,
+      TargetInfo::Throws { .. } => 0x17,
+    }
+  }
+
+  /// Whether the target may appear in a class file's attributes.
+  pub const fn is_class_target(&self) -> bool {
+    matches!(
+      self,
+      TargetInfo::ClassTypeParameter { .. }
+        | TargetInfo::Supertype { .. }
+        | TargetInfo::ClassTypeParameterBound { .. }
+    )
+  }
+
+  /// Whether the target may appear in a field's attributes.
+  pub const fn is_field_target(&self) -> bool {
+    matches!(self, TargetInfo::Field)
+  }
+
+  /// Whether the target may appear in a method's attributes.
+  pub const fn is_method_target(&self) -> bool {
+    matches!(
+      self,
+      TargetInfo::MethodTypeParameter { .. }
+        | TargetInfo::MethodTypeParameterBound { .. }
+        | TargetInfo::MethodReturn
+        | TargetInfo::MethodReceiver
+        | TargetInfo::MethodFormalParameter { .. }
+        | TargetInfo::Throws { .. }
+    )
+  }
+}
+
+/// Kind of local variable declaration annotated by
+/// [MethodVisitor::visit_local_variable_annotation](crate::method::MethodVisitor::visit_local_variable_annotation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LocalVariableTarget {
+  /// Type in local variable declaration.
+  LocalVariable,
+  /// Type in resource variable declaration of try-with-resources statement.
+  ResourceVariable,
+}
+
+impl LocalVariableTarget {
+  pub const fn target_type(&self) -> u8 {
+    match self {
+      LocalVariableTarget::LocalVariable => 0x40,
+      LocalVariableTarget::ResourceVariable => 0x41,
+    }
+  }
+}
+
+/// A step of type_path locating the annotated part of a type, see
+/// JVMS 4.7.20.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypePathStep {
+  /// Deeper in an array type.
+  Array,
+  /// Deeper in a nested type.
+  Nested,
+  /// On the bound of a wildcard type argument.
+  WildcardBound,
+  /// On a type argument of a parameterized type.
+  TypeArgument(u8),
+}
+
+impl TypePathStep {
+  fn put(&self, vec: &mut ByteVec) {
+    let (kind, argument_index) = match self {
+      TypePathStep::Array => (0, 0),
+      TypePathStep::Nested => (1, 0),
+      TypePathStep::WildcardBound => (2, 0),
+      TypePathStep::TypeArgument(index) => (3, *index),
+    };
+
+    vec.push_u8(kind).push_u8(argument_index);
+  }
+}
+
+#[derive(Debug)]
+enum TypeAnnotationTarget {
+  Info(TargetInfo),
+  // (start_pc, length, index) of each live range
+  LocalVariable(LocalVariableTarget, Vec<(u16, u16, u16)>),
+}
+
+/// Writes a type_annotation structure, element values are visited through
+/// [AnnotationVisitor].
+#[derive(Debug)]
+pub struct TypeAnnotationWriter {
+  target: TypeAnnotationTarget,
+  type_path: Vec<TypePathStep>,
+  annotation: AnnotationWriter,
+}
+
+impl TypeAnnotationWriter {
+  pub(crate) fn new(
+    constant_pool: Rc<RefCell<ConstantPool>>,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+  ) -> KapiResult<Self> {
+    Ok(Self {
+      target: TypeAnnotationTarget::Info(target_info),
+      type_path: type_path.to_vec(),
+      annotation: AnnotationWriter::new(constant_pool, descriptor)?,
+    })
+  }
+
+  /// Creates writer of a local or resource variable type annotation,
+  /// `ranges` are (start_pc, length, index) of each live range of the
+  /// variable.
+  pub(crate) fn new_local_variable(
+    constant_pool: Rc<RefCell<ConstantPool>>,
+    target: LocalVariableTarget,
+    ranges: Vec<(u16, u16, u16)>,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+  ) -> KapiResult<Self> {
+    Ok(Self {
+      target: TypeAnnotationTarget::LocalVariable(target, ranges),
+      type_path: type_path.to_vec(),
+      annotation: AnnotationWriter::new(constant_pool, descriptor)?,
+    })
+  }
+
+  pub(crate) fn compute_size(&self) -> usize {
+    let target_info_size = match &self.target {
+      TypeAnnotationTarget::Info(
+        TargetInfo::Field | TargetInfo::MethodReturn | TargetInfo::MethodReceiver,
+      ) => 0,
+      TypeAnnotationTarget::Info(
+        TargetInfo::ClassTypeParameter { .. }
+        | TargetInfo::MethodTypeParameter { .. }
+        | TargetInfo::MethodFormalParameter { .. },
+      ) => 1,
+      TypeAnnotationTarget::Info(_) => 2,
+      TypeAnnotationTarget::LocalVariable(_, ranges) => 2 + 6 * ranges.len(),
+    };
+
+    1 + target_info_size + 1 + 2 * self.type_path.len() + self.annotation.compute_size()
+  }
+
+  /// Writes type annotation with bytecode offsets of local variable live
+  /// ranges mapped by `offset`.
+  pub(crate) fn put_bytes_with(
+    &self,
+    vec: &mut ByteVec,
+    offset: &dyn Fn(u16) -> KapiResult<u16>,
+  ) -> KapiResult<()> {
+    match &self.target {
+      TypeAnnotationTarget::Info(target_info) => {
+        vec.push_u8(target_info.target_type());
+
+        match target_info {
+          TargetInfo::ClassTypeParameter { index }
+          | TargetInfo::MethodTypeParameter { index }
+          | TargetInfo::MethodFormalParameter { index } => {
+            vec.push_u8(*index);
+          }
+          TargetInfo::Supertype { index } | TargetInfo::Throws { index } => {
+            vec.push_u16(*index);
+          }
+          TargetInfo::ClassTypeParameterBound {
+            type_parameter_index,
+            bound_index,
+          }
+          | TargetInfo::MethodTypeParameterBound {
+            type_parameter_index,
+            bound_index,
+          } => {
+            vec.push_u8(*type_parameter_index).push_u8(*bound_index);
+          }
+          TargetInfo::Field | TargetInfo::MethodReturn | TargetInfo::MethodReceiver => {}
+        }
+      }
+      TypeAnnotationTarget::LocalVariable(target, ranges) => {
+        vec
+          .push_u8(target.target_type())
+          .push_u16(ranges.len() as u16);
+
+        for (start_pc, length, index) in ranges {
+          let end_pc = start_pc.checked_add(*length).ok_or_else(|| {
+            KapiError::ArgError(format!(
+              "Live range at bytecode offset {start_pc} of length {length} exceeds the limit of 65535"
+            ))
+          })?;
+
+          vec
+            .push_u16(offset(*start_pc)?)
+            .push_u16(offset(end_pc)? - offset(*start_pc)?)
+            .push_u16(*index);
+        }
+      }
+    }
+
+    vec.push_u8(self.type_path.len() as u8);
+
+    for step in &self.type_path {
+      step.put(vec);
+    }
+
+    self.annotation.put_bytes(vec)
+  }
+}
+
+impl AnnotationVisitor for TypeAnnotationWriter {
+  fn inner(&mut self) -> Option<&mut dyn AnnotationVisitor> {
+    Some(&mut self.annotation)
+  }
+}
+
+impl ToBytes for TypeAnnotationWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    self.put_bytes_with(vec, &Ok)
+  }
+}
+
+/// Computes size of a RuntimeVisibleTypeAnnotations or
+/// RuntimeInvisibleTypeAnnotations attribute, 0 if there's no type annotation
+/// to write.
+pub(crate) fn compute_type_annotations_size(type_annotations: &[TypeAnnotationWriter]) -> usize {
+  if type_annotations.is_empty() {
+    0
+  } else {
+    8 + type_annotations
+      .iter()
+      .map(TypeAnnotationWriter::compute_size)
+      .sum::<usize>()
+  }
+}
+
+/// Writes a RuntimeVisibleTypeAnnotations or RuntimeInvisibleTypeAnnotations
+/// attribute named `attribute_name`, nothing is written if there's no type
+/// annotation.
+pub(crate) fn put_type_annotations(
+  cp: &ConstantPool,
+  vec: &mut ByteVec,
+  attribute_name: &str,
+  type_annotations: &[TypeAnnotationWriter],
+  offset: &dyn Fn(u16) -> KapiResult<u16>,
+) -> KapiResult<()> {
+  if type_annotations.is_empty() {
+    return Ok(());
+  }
+
+  vec.push_u16(cp.get_utf8(attribute_name).unwrap());
+
+  let attr_len = vec.reserve_slot(4);
+
+  vec.push_u16(type_annotations.len() as u16);
+
+  for type_annotation in type_annotations {
+    type_annotation.put_bytes_with(vec, offset)?;
+  }
+
+  vec.patch_len(attr_len)
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    cell::RefCell,
+    rc::Rc,
+  };
+
+  use crate::{
+    annotation::{
+      AnnotationValue,
+      AnnotationVisitor,
+      AnnotationWriter,
+      LocalVariableTarget,
+      TargetInfo,
+      TypeAnnotationWriter,
+      TypePathStep,
+    },
+    byte_vec::ToBytes,
+    constant::ConstantPool,
+  };
+
+  #[test]
+  fn test_nested_element_values() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+    let mut aw = AnnotationWriter::new(cp.clone(), "LOuter;").unwrap();
+
+    aw.visit_enum(
+      "policy",
+      "Ljava/lang/annotation/RetentionPolicy;",
+      "RUNTIME",
+    );
+    aw.visit_class("type", "Ljava/lang/String;");
+
+    let inner = aw.visit_annotation("inner", "LInner;").unwrap();
+    let values = inner.visit_array("values").unwrap();
+
+    values.visit("ignored", AnnotationValue::Int(1));
+    values.visit("ignored", AnnotationValue::Int(2));
+    values.visit_end();
+    inner.visit_end();
+    aw.visit_end();
+
+    let mut bytes = Vec::new();
+
+    aw.put_bytes(&mut bytes).unwrap();
+
+    // Constants are deduplicated so these don't add new entries
+    let [one_0, one_1] = cp.borrow_mut().put_integer(1).unwrap().to_be_bytes();
+    let [two_0, two_1] = cp.borrow_mut().put_integer(2).unwrap().to_be_bytes();
+    let cp = cp.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [outer_0, outer_1] = utf8("LOuter;");
+    let [policy_0, policy_1] = utf8("policy");
+    let [retention_0, retention_1] = utf8("Ljava/lang/annotation/RetentionPolicy;");
+    let [runtime_0, runtime_1] = utf8("RUNTIME");
+    let [type_0, type_1] = utf8("type");
+    let [string_0, string_1] = utf8("Ljava/lang/String;");
+    let [inner_0, inner_1] = utf8("inner");
+    let [inner_type_0, inner_type_1] = utf8("LInner;");
+    let [values_0, values_1] = utf8("values");
+
+    assert_eq!(cp.get_utf8("ignored"), None);
+
+    #[rustfmt::skip]
+    assert_eq!(
+      bytes,
+      vec![
+        outer_0, outer_1, 0, 3,
+        policy_0, policy_1, b'e', retention_0, retention_1, runtime_0, runtime_1,
+        type_0, type_1, b'c', string_0, string_1,
+        inner_0, inner_1, b'@', inner_type_0, inner_type_1, 0, 1,
+        values_0, values_1, b'[', 0, 2,
+        b'I', one_0, one_1,
+        b'I', two_0, two_1,
+      ]
+    );
+    assert_eq!(aw.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_type_annotation() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+    let mut taw = TypeAnnotationWriter::new(
+      cp.clone(),
+      TargetInfo::ClassTypeParameterBound {
+        type_parameter_index: 1,
+        bound_index: 0,
+      },
+      &[TypePathStep::TypeArgument(2), TypePathStep::Array],
+      "LNonNull;",
+    )
+    .unwrap();
+
+    taw.visit("value", AnnotationValue::Boolean(true));
+    taw.visit_end();
+
+    let mut bytes = Vec::new();
+
+    taw.put_bytes(&mut bytes).unwrap();
+
+    let [true_0, true_1] = cp.borrow_mut().put_integer(1).unwrap().to_be_bytes();
+    let cp = cp.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [type_0, type_1] = utf8("LNonNull;");
+    let [value_0, value_1] = utf8("value");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      bytes,
+      vec![
+        0x11, 1, 0,
+        2, 3, 2, 0, 0,
+        type_0, type_1, 0, 1,
+        value_0, value_1, b'Z', true_0, true_1,
+      ]
+    );
+    assert_eq!(taw.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_local_variable_type_annotation() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+
+    for (target, target_type) in [
+      (LocalVariableTarget::LocalVariable, 0x40),
+      (LocalVariableTarget::ResourceVariable, 0x41),
+    ] {
+      let taw = TypeAnnotationWriter::new_local_variable(
+        cp.clone(),
+        target,
+        vec![(2, 5, 1)],
+        &[],
+        "LNonNull;",
+      )
+      .unwrap();
+      let mut bytes = Vec::new();
+
+      taw
+        .put_bytes_with(&mut bytes, &|offset| Ok(offset * 2))
+        .unwrap();
+
+      let [type_0, type_1] = cp.borrow().get_utf8("LNonNull;").unwrap().to_be_bytes();
+
+      #[rustfmt::skip]
+      assert_eq!(
+        bytes,
+        vec![
+          target_type, 0, 1, 0, 4, 0, 10, 0, 1,
+          0,
+          type_0, type_1, 0, 0,
+        ]
+      );
+      assert_eq!(taw.compute_size(), bytes.len());
+    }
+  }
+}
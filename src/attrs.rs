@@ -1,33 +1,26 @@
-pub(crate) const CONSTANT_VALUE: &'static str = "ConstantValue";
-pub(crate) const CODE: &'static str = "Code";
-pub(crate) const STACK_MAP_TABLE: &'static str = "StackMapTable";
-pub(crate) const EXCEPTIONS: &'static str = "Exceptions";
-pub(crate) const INNER_CLASSES: &'static str = "InnerClasses";
-pub(crate) const ENCLOSING_METHOD: &'static str = "EnclosingMethod";
-pub(crate) const SYNTHETIC: &'static str = "Synthetic";
-pub(crate) const SIGNATURE: &'static str = "Signature";
-pub(crate) const SOURCE_FILE: &'static str = "SourceFile";
-pub(crate) const SOURCE_DEBUG_EXTENSION: &'static str = "SourceDebugExtension";
-pub(crate) const LINE_NUMBER_TABLE: &'static str = "LineNumberTable";
-pub(crate) const LOCAL_VARIABLE_TABLE: &'static str = "LocalVariableTable";
-pub(crate) const LOCAL_VARIABLE_TYPE_TABLE: &'static str = "LocalVariableTypeTable";
-pub(crate) const DEPRECATED: &'static str = "Deprecated";
-pub(crate) const RUNTIME_VISIBLE_ANNOTATIONS: &'static str = "RuntimeVisibleAnnotations";
-pub(crate) const RUNTIME_INVISIBLE_ANNOTATIONS: &'static str = "RuntimeInvisibleAnnotations";
-pub(crate) const RUNTIME_VISIBLE_PARAMETER_ANNOTATIONS: &'static str =
-  "RuntimeVisibleParameterAnnotations";
-pub(crate) const RUNTIME_INVISIBLE_PARAMETER_ANNOTATIONS: &'static str =
-  "RuntimeInvisibleParameterAnnotations";
-pub(crate) const RUNTIME_VISIBLE_TYPE_ANNOTATIONS: &'static str = "RuntimeVisibleTypeAnnotations";
-pub(crate) const RUNTIME_INVISIBLE_TYPE_ANNOTATIONS: &'static str =
-  "RuntimeInvisibleTypeAnnotations";
-pub(crate) const ANNOTATION_DEFAULT: &'static str = "AnnotationDefault";
-pub(crate) const BOOTSTRAP_METHODS: &'static str = "BootstrapMethods";
-pub(crate) const METHOD_PARAMETERS: &'static str = "MethodParameters";
-pub(crate) const MODULE: &'static str = "Module";
-pub(crate) const MODULE_PACKAGES: &'static str = "ModulePackages";
-pub(crate) const MODULE_MAIN_CLASS: &'static str = "ModuleMainClass";
-pub(crate) const NEST_HOST: &'static str = "NestHost";
-pub(crate) const NEST_MEMBERS: &'static str = "NestMembers";
-pub(crate) const PERMITTED_SUBCLASSES: &'static str = "PermittedSubclasses";
-pub(crate) const RECORD: &'static str = "Record";
+pub(crate) const CONSTANT_VALUE: &str = "ConstantValue";
+pub(crate) const CODE: &str = "Code";
+pub(crate) const EXCEPTIONS: &str = "Exceptions";
+pub(crate) const INNER_CLASSES: &str = "InnerClasses";
+pub(crate) const ENCLOSING_METHOD: &str = "EnclosingMethod";
+pub(crate) const SYNTHETIC: &str = "Synthetic";
+pub(crate) const SIGNATURE: &str = "Signature";
+pub(crate) const SOURCE_FILE: &str = "SourceFile";
+pub(crate) const SOURCE_DEBUG_EXTENSION: &str = "SourceDebugExtension";
+pub(crate) const LINE_NUMBER_TABLE: &str = "LineNumberTable";
+pub(crate) const LOCAL_VARIABLE_TABLE: &str = "LocalVariableTable";
+pub(crate) const LOCAL_VARIABLE_TYPE_TABLE: &str = "LocalVariableTypeTable";
+pub(crate) const DEPRECATED: &str = "Deprecated";
+pub(crate) const RUNTIME_VISIBLE_ANNOTATIONS: &str = "RuntimeVisibleAnnotations";
+pub(crate) const RUNTIME_INVISIBLE_ANNOTATIONS: &str = "RuntimeInvisibleAnnotations";
+pub(crate) const RUNTIME_VISIBLE_TYPE_ANNOTATIONS: &str = "RuntimeVisibleTypeAnnotations";
+pub(crate) const RUNTIME_INVISIBLE_TYPE_ANNOTATIONS: &str = "RuntimeInvisibleTypeAnnotations";
+pub(crate) const BOOTSTRAP_METHODS: &str = "BootstrapMethods";
+pub(crate) const METHOD_PARAMETERS: &str = "MethodParameters";
+pub(crate) const MODULE: &str = "Module";
+pub(crate) const MODULE_PACKAGES: &str = "ModulePackages";
+pub(crate) const MODULE_MAIN_CLASS: &str = "ModuleMainClass";
+pub(crate) const NEST_HOST: &str = "NestHost";
+pub(crate) const NEST_MEMBERS: &str = "NestMembers";
+pub(crate) const PERMITTED_SUBCLASSES: &str = "PermittedSubclasses";
+pub(crate) const RECORD: &str = "Record";
@@ -0,0 +1,211 @@
+use crate::error::{
+  KapiError,
+  KapiResult,
+};
+
+/// Cursor reading big-endian values from raw bytes, such as the info of an
+/// attribute. Reading past the end returns [KapiError::UnexpectedEnd] and
+/// leaves the cursor unchanged.
+///
+/// ```
+/// use ka_pi::byte_reader::ByteReader;
+///
+/// // A custom attribute holding a version and a length-prefixed name
+/// let info = [0, 3, 0, 4, b'k', b'a', b'p', b'i'];
+/// let mut reader = ByteReader::new(&info);
+///
+/// assert_eq!(reader.read_u16(), Ok(3));
+/// assert_eq!(reader.read_utf8_with_len(), Ok(String::from("kapi")));
+/// assert_eq!(reader.remaining(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ByteReader<'a> {
+  bytes: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+  pub fn new(bytes: &'a [u8]) -> Self {
+    Self { bytes, offset: 0 }
+  }
+
+  /// Gets the offset of next byte to read.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  /// Moves the cursor to `offset`, which may be at most the length of the
+  /// bytes.
+  pub fn set_offset(&mut self, offset: usize) -> KapiResult<()> {
+    if offset > self.bytes.len() {
+      return Err(KapiError::StateError(format!(
+        "Offset {offset} is out of bytes of length {}",
+        self.bytes.len()
+      )));
+    }
+
+    self.offset = offset;
+
+    Ok(())
+  }
+
+  pub fn remaining(&self) -> usize {
+    self.bytes.len() - self.offset
+  }
+
+  /// Reads next `len` bytes as is.
+  pub fn read_bytes(&mut self, len: usize) -> KapiResult<&'a [u8]> {
+    if len > self.remaining() {
+      return Err(KapiError::UnexpectedEnd {
+        offset: self.offset,
+        needed: len,
+        remaining: self.remaining(),
+      });
+    }
+
+    let bytes = &self.bytes[self.offset..self.offset + len];
+
+    self.offset += len;
+
+    Ok(bytes)
+  }
+
+  pub fn skip(&mut self, len: usize) -> KapiResult<()> {
+    self.read_bytes(len).map(|_| ())
+  }
+
+  /// Splits off a reader over next `len` bytes, for nested structures with
+  /// known length. Current reader continues after them.
+  pub fn sub_reader(&mut self, len: usize) -> KapiResult<ByteReader<'a>> {
+    self.read_bytes(len).map(ByteReader::new)
+  }
+
+  fn read_array<const N: usize>(&mut self) -> KapiResult<[u8; N]> {
+    self
+      .read_bytes(N)
+      .map(|bytes| bytes.try_into().expect("Read exactly N bytes"))
+  }
+
+  pub fn read_u8(&mut self) -> KapiResult<u8> {
+    self.read_array().map(u8::from_be_bytes)
+  }
+
+  pub fn read_u16(&mut self) -> KapiResult<u16> {
+    self.read_array().map(u16::from_be_bytes)
+  }
+
+  pub fn read_u32(&mut self) -> KapiResult<u32> {
+    self.read_array().map(u32::from_be_bytes)
+  }
+
+  pub fn read_i8(&mut self) -> KapiResult<i8> {
+    self.read_array().map(i8::from_be_bytes)
+  }
+
+  pub fn read_i16(&mut self) -> KapiResult<i16> {
+    self.read_array().map(i16::from_be_bytes)
+  }
+
+  pub fn read_i32(&mut self) -> KapiResult<i32> {
+    self.read_array().map(i32::from_be_bytes)
+  }
+
+  pub fn read_i64(&mut self) -> KapiResult<i64> {
+    self.read_array().map(i64::from_be_bytes)
+  }
+
+  pub fn read_f32(&mut self) -> KapiResult<f32> {
+    self.read_array().map(f32::from_be_bytes)
+  }
+
+  pub fn read_f64(&mut self) -> KapiResult<f64> {
+    self.read_array().map(f64::from_be_bytes)
+  }
+
+  /// Reads a u2 length followed by as many bytes of modified UTF-8, the
+  /// encoding of Utf8 constants, see JVMS 4.4.7.
+  pub fn read_utf8_with_len(&mut self) -> KapiResult<String> {
+    let start = self.offset;
+    let len = self.read_u16()? as usize;
+    let bytes = self.read_bytes(len).inspect_err(|_| self.offset = start)?;
+
+    cesu8::from_java_cesu8(bytes)
+      .map(|string| string.into_owned())
+      .map_err(|_| {
+        self.offset = start;
+
+        KapiError::StateError(format!("Malformed modified UTF-8 at offset {start}"))
+      })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    byte_reader::ByteReader,
+    error::KapiError,
+  };
+
+  #[test]
+  fn test_read_primitives() {
+    #[rustfmt::skip]
+    let bytes = [
+      0xFF,
+      0x80, 0x00,
+      0xDE, 0xAD, 0xBE, 0xEF,
+      0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE,
+      0x3F, 0xC0, 0x00, 0x00,
+      0x40, 0x09, 0x21, 0xFB, 0x54, 0x44, 0x2D, 0x18,
+    ];
+    let mut reader = ByteReader::new(&bytes);
+
+    assert_eq!(reader.read_i8(), Ok(-1));
+    assert_eq!(reader.read_i16(), Ok(i16::MIN));
+    assert_eq!(reader.read_u32(), Ok(0xDEADBEEF));
+    assert_eq!(reader.read_i64(), Ok(-2));
+    assert_eq!(reader.read_f32(), Ok(1.5));
+    assert_eq!(reader.read_f64(), Ok(std::f64::consts::PI));
+    assert_eq!(reader.remaining(), 0);
+  }
+
+  #[test]
+  fn test_read_utf8_with_len() {
+    // Supplementary characters are encoded as surrogate pairs
+    let mut reader = ByteReader::new(&[0, 8, 0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]);
+
+    assert_eq!(reader.read_utf8_with_len(), Ok(String::from("\0\u{1F600}")));
+
+    let mut reader = ByteReader::new(&[0, 2, 0xC3]);
+
+    assert!(matches!(
+      reader.read_utf8_with_len(),
+      Err(KapiError::UnexpectedEnd { .. })
+    ));
+    assert_eq!(reader.offset(), 0);
+  }
+
+  #[test]
+  fn test_sub_reader_and_bounds() {
+    let mut reader = ByteReader::new(&[0, 1, 0, 2, 3]);
+    let mut sub_reader = reader.sub_reader(4).unwrap();
+
+    assert_eq!(sub_reader.read_u16(), Ok(1));
+    assert_eq!(sub_reader.read_u16(), Ok(2));
+    assert_eq!(
+      sub_reader.read_u8(),
+      Err(KapiError::UnexpectedEnd {
+        offset: 4,
+        needed: 1,
+        remaining: 0
+      })
+    );
+    assert_eq!(reader.offset(), 4);
+    assert!(reader.read_u16().is_err());
+    assert_eq!(reader.read_u8(), Ok(3));
+    assert!(reader.skip(1).is_err());
+    assert_eq!(reader.set_offset(2), Ok(()));
+    assert_eq!(reader.read_u16(), Ok(2));
+    assert!(reader.set_offset(6).is_err());
+    assert_eq!(reader.offset(), 4);
+  }
+}
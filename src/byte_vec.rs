@@ -1,3 +1,8 @@
+use crate::error::{
+  KapiError,
+  KapiResult,
+};
+
 pub(crate) trait SizeComputable {
   /// Gets total size of current class, method, or field.
   fn compute_size(&self) -> usize;
@@ -7,7 +12,27 @@ pub(crate) trait SizeComputable {
 }
 
 pub(crate) trait ToBytes {
-  fn put_bytes(&self, vec: &mut ByteVec);
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()>;
+}
+
+/// Placeholder bytes reserved by [ByteVector::reserve_slot], filled by
+/// [ByteVector::patch] once their value is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PatchHandle {
+  offset: usize,
+  len: usize,
+}
+
+impl PatchHandle {
+  /// Gets the amount of reserved bytes.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Gets the offset right after the reserved bytes.
+  pub fn end(&self) -> usize {
+    self.offset + self.len
+  }
 }
 
 pub trait ByteVector {
@@ -18,6 +43,28 @@ pub trait ByteVector {
   fn push_u16(&mut self, u16: u16) -> &mut Self;
 
   fn push_u32(&mut self, u32: u32) -> &mut Self;
+
+  fn push_i16(&mut self, i16: i16) -> &mut Self;
+
+  fn push_i32(&mut self, i32: i32) -> &mut Self;
+
+  fn push_i64(&mut self, i64: i64) -> &mut Self;
+
+  /// Pushes a u2 length followed by the string in modified UTF-8, as used by
+  /// Utf8 constants, see JVMS 4.4.7.
+  fn push_utf8(&mut self, utf8: &str) -> KapiResult<&mut Self>;
+
+  /// Pushes `len` zero bytes to be patched later, e.g. the length of an
+  /// attribute which is only known after writing it.
+  fn reserve_slot(&mut self, len: usize) -> PatchHandle;
+
+  /// Overwrites bytes reserved by `handle`, `bytes` must be exactly as long
+  /// as the reserved bytes.
+  fn patch(&mut self, handle: PatchHandle, bytes: &[u8]) -> KapiResult<()>;
+
+  /// Patches a 4-byte length reserved by `handle` with the amount of bytes
+  /// pushed after it.
+  fn patch_len(&mut self, handle: PatchHandle) -> KapiResult<()>;
 }
 
 pub type ByteVec = Vec<u8>;
@@ -42,4 +89,145 @@ impl ByteVector for ByteVec {
     self.push_u8s(&u32.to_be_bytes());
     self
   }
+
+  fn push_i16(&mut self, i16: i16) -> &mut Self {
+    self.push_u8s(&i16.to_be_bytes());
+    self
+  }
+
+  fn push_i32(&mut self, i32: i32) -> &mut Self {
+    self.push_u8s(&i32.to_be_bytes());
+    self
+  }
+
+  fn push_i64(&mut self, i64: i64) -> &mut Self {
+    self.push_u8s(&i64.to_be_bytes());
+    self
+  }
+
+  fn push_utf8(&mut self, utf8: &str) -> KapiResult<&mut Self> {
+    let bytes = cesu8::to_java_cesu8(utf8);
+    let len = u16::try_from(bytes.len()).map_err(|_| {
+      KapiError::StateError(format!(
+        "Modified UTF-8 string is {} bytes long, but at most {} bytes are allowed",
+        bytes.len(),
+        u16::MAX
+      ))
+    })?;
+
+    Ok(self.push_u16(len).push_u8s(&bytes))
+  }
+
+  fn reserve_slot(&mut self, len: usize) -> PatchHandle {
+    let handle = PatchHandle {
+      offset: self.len(),
+      len,
+    };
+
+    self.resize(handle.end(), 0);
+
+    handle
+  }
+
+  fn patch(&mut self, handle: PatchHandle, bytes: &[u8]) -> KapiResult<()> {
+    if bytes.len() != handle.len {
+      return Err(KapiError::StateError(format!(
+        "Patch of {} bytes does not fit slot of {} bytes at offset {}",
+        bytes.len(),
+        handle.len,
+        handle.offset
+      )));
+    }
+
+    let vec_len = self.len();
+    let slot = self.get_mut(handle.offset..handle.end()).ok_or_else(|| {
+      KapiError::StateError(format!(
+        "Slot at offset {} exceeds byte vector length {vec_len}",
+        handle.offset
+      ))
+    })?;
+
+    slot.copy_from_slice(bytes);
+
+    Ok(())
+  }
+
+  fn patch_len(&mut self, handle: PatchHandle) -> KapiResult<()> {
+    let len = self.len().saturating_sub(handle.end()) as u32;
+
+    self.patch(handle, &len.to_be_bytes())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    byte_vec::{
+      ByteVec,
+      ByteVector,
+    },
+    error::KapiError,
+  };
+
+  #[test]
+  fn test_push_signed() {
+    let mut vec = ByteVec::new();
+
+    vec.push_i16(-2).push_i32(i32::MIN).push_i64(-1);
+
+    #[rustfmt::skip]
+    assert_eq!(
+      vec,
+      [
+        0xFF, 0xFE,
+        0x80, 0x00, 0x00, 0x00,
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_push_utf8() {
+    let mut vec = ByteVec::new();
+
+    vec.push_utf8("a\0").unwrap();
+
+    assert_eq!(vec, [0, 3, b'a', 0xC0, 0x80]);
+    assert!(matches!(
+      vec.push_utf8(&"a".repeat(u16::MAX as usize + 1)),
+      Err(KapiError::StateError(_))
+    ));
+    assert_eq!(vec.len(), 5);
+  }
+
+  #[test]
+  fn test_reserve_and_patch() {
+    let mut vec = ByteVec::new();
+
+    vec.push_u8(1);
+
+    let len = vec.reserve_slot(4);
+
+    vec.push_u16(0xCAFE);
+
+    let tag = vec.reserve_slot(1);
+
+    assert_eq!(vec, [1, 0, 0, 0, 0, 0xCA, 0xFE, 0]);
+    assert!(matches!(
+      vec.patch(tag, &[1, 2]),
+      Err(KapiError::StateError(_))
+    ));
+
+    vec.patch(tag, &[7]).unwrap();
+    vec.patch_len(len).unwrap();
+
+    assert_eq!(vec, [1, 0, 0, 0, 3, 0xCA, 0xFE, 7]);
+
+    vec.truncate(2);
+
+    assert!(matches!(
+      vec.patch(len, &[0; 4]),
+      Err(KapiError::StateError(_))
+    ));
+  }
 }
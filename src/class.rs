@@ -1,12 +1,38 @@
 use std::{
-  cell::RefCell,
+  cell::{
+    Ref,
+    RefCell,
+  },
+  collections::{
+    HashMap,
+    HashSet,
+  },
+  fmt::Debug,
+  hash::{
+    Hash,
+    Hasher,
+  },
   rc::Rc,
 };
 
 use crate::{
   access_flag::{
+    validate_class_flags,
+    validate_field_flags,
+    validate_method_flags,
     ClassAccessFlag,
+    FieldAccessFlag,
     MethodAccessFlag,
+    ModuleAccessFlag,
+    NestedClassAccessFlag,
+  },
+  annotation::{
+    compute_type_annotations_size,
+    put_type_annotations,
+    AnnotationVisitor,
+    TargetInfo,
+    TypeAnnotationWriter,
+    TypePathStep,
   },
   attrs,
   byte_vec::{
@@ -15,15 +41,76 @@ use crate::{
     SizeComputable,
     ToBytes,
   },
+  constant::{
+    ConstantPool,
+    ConstantTag,
+  },
+  error::{
+    KapiError,
+    KapiResult,
+    WarningSink,
+  },
+  extensions::Extensions,
+  field::{
+    FieldVisitor,
+    FieldWriter,
+  },
   method::{
     MethodVisitor,
     MethodWriter,
+    DEFAULT_HUGE_METHOD_THRESHOLD,
+  },
+  module::{
+    ModuleVisitor,
+    ModuleWriter,
   },
-  constant::ConstantPool,
+  record::{
+    RecordComponentVisitor,
+    RecordComponentWriter,
+  },
+  types::parse_method_descriptor,
 };
 
+/// Determines which properties of emitted methods are computed by
+/// [ClassWriter] rather than supplied by the user.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ComputeOption {
+  /// Uses the values supplied through [MethodVisitor::visit_maxs].
+  #[default]
+  Nothing,
+  /// Computes max_stack and max_locals of each method from its emitted code,
+  /// values supplied through [MethodVisitor::visit_maxs] are ignored.
+  Maxs,
+}
+
+/// Picks names for members synthesized on behalf of the user, see
+/// [ClassWriter::synthetic_name].
+pub trait SyntheticNamer: Debug {
+  /// Returns a name derived from `base` for a member with given descriptor,
+  /// `is_taken` tells whether a candidate name collides with an existing
+  /// member of the same kind and descriptor.
+  fn name(&mut self, base: &str, descriptor: &str, is_taken: &dyn Fn(&str) -> bool) -> String;
+}
+
+/// Default [SyntheticNamer], keeps `base` if it is free, otherwise appends
+/// the smallest numeric suffix starting from 1 which makes it unique.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SuffixNamer;
+
+impl SyntheticNamer for SuffixNamer {
+  fn name(&mut self, base: &str, _descriptor: &str, is_taken: &dyn Fn(&str) -> bool) -> String {
+    if !is_taken(base) {
+      return base.to_owned();
+    }
+
+    (1..)
+      .map(|suffix| format!("{base}{suffix}"))
+      .find(|name| !is_taken(name))
+      .unwrap()
+  }
+}
+
 #[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
 pub enum JavaVersion {
   V1_1,
   V1_2,
@@ -53,26 +140,26 @@ impl JavaVersion {
   pub fn version(&self) -> u32 {
     match self {
       Self::V1_1 => 3 << 16 | 45,
-      Self::V1_2 => 0 << 16 | 46,
-      Self::V1_3 => 0 << 16 | 47,
-      Self::V1_4 => 0 << 16 | 48,
-      Self::V1_5 => 0 << 16 | 49,
-      Self::V1_6 => 0 << 16 | 50,
-      Self::V1_7 => 0 << 16 | 51,
-      Self::V1_8 => 0 << 16 | 52,
-      Self::V9 => 0 << 16 | 53,
-      Self::V10 => 0 << 16 | 54,
-      Self::V11 => 0 << 16 | 55,
-      Self::V12 => 0 << 16 | 56,
-      Self::V13 => 0 << 16 | 57,
-      Self::V14 => 0 << 16 | 58,
-      Self::V15 => 0 << 16 | 59,
-      Self::V16 => 0 << 16 | 60,
-      Self::V17 => 0 << 16 | 61,
-      Self::V18 => 0 << 16 | 62,
-      Self::V19 => 0 << 16 | 63,
-      Self::V20 => 0 << 16 | 64,
-      Self::V21 => 0 << 16 | 65,
+      Self::V1_2 => 46,
+      Self::V1_3 => 47,
+      Self::V1_4 => 48,
+      Self::V1_5 => 49,
+      Self::V1_6 => 50,
+      Self::V1_7 => 51,
+      Self::V1_8 => 52,
+      Self::V9 => 53,
+      Self::V10 => 54,
+      Self::V11 => 55,
+      Self::V12 => 56,
+      Self::V13 => 57,
+      Self::V14 => 58,
+      Self::V15 => 59,
+      Self::V16 => 60,
+      Self::V17 => 61,
+      Self::V18 => 62,
+      Self::V19 => 63,
+      Self::V20 => 64,
+      Self::V21 => 65,
       JavaVersion::Custom { minor, major } => {
         let minor = minor.to_be_bytes();
         let major = major.to_be_bytes();
@@ -81,11 +168,90 @@ impl JavaVersion {
       }
     }
   }
+
+  pub(crate) fn major_version(&self) -> u16 {
+    self.version() as u16
+  }
+
+  /// Whether class files of this version may use given feature, including
+  /// features available as preview feature (minor version 65535).
+  pub fn supports(&self, feature: Feature) -> bool {
+    let version = self.version();
+    let (minor, major) = ((version >> 16) as u16, version as u16);
+
+    major >= feature.major_version()
+      || feature
+        .preview_major_version()
+        .is_some_and(|preview_major| major >= preview_major && minor == 0xFFFF)
+  }
+}
+
+// Versions are compared by their class file version, so that a custom version
+// equals the named version it represents
+impl PartialEq for JavaVersion {
+  fn eq(&self, other: &Self) -> bool {
+    self.version() == other.version()
+  }
+}
+
+impl Eq for JavaVersion {}
+
+impl Hash for JavaVersion {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.version().hash(state);
+  }
+}
+
+/// Class file features requiring a minimum class file version, see
+/// [JavaVersion::supports].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+  /// MethodHandle and MethodType constants, and BootstrapMethods attribute.
+  MethodHandles,
+  /// Module attribute and Module flag.
+  Module,
+  /// NestHost and NestMembers attributes.
+  NestMates,
+  /// Dynamic constants.
+  ConstantDynamic,
+  /// Record attribute, available as preview feature in Java 14 and 15.
+  Record,
+  /// PermittedSubclasses attribute, available as preview feature in Java 15
+  /// and 16.
+  PermittedSubclasses,
 }
 
-impl Default for JavaVersion {
-  fn default() -> Self {
-    Self::V17
+impl Feature {
+  /// Gets the major version since which the feature is final.
+  pub const fn major_version(&self) -> u16 {
+    match self {
+      Self::MethodHandles => 51,
+      Self::Module => 53,
+      Self::NestMates | Self::ConstantDynamic => 55,
+      Self::Record => 60,
+      Self::PermittedSubclasses => 61,
+    }
+  }
+
+  /// Gets the major version since which the feature is available as preview
+  /// feature, if it ever was one.
+  pub const fn preview_major_version(&self) -> Option<u16> {
+    match self {
+      Self::Record => Some(58),
+      Self::PermittedSubclasses => Some(59),
+      _ => None,
+    }
+  }
+
+  pub(crate) const fn description(&self) -> &'static str {
+    match self {
+      Self::MethodHandles => "MethodHandle and MethodType constants",
+      Self::Module => "Module attribute",
+      Self::NestMates => "NestHost and NestMembers attributes",
+      Self::ConstantDynamic => "Dynamic constants",
+      Self::Record => "Record attribute",
+      Self::PermittedSubclasses => "PermittedSubclasses attribute",
+    }
   }
 }
 
@@ -102,9 +268,25 @@ pub trait ClassVisitor {
     signature: Option<&str>,
     super_name: &str,
     interfaces: &[&str],
-  ) {
+  ) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit(version, access, name, signature, super_name, interfaces)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn visit_field(
+    &mut self,
+    access: FieldAccessFlag,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+  ) -> KapiResult<Option<&mut dyn FieldVisitor>> {
     if let Some(inner) = self.inner() {
-      inner.visit(version, access, name, signature, super_name, interfaces);
+      inner.visit_field(access, name, descriptor, signature)
+    } else {
+      Ok(None)
     }
   }
 
@@ -115,9 +297,41 @@ pub trait ClassVisitor {
     descriptor: &str,
     signature: Option<&str>,
     exceptions: &[&str],
-  ) -> Option<&mut dyn MethodVisitor> {
+  ) -> KapiResult<Option<&mut dyn MethodVisitor>> {
     if let Some(inner) = self.inner() {
       inner.visit_method(access, name, descriptor, signature, exceptions)
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn visit_signature(&mut self, signature: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_signature(signature);
+    }
+  }
+
+  fn visit_module(
+    &mut self,
+    name: &str,
+    access: ModuleAccessFlag,
+    version: Option<&str>,
+  ) -> Option<&mut dyn ModuleVisitor> {
+    if let Some(inner) = self.inner() {
+      inner.visit_module(name, access, version)
+    } else {
+      None
+    }
+  }
+
+  fn visit_record_component(
+    &mut self,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+  ) -> Option<&mut dyn RecordComponentVisitor> {
+    if let Some(inner) = self.inner() {
+      inner.visit_record_component(name, descriptor, signature)
     } else {
       None
     }
@@ -129,6 +343,12 @@ pub trait ClassVisitor {
     }
   }
 
+  fn visit_synthetic(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_synthetic();
+    }
+  }
+
   fn visit_source(&mut self, source_file: &str) {
     if let Some(inner) = self.inner() {
       inner.visit_source(source_file);
@@ -159,20 +379,68 @@ pub trait ClassVisitor {
     }
   }
 
+  fn visit_inner_class(
+    &mut self,
+    name: &str,
+    outer_name: Option<&str>,
+    inner_name: Option<&str>,
+    access: NestedClassAccessFlag,
+  ) {
+    if let Some(inner) = self.inner() {
+      inner.visit_inner_class(name, outer_name, inner_name, access);
+    }
+  }
+
+  fn visit_permitted_subclass(&mut self, permitted_subclass: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_permitted_subclass(permitted_subclass);
+    }
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if let Some(inner) = self.inner() {
+      inner.visit_type_annotation(target_info, type_path, descriptor, visible)
+    } else {
+      Ok(None)
+    }
+  }
+
   fn visit_end(&mut self) {}
 }
 
+/// Writes a class file. Method writers share the class writer's constant
+/// pool, hence [ClassWriter] is neither [Clone] nor [Send].
 #[derive(Debug, Default)]
 pub struct ClassWriter {
-  version: JavaVersion,
+  version: Option<JavaVersion>,
   access: ClassAccessFlag,
   constant_pool: Rc<RefCell<ConstantPool>>,
+  name: Option<String>,
   this_class: Option<u16>,
   signature: Option<u16>,
   super_class: Option<u16>,
   interfaces: Vec<u16>,
-  // fields: Vec<_>,
+  fields: Vec<FieldWriter>,
+  // Visited (name, descriptor) pairs of fields
+  field_keys: HashSet<(String, String)>,
   methods: Vec<MethodWriter>,
+  // Visited (name, descriptor) pairs of methods
+  method_keys: HashSet<(String, String)>,
+  allow_duplicates: bool,
+  compute: ComputeOption,
+  collect_inner_classes: bool,
+  force_emit_empty: bool,
+  verify_constant_pool: bool,
+  warnings: Option<WarningSink>,
+  huge_method_threshold: Option<u32>,
+  synthetic_namer: Option<Box<dyn SyntheticNamer>>,
+  extensions: Extensions,
   // Attribute SourceFile
   source: Option<u16>,
   // Attribute SourceDebugExtension
@@ -181,11 +449,27 @@ pub struct ClassWriter {
   nest_host: Option<u16>,
   // Ka-Pi Specified
   deprecated: bool,
+  // Attribute Synthetic
+  synthetic: bool,
+  // Attribute RuntimeVisibleTypeAnnotations
+  visible_type_annotations: Vec<TypeAnnotationWriter>,
+  // Attribute RuntimeInvisibleTypeAnnotations
+  invisible_type_annotations: Vec<TypeAnnotationWriter>,
   // Attribute EnclosingMethod
   enclosing_class: Option<u16>,
   enclosing_method: Option<u16>,
   // Attribute NestMember
   nest_members: Option<ByteVec>,
+  // Attribute PermittedSubclasses
+  permitted_subclasses: Option<ByteVec>,
+  // Attribute InnerClasses
+  inner_classes: Option<ByteVec>,
+  // Visited inner_class_info_index of inner classes
+  inner_class_keys: HashSet<u16>,
+  // Attribute Module, ModulePackages and ModuleMainClass
+  module: Option<ModuleWriter>,
+  // Attribute Record
+  record_components: Vec<RecordComponentWriter>,
 }
 
 impl ClassWriter {
@@ -193,15 +477,233 @@ impl ClassWriter {
     Self::default()
   }
 
-  pub fn to_bytes(&self) -> Vec<u8> {
+  /// Accepts members sharing the same name and descriptor instead of
+  /// rejecting them, the produced class will fail to load but this is useful
+  /// for producing malformed test inputs.
+  pub fn allow_duplicates(mut self) -> Self {
+    self.allow_duplicates = true;
+    self
+  }
+
+  /// Sets which properties of emitted methods should be computed
+  /// automatically, see [ComputeOption].
+  pub fn compute(mut self, compute: ComputeOption) -> Self {
+    self.compute = compute;
+    self
+  }
+
+  /// Adds an InnerClasses entry on [ClassVisitor::visit_end] for every
+  /// referenced class whose name contains `$` and was not visited through
+  /// [ClassVisitor::visit_inner_class]. Names are split at the last `$`, and
+  /// synthesized entries have no access flags since they are unknown here.
+  pub fn collect_inner_classes(mut self) -> Self {
+    self.collect_inner_classes = true;
+    self
+  }
+
+  /// Emits Exceptions, InnerClasses, LineNumberTable and LocalVariableTable
+  /// attributes even when they have no entries, which javac sometimes does,
+  /// for reproducing class files byte by byte. By default attributes without
+  /// entries are omitted. Empty InnerClasses is added on
+  /// [ClassVisitor::visit_end].
+  pub fn force_emit_empty(mut self) -> Self {
+    self.force_emit_empty = true;
+    self
+  }
+
+  /// Verifies cross-references between constants before writing the class
+  /// file, see [ConstantPoolError](crate::error::ConstantPoolError).
+  pub fn verify_constant_pool(mut self) -> Self {
+    self.verify_constant_pool = true;
+    self
+  }
+
+  /// Collects [GenerationWarning](crate::error::GenerationWarning)s into
+  /// given sink while writing the class file.
+  pub fn warning_sink(mut self, warnings: WarningSink) -> Self {
+    self.warnings = Some(warnings);
+    self
+  }
+
+  /// Sets the code length in bytes above which methods are reported as
+  /// [GenerationWarning::HugeMethod](crate::error::GenerationWarning::HugeMethod)
+  /// to warning sink, defaults to [DEFAULT_HUGE_METHOD_THRESHOLD].
+  pub fn huge_method_threshold(mut self, threshold: u32) -> Self {
+    self.huge_method_threshold = Some(threshold);
+    self
+  }
+
+  /// Sets the [SyntheticNamer] consulted by [ClassWriter::synthetic_name],
+  /// defaults to [SuffixNamer].
+  pub fn synthetic_namer(mut self, namer: impl SyntheticNamer + 'static) -> Self {
+    self.synthetic_namer = Some(Box::new(namer));
+    self
+  }
+
+  /// Checks whether a field (for field descriptors) or a method (for method
+  /// descriptors) with given name and descriptor has already been visited.
+  pub fn is_member_name_taken(&self, name: &str, descriptor: &str) -> bool {
+    let key = (name.to_owned(), descriptor.to_owned());
+
+    if descriptor.starts_with('(') {
+      self.method_keys.contains(&key)
+    } else {
+      self.field_keys.contains(&key)
+    }
+  }
+
+  /// Picks a name derived from `base` for a synthetic member with given
+  /// descriptor through configured [SyntheticNamer]. Returns
+  /// [KapiError::DuplicateMember] if the namer picks a name which collides with a
+  /// visited member.
+  pub fn synthetic_name(&mut self, base: &str, descriptor: &str) -> KapiResult<String> {
+    let mut namer = self
+      .synthetic_namer
+      .take()
+      .unwrap_or_else(|| Box::new(SuffixNamer));
+    let name = namer.name(base, descriptor, &|name| {
+      self.is_member_name_taken(name, descriptor)
+    });
+
+    self.synthetic_namer = Some(namer);
+
+    if self.is_member_name_taken(&name, descriptor) {
+      return Err(KapiError::DuplicateMember {
+        name,
+        descriptor: descriptor.to_owned(),
+      });
+    }
+
+    Ok(name)
+  }
+
+  /// Gets user data attached to this writer, see [Extensions].
+  pub fn extensions(&self) -> &Extensions {
+    &self.extensions
+  }
+
+  /// Gets user data attached to this writer for modification, see
+  /// [Extensions].
+  pub fn extensions_mut(&mut self) -> &mut Extensions {
+    &mut self.extensions
+  }
+
+  /// Iterates over visited methods in visiting order.
+  pub fn methods(&self) -> impl Iterator<Item = &MethodWriter> {
+    self.methods.iter()
+  }
+
+  /// Iterates over visited methods in visiting order for modification, e.g.
+  /// attaching user data through [MethodWriter::extensions_mut].
+  pub fn methods_mut(&mut self) -> impl Iterator<Item = &mut MethodWriter> {
+    self.methods.iter_mut()
+  }
+
+  /// Gets a read-only view of the constant pool shared by this writer and
+  /// its member writers. The view must be dropped before visiting anything
+  /// else, since visits put constants into the same pool.
+  pub fn constant_pool(&self) -> Ref<'_, ConstantPool> {
+    self.constant_pool.borrow()
+  }
+
+  /// Gets the internal name of the class, [None] if [ClassVisitor::visit]
+  /// has not been called yet.
+  pub fn class_name(&self) -> Option<&str> {
+    self.name.as_deref()
+  }
+
+  /// Gets the constant pool index of this_class, [None] if
+  /// [ClassVisitor::visit] has not been called yet. Constant pool never
+  /// reorders entries, so the index stays the same in the emitted class file.
+  pub fn this_class_index(&self) -> Option<u16> {
+    self.this_class
+  }
+
+  /// Gets the constant pool index of super_class, [None] if
+  /// [ClassVisitor::visit] has not been called yet.
+  pub fn super_class_index(&self) -> Option<u16> {
+    self.super_class
+  }
+
+  /// Gets the internal names of the superclass followed by direct
+  /// superinterfaces, empty if [ClassVisitor::visit] has not been called yet
+  /// or the class is a module.
+  pub fn supertype_names(&self) -> Vec<String> {
+    let cp = self.constant_pool.borrow();
+
+    self
+      .super_class
+      .iter()
+      .chain(&self.interfaces)
+      .filter_map(|index| cp.resolve_class_name(*index))
+      .map(str::to_owned)
+      .collect()
+  }
+
+  pub fn to_bytes(&self) -> KapiResult<Vec<u8>> {
     let size = self.compute_size();
     // We avoid additional reallocation by precomputing the
     // class file size based on spec
     let mut vec = ByteVec::with_capacity(size);
 
-    self.put_bytes(&mut vec);
+    self.put_bytes(&mut vec)?;
 
-    vec
+    Ok(vec)
+  }
+
+  fn class_version(&self) -> KapiResult<JavaVersion> {
+    self.version.ok_or_else(|| {
+      KapiError::StateError(String::from(
+        "Class version is unset, probably missing `visit` call?",
+      ))
+    })
+  }
+
+  /// Checks every visited feature is supported by the class file version.
+  fn check_features(&self) -> KapiResult<()> {
+    let class_version = self.class_version()?;
+    let cp = self.constant_pool.borrow();
+    let features = [
+      (
+        Feature::MethodHandles,
+        cp.contains_tag(ConstantTag::MethodHandle)
+          || cp.contains_tag(ConstantTag::MethodType)
+          || cp.contains_tag(ConstantTag::InvokeDynamic),
+      ),
+      (
+        Feature::Module,
+        self.module.is_some()
+          || cp.contains_tag(ConstantTag::Module)
+          || cp.contains_tag(ConstantTag::Package),
+      ),
+      (
+        Feature::NestMates,
+        self.nest_host.is_some() || self.nest_members.is_some(),
+      ),
+      (
+        Feature::ConstantDynamic,
+        cp.contains_tag(ConstantTag::Dynamic),
+      ),
+      (Feature::Record, !self.record_components.is_empty()),
+      (
+        Feature::PermittedSubclasses,
+        self.permitted_subclasses.is_some(),
+      ),
+    ];
+
+    for (feature, used) in features {
+      if used && !class_version.supports(feature) {
+        let version = class_version.version();
+
+        return Err(KapiError::UnsupportedFeature {
+          feature,
+          major_version: version as u16,
+          minor_version: (version >> 16) as u16,
+        });
+      }
+    }
+
+    Ok(())
   }
 }
 
@@ -214,23 +716,76 @@ impl ClassVisitor for ClassWriter {
     signature: Option<&str>,
     super_name: &str,
     interfaces: &[&str],
-  ) {
+  ) -> KapiResult<()> {
+    validate_class_flags(access, version)?;
+
+    if interfaces.len() > u16::MAX as usize {
+      return Err(KapiError::ArgError(format!(
+        "Class {name} cannot have more than 65535 direct superinterfaces, got {}",
+        interfaces.len()
+      )));
+    }
+
     let mut cp = self.constant_pool.borrow_mut();
+    let this_class = cp.put_class(name)?;
+    // Module descriptors have no superclass
+    let super_class = if access.contains(ClassAccessFlag::Module) {
+      0
+    } else {
+      cp.put_class(super_name)?
+    };
+    let interfaces = interfaces
+      .iter()
+      .map(|interface| cp.put_class(interface))
+      .collect::<KapiResult<Vec<_>>>()?;
 
-    self.version = version;
+    drop(cp);
+
+    self.version = Some(version);
     self.access = access;
-    self.this_class = Some(cp.put_class(name));
+    self.name = Some(name.to_owned());
+    self.this_class = Some(this_class);
+    self.super_class = Some(super_class);
+    self.interfaces = interfaces;
 
     if let Some(signature) = signature {
-      cp.put_utf8(attrs::SIGNATURE);
-      self.signature = Some(cp.put_class(signature));
+      self.visit_signature(signature);
     }
 
-    self.super_class = Some(cp.put_class(super_name));
-    self.interfaces = interfaces
-      .into_iter()
-      .map(|interface| cp.put_class(interface))
-      .collect()
+    Ok(())
+  }
+
+  fn visit_field(
+    &mut self,
+    access: FieldAccessFlag,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+  ) -> KapiResult<Option<&mut dyn FieldVisitor>> {
+    validate_field_flags(access, self.access)?;
+
+    if !self
+      .field_keys
+      .insert((name.to_owned(), descriptor.to_owned()))
+      && !self.allow_duplicates
+    {
+      return Err(KapiError::DuplicateMember {
+        name: name.to_owned(),
+        descriptor: descriptor.to_owned(),
+      });
+    }
+
+    let fw = FieldWriter::new(
+      self.constant_pool.clone(),
+      access,
+      name,
+      descriptor,
+      signature,
+    )?;
+
+    self.fields.push(fw);
+
+    Ok(self.fields.last_mut().map(|fw| fw as &mut dyn FieldVisitor))
   }
 
   fn visit_method(
@@ -240,115 +795,386 @@ impl ClassVisitor for ClassWriter {
     descriptor: &str,
     signature: Option<&str>,
     exceptions: &[&str],
-  ) -> Option<&mut dyn MethodVisitor> {
-    let mw = MethodWriter::new(
+  ) -> KapiResult<Option<&mut dyn MethodVisitor>> {
+    validate_method_flags(access, name, self.access, self.class_version()?)?;
+    parse_method_descriptor(descriptor)?;
+
+    if !self
+      .method_keys
+      .insert((name.to_owned(), descriptor.to_owned()))
+      && !self.allow_duplicates
+    {
+      return Err(KapiError::DuplicateMember {
+        name: name.to_owned(),
+        descriptor: descriptor.to_owned(),
+      });
+    }
+
+    let mut mw = MethodWriter::new(
       self.constant_pool.clone(),
+      self.compute,
+      self.warnings.clone(),
       access,
       name,
       descriptor,
       signature,
       exceptions,
+    )?
+    .huge_method_threshold(
+      self
+        .huge_method_threshold
+        .unwrap_or(DEFAULT_HUGE_METHOD_THRESHOLD),
     );
 
+    if self.force_emit_empty {
+      mw = mw.force_emit_empty();
+    }
+
     self.methods.push(mw);
+
+    Ok(
+      self
+        .methods
+        .last_mut()
+        .map(|mw| mw as &mut dyn MethodVisitor),
+    )
+  }
+
+  fn visit_signature(&mut self, signature: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::SIGNATURE);
+    self.signature = cp.put_utf8(signature).ok();
+  }
+
+  fn visit_module(
+    &mut self,
+    name: &str,
+    access: ModuleAccessFlag,
+    version: Option<&str>,
+  ) -> Option<&mut dyn ModuleVisitor> {
+    let module = ModuleWriter::new(self.constant_pool.clone(), name, access, version).ok()?;
+
+    Some(self.module.insert(module))
+  }
+
+  fn visit_record_component(
+    &mut self,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+  ) -> Option<&mut dyn RecordComponentVisitor> {
     self
-      .methods
+      .constant_pool
+      .borrow_mut()
+      .put_utf8(attrs::RECORD)
+      .ok()?;
+    self.record_components.push(
+      RecordComponentWriter::new(self.constant_pool.clone(), name, descriptor, signature).ok()?,
+    );
+
+    self
+      .record_components
       .last_mut()
-      .map(|mw| mw as &mut dyn MethodVisitor)
+      .map(|rcw| rcw as &mut dyn RecordComponentVisitor)
   }
 
   fn visit_deprecated(&mut self) {
     let mut cp = self.constant_pool.borrow_mut();
 
-    cp.put_utf8(attrs::DEPRECATED);
+    let _ = cp.put_utf8(attrs::DEPRECATED);
     self.deprecated = true;
   }
 
+  fn visit_synthetic(&mut self) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::SYNTHETIC);
+    self.synthetic = true;
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if !target_info.is_class_target() {
+      return Err(KapiError::ArgError(format!(
+        "Type annotation target {target_info:?} is not applicable to class"
+      )));
+    }
+
+    let type_annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.visible_type_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.invisible_type_annotations
+    };
+
+    type_annotations.push(TypeAnnotationWriter::new(
+      self.constant_pool.clone(),
+      target_info,
+      type_path,
+      descriptor,
+    )?);
+
+    Ok(
+      type_annotations
+        .last_mut()
+        .map(|taw| taw as &mut dyn AnnotationVisitor),
+    )
+  }
+
   fn visit_source(&mut self, source_file: &str) {
     let mut cp = self.constant_pool.borrow_mut();
 
-    cp.put_utf8(attrs::SOURCE_FILE);
-    self.source = Some(cp.put_utf8(source_file));
+    let _ = cp.put_utf8(attrs::SOURCE_FILE);
+    self.source = cp.put_utf8(source_file).ok();
   }
 
   fn visit_debug_extension(&mut self, debug_extension: &str) {
     let mut cp = self.constant_pool.borrow_mut();
 
-    cp.put_utf8(attrs::SOURCE_DEBUG_EXTENSION);
+    let _ = cp.put_utf8(attrs::SOURCE_DEBUG_EXTENSION);
     self.debug_extension = Some(cesu8::to_java_cesu8(debug_extension).to_vec());
   }
 
   fn visit_nest_host(&mut self, nest_host: &str) {
     let mut cp = self.constant_pool.borrow_mut();
 
-    cp.put_utf8(attrs::NEST_HOST);
-    self.nest_host = Some(cp.put_class(nest_host));
+    let _ = cp.put_utf8(attrs::NEST_HOST);
+    self.nest_host = cp.put_class(nest_host).ok();
   }
 
   fn visit_outer_class(&mut self, class: &str, name: Option<&str>, descriptor: Option<&str>) {
     let mut cp = self.constant_pool.borrow_mut();
 
-    cp.put_utf8(attrs::ENCLOSING_METHOD);
-    self.enclosing_class = Some(cp.put_class(class));
+    let _ = cp.put_utf8(attrs::ENCLOSING_METHOD);
+    self.enclosing_class = cp.put_class(class).ok();
 
-    match (name, descriptor) {
-      (Some(name), Some(descriptor)) => {
-        self.enclosing_method = Some(cp.put_name_and_type(name, descriptor));
-      }
-      _ => {}
+    if let (Some(name), Some(descriptor)) = (name, descriptor) {
+      self.enclosing_method = cp.put_name_and_type(name, descriptor).ok();
     }
   }
 
   fn visit_nest_member(&mut self, nest_member: &str) {
     let mut cp = self.constant_pool.borrow_mut();
+    let Ok(nest_member) = cp.put_class(nest_member) else {
+      return;
+    };
 
     if let Some(nest_members) = &mut self.nest_members {
-      nest_members.push_u16(cp.put_class(nest_member));
+      nest_members.push_u16(nest_member);
     } else {
-      cp.put_utf8(attrs::NEST_MEMBERS);
+      let _ = cp.put_utf8(attrs::NEST_MEMBERS);
 
       let mut nest_members = ByteVec::with_capacity(2);
 
-      nest_members.push_u16(cp.put_class(nest_member));
+      nest_members.push_u16(nest_member);
 
       self.nest_members = Some(nest_members);
     }
   }
-}
 
-impl ToBytes for ClassWriter {
-  fn put_bytes(&self, vec: &mut ByteVec) {
-    let cp = self.constant_pool.borrow();
+  fn visit_inner_class(
+    &mut self,
+    name: &str,
+    outer_name: Option<&str>,
+    inner_name: Option<&str>,
+    access: NestedClassAccessFlag,
+  ) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let Ok(inner_class_info_index) = cp.put_class(name) else {
+      return;
+    };
 
-    vec.push_u32(0xCAFEBABE).push_u32(self.version.version());
+    // Each class is listed at most once, see JVMS 4.7.6
+    if !self.inner_class_keys.insert(inner_class_info_index) {
+      return;
+    }
 
-    cp.put_bytes(vec);
+    let (Ok(outer_class_info_index), Ok(inner_name_index)) = (
+      outer_name.map_or(Ok(0), |outer_name| cp.put_class(outer_name)),
+      inner_name.map_or(Ok(0), |inner_name| cp.put_utf8(inner_name)),
+    ) else {
+      return;
+    };
+    let inner_classes = self.inner_classes.get_or_insert_with(|| {
+      let _ = cp.put_utf8(attrs::INNER_CLASSES);
+      ByteVec::with_capacity(8)
+    });
+
+    inner_classes
+      .push_u16(inner_class_info_index)
+      .push_u16(outer_class_info_index)
+      .push_u16(inner_name_index)
+      .push_u16(access.bits());
+  }
 
-    vec
-      .push_u16(self.access.bits())
-      .push_u16(
-        self
-          .this_class
-          .expect("This class is unset, probably missing `visit` call?"),
-      )
-      .push_u16(
-        self
-          .super_class
-          .expect("Super class is unset, probably missing `visit` call?"),
-      )
-      .push_u16(self.interfaces.len() as u16);
+  fn visit_permitted_subclass(&mut self, permitted_subclass: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let Ok(permitted_subclass) = cp.put_class(permitted_subclass) else {
+      return;
+    };
 
-    for interface in &self.interfaces {
-      vec.push_u16(*interface);
+    if let Some(permitted_subclasses) = &mut self.permitted_subclasses {
+      permitted_subclasses.push_u16(permitted_subclass);
+    } else {
+      let _ = cp.put_utf8(attrs::PERMITTED_SUBCLASSES);
+
+      let mut permitted_subclasses = ByteVec::with_capacity(2);
+
+      permitted_subclasses.push_u16(permitted_subclass);
+
+      self.permitted_subclasses = Some(permitted_subclasses);
     }
+  }
 
-    // TODO: Field
-    vec.push_u16(0);
-    // TODO: Method
-    vec.push_u16(self.methods.len() as u16);
+  fn visit_end(&mut self) {
+    if self.force_emit_empty && self.inner_classes.is_none() {
+      let _ = self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::INNER_CLASSES);
+      self.inner_classes = Some(ByteVec::new());
+    }
 
-    for mw in &self.methods {
-      mw.put_bytes(vec);
+    if !self.collect_inner_classes {
+      return;
+    }
+
+    // Registering an entry may introduce its outer class, which can be
+    // nested as well, so repeats until no new class shows up
+    loop {
+      let class_names = self.constant_pool.borrow().class_names();
+      let mut collected = false;
+
+      for class_name in &class_names {
+        if class_name.starts_with('[') {
+          continue;
+        }
+
+        let Some((outer_name, simple_name)) = class_name
+          .rsplit_once('$')
+          .filter(|(_, simple_name)| !simple_name.is_empty())
+        else {
+          continue;
+        };
+
+        let Ok(class_index) = self.constant_pool.borrow_mut().put_class(class_name) else {
+          continue;
+        };
+
+        if self.inner_class_keys.contains(&class_index) {
+          continue;
+        }
+
+        // Anonymous and local classes are prefixed with a number and have no
+        // outer class
+        let inner_name = simple_name.trim_start_matches(|c: char| c.is_ascii_digit());
+        let outer_name = (inner_name.len() == simple_name.len()).then_some(outer_name);
+        let inner_name = (!inner_name.is_empty()).then_some(inner_name);
+
+        self.visit_inner_class(
+          class_name,
+          outer_name,
+          inner_name,
+          NestedClassAccessFlag::empty(),
+        );
+        collected = true;
+      }
+
+      if !collected {
+        break;
+      }
+    }
+  }
+}
+
+impl ToBytes for ClassWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    self.check_features()?;
+
+    if let Some(this_class) = self.this_class {
+      if self.super_class == Some(this_class) || self.interfaces.contains(&this_class) {
+        return Err(KapiError::StateError(format!(
+          "Class {} cannot be its own superclass or superinterface",
+          self.name.as_deref().unwrap_or_default()
+        )));
+      }
+    }
+
+    let mut visited_interfaces = HashSet::with_capacity(self.interfaces.len());
+
+    for interface in &self.interfaces {
+      if !visited_interfaces.insert(interface) {
+        return Err(KapiError::StateError(format!(
+          "Interface {} is listed more than once",
+          self
+            .constant_pool
+            .borrow()
+            .resolve_class_name(*interface)
+            .unwrap_or_default()
+        )));
+      }
+    }
+
+    // A class is either a nest host or a nest member, see JVMS 4.7.29
+    if self.nest_host.is_some() && self.nest_members.is_some() {
+      return Err(KapiError::StateError(String::from(
+        "NestHost and NestMembers attributes cannot be both present",
+      )));
+    }
+
+    let cp = self.constant_pool.borrow();
+
+    if self.verify_constant_pool {
+      cp.verify().map_err(KapiError::InvalidConstantPool)?;
+    }
+
+    vec
+      .push_u32(0xCAFEBABE)
+      .push_u32(self.class_version()?.version());
+
+    cp.put_bytes(vec)?;
+
+    vec
+      .push_u16(self.access.bits())
+      .push_u16(
+        self
+          .this_class
+          .expect("This class is unset, probably missing `visit` call?"),
+      )
+      .push_u16(
+        self
+          .super_class
+          .expect("Super class is unset, probably missing `visit` call?"),
+      )
+      .push_u16(self.interfaces.len() as u16);
+
+    for interface in &self.interfaces {
+      vec.push_u16(*interface);
+    }
+
+    vec.push_u16(self.fields.len() as u16);
+
+    for fw in &self.fields {
+      fw.put_bytes(vec)?;
+    }
+
+    vec.push_u16(self.methods.len() as u16);
+
+    for mw in &self.methods {
+      mw.put_bytes(vec)?;
     }
 
     // TODO: Attribute
@@ -367,6 +1193,12 @@ impl ToBytes for ClassWriter {
         .push_u32(0);
     }
 
+    if self.synthetic {
+      vec
+        .push_u16(cp.get_utf8(attrs::SYNTHETIC).unwrap())
+        .push_u32(0);
+    }
+
     if let Some(source) = self.source {
       vec
         .push_u16(cp.get_utf8(attrs::SOURCE_FILE).unwrap())
@@ -378,7 +1210,7 @@ impl ToBytes for ClassWriter {
       vec
         .push_u16(cp.get_utf8(attrs::SOURCE_DEBUG_EXTENSION).unwrap())
         .push_u32(debug_extension.len() as u32)
-        .push_u8s(&debug_extension);
+        .push_u8s(debug_extension);
     }
 
     if let Some(nest_host) = self.nest_host {
@@ -403,15 +1235,83 @@ impl ToBytes for ClassWriter {
         .push_u16((nest_members.len() / 2) as u16)
         .extend(nest_members);
     }
+
+    if let Some(inner_classes) = &self.inner_classes {
+      vec
+        .push_u16(cp.get_utf8(attrs::INNER_CLASSES).unwrap())
+        .push_u32((inner_classes.len() + 2) as u32)
+        .push_u16((inner_classes.len() / 8) as u16)
+        .extend(inner_classes);
+    }
+
+    if let Some(permitted_subclasses) = &self.permitted_subclasses {
+      vec
+        .push_u16(cp.get_utf8(attrs::PERMITTED_SUBCLASSES).unwrap())
+        .push_u32((permitted_subclasses.len() + 2) as u32)
+        .push_u16((permitted_subclasses.len() / 2) as u16)
+        .extend(permitted_subclasses);
+    }
+
+    if let Some(module) = &self.module {
+      module.put_bytes(vec)?;
+    }
+
+    if !self.record_components.is_empty() {
+      vec
+        .push_u16(cp.get_utf8(attrs::RECORD).unwrap())
+        .push_u32(
+          (2 + self
+            .record_components
+            .iter()
+            .map(RecordComponentWriter::compute_size)
+            .sum::<usize>()) as u32,
+        )
+        .push_u16(self.record_components.len() as u16);
+
+      for rcw in &self.record_components {
+        rcw.put_bytes(vec)?;
+      }
+    }
+
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS,
+      &self.visible_type_annotations,
+      &Ok,
+    )?;
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS,
+      &self.invisible_type_annotations,
+      &Ok,
+    )?;
+
+    if cp.has_bootstrap_methods() {
+      cp.put_bootstrap_methods(vec);
+    }
+
+    Ok(())
   }
 }
 
 impl SizeComputable for ClassWriter {
   fn compute_size(&self) -> usize {
-    let mut size = 24 + 2 * self.interfaces.len();
-    // TODO: Fields
-    // TODO: Methods
-    // TODO: Attributes
+    // Constant pool's size already includes constant_pool_count
+    let mut size = 22 + self.constant_pool.borrow().compute_size() + 2 * self.interfaces.len();
+
+    size += self
+      .fields
+      .iter()
+      .map(FieldWriter::compute_size)
+      .sum::<usize>();
+    size += self
+      .methods
+      .iter()
+      .map(MethodWriter::compute_size)
+      .sum::<usize>();
+
     if self.signature.is_some() {
       size += 8;
     }
@@ -420,6 +1320,10 @@ impl SizeComputable for ClassWriter {
       size += 6;
     }
 
+    if self.synthetic {
+      size += 6;
+    }
+
     if self.source.is_some() {
       size += 8;
     }
@@ -440,6 +1344,36 @@ impl SizeComputable for ClassWriter {
       size += 8 + nest_members.len();
     }
 
+    if let Some(inner_classes) = &self.inner_classes {
+      size += 8 + inner_classes.len();
+    }
+
+    if let Some(permitted_subclasses) = &self.permitted_subclasses {
+      size += 8 + permitted_subclasses.len();
+    }
+
+    if let Some(module) = &self.module {
+      size += module.compute_size();
+    }
+
+    if !self.record_components.is_empty() {
+      size += 8
+        + self
+          .record_components
+          .iter()
+          .map(RecordComponentWriter::compute_size)
+          .sum::<usize>();
+    }
+
+    size += compute_type_annotations_size(&self.visible_type_annotations);
+    size += compute_type_annotations_size(&self.invisible_type_annotations);
+
+    let cp = self.constant_pool.borrow();
+
+    if cp.has_bootstrap_methods() {
+      size += cp.compute_bootstrap_methods_size();
+    }
+
     size
   }
 
@@ -454,6 +1388,10 @@ impl SizeComputable for ClassWriter {
       count += 1;
     }
 
+    if self.synthetic {
+      count += 1;
+    }
+
     if self.source.is_some() {
       count += 1;
     }
@@ -474,6 +1412,1422 @@ impl SizeComputable for ClassWriter {
       count += 1;
     }
 
+    if self.inner_classes.is_some() {
+      count += 1;
+    }
+
+    if self.permitted_subclasses.is_some() {
+      count += 1;
+    }
+
+    if let Some(module) = &self.module {
+      count += module.attributes_count();
+    }
+
+    if !self.record_components.is_empty() {
+      count += 1;
+    }
+
+    if !self.visible_type_annotations.is_empty() {
+      count += 1;
+    }
+
+    if !self.invisible_type_annotations.is_empty() {
+      count += 1;
+    }
+
+    if self.constant_pool.borrow().has_bootstrap_methods() {
+      count += 1;
+    }
+
     count
   }
 }
+
+/// Checks superclass and superinterface edges among given classes never
+/// form a cycle, which would hang any hierarchy resolver walking them.
+/// Supertypes outside given classes are treated as leaves. The first cycle
+/// found is reported with its full path, starting and ending with the same
+/// class.
+pub fn check_hierarchy<'a>(classes: impl IntoIterator<Item = &'a ClassWriter>) -> KapiResult<()> {
+  let mut order = Vec::new();
+  let mut supertypes = HashMap::new();
+
+  for class in classes {
+    if let Some(name) = class.class_name() {
+      order.push(name.to_owned());
+      supertypes.insert(name.to_owned(), class.supertype_names());
+    }
+  }
+
+  // Classes whose supertypes are all visited and known to be acyclic
+  let mut finished = HashSet::new();
+
+  for root in &order {
+    if finished.contains(root.as_str()) {
+      continue;
+    }
+
+    // Current path from root, with index of the next supertype to visit
+    let mut path: Vec<(&str, usize)> = vec![(root, 0)];
+
+    while let Some((name, next)) = path.last_mut() {
+      let Some(supertype) = supertypes[*name].get(*next) else {
+        finished.insert(*name);
+        path.pop();
+        continue;
+      };
+
+      *next += 1;
+
+      if finished.contains(supertype.as_str()) || !supertypes.contains_key(supertype) {
+        continue;
+      }
+
+      if let Some(start) = path.iter().position(|(name, _)| name == supertype) {
+        let mut cycle = path[start..]
+          .iter()
+          .map(|(name, _)| (*name).to_owned())
+          .collect::<Vec<_>>();
+
+        cycle.push(supertype.clone());
+
+        return Err(KapiError::CyclicHierarchy(cycle));
+      }
+
+      path.push((supertype, 0));
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    cell::RefCell,
+    rc::Rc,
+  };
+
+  use crate::{
+    access_flag::{
+      ClassAccessFlag,
+      FieldAccessFlag,
+      MethodAccessFlag,
+      ModuleAccessFlag,
+      NestedClassAccessFlag,
+      ParameterAccessFlag,
+      RequiresAccessFlag,
+    },
+    annotation::{
+      TargetInfo,
+      TypePathStep,
+    },
+    byte_vec::{
+      SizeComputable,
+      ToBytes,
+    },
+    class::{
+      check_hierarchy,
+      ClassVisitor,
+      ClassWriter,
+      ComputeOption,
+      Feature,
+      JavaVersion,
+      SyntheticNamer,
+    },
+    error::{
+      ConstantPoolError,
+      GenerationWarning,
+      KapiError,
+    },
+    field::ConstantValue,
+    handle::{
+      Handle,
+      RefKind,
+    },
+    label::Label,
+    method::{
+      ConstantDynamic,
+      LdcConstant,
+    },
+    opcodes,
+  };
+
+  fn class_writer(writer: ClassWriter) -> ClassWriter {
+    let mut writer = writer;
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+
+    writer
+  }
+
+  /// Slices emitted class file from access_flags, which begins right after
+  /// constant pool.
+  fn class_header<'a>(writer: &ClassWriter, bytes: &'a [u8]) -> &'a [u8] {
+    let mut cp_bytes = Vec::new();
+
+    writer
+      .constant_pool
+      .borrow()
+      .put_bytes(&mut cp_bytes)
+      .unwrap();
+
+    &bytes[8 + cp_bytes.len()..]
+  }
+
+  #[test]
+  fn test_duplicate_method_rejected() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    assert!(writer
+      .visit_method(
+        MethodAccessFlag::Public | MethodAccessFlag::Static,
+        "main",
+        "([Ljava/lang/String;)V",
+        None,
+        &[],
+      )
+      .is_ok());
+    assert_eq!(
+      writer
+        .visit_method(
+          MethodAccessFlag::Public,
+          "main",
+          "([Ljava/lang/String;)V",
+          None,
+          &[],
+        )
+        .err(),
+      Some(KapiError::DuplicateMember {
+        name: String::from("main"),
+        descriptor: String::from("([Ljava/lang/String;)V"),
+      })
+    );
+  }
+
+  #[test]
+  fn test_malformed_method_descriptor() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    for descriptor in ["(", "(I", "()", "(V)V"] {
+      assert!(matches!(
+        writer.visit_method(MethodAccessFlag::Public, "run", descriptor, None, &[]),
+        Err(KapiError::InvalidDescriptor { .. })
+      ));
+    }
+
+    assert!(writer.methods.is_empty());
+    assert!(writer.method_keys.is_empty());
+  }
+
+  #[test]
+  fn test_duplicate_method_allowed() {
+    let mut writer = class_writer(ClassWriter::new().allow_duplicates());
+
+    for _ in 0..2 {
+      assert!(writer
+        .visit_method(MethodAccessFlag::Public, "run", "()V", None, &[])
+        .is_ok());
+    }
+
+    assert_eq!(writer.methods.len(), 2);
+  }
+
+  #[test]
+  fn test_overloaded_methods() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    for descriptor in ["()V", "(I)V", "(Ljava/lang/String;)V"] {
+      assert!(writer
+        .visit_method(MethodAccessFlag::Public, "<init>", descriptor, None, &[])
+        .is_ok());
+    }
+
+    assert_eq!(writer.methods.len(), 3);
+  }
+
+  #[test]
+  fn test_synthetic_name_suffix() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    assert!(!writer.is_member_name_taken("$VALUES", "[LMain;"));
+    assert_eq!(
+      writer.synthetic_name("$VALUES", "[LMain;"),
+      Ok("$VALUES".to_string())
+    );
+
+    for name in ["$VALUES", "$VALUES1"] {
+      assert!(writer
+        .visit_field(FieldAccessFlag::Private, name, "[LMain;", None)
+        .is_ok());
+    }
+
+    assert!(writer.is_member_name_taken("$VALUES", "[LMain;"));
+    assert!(!writer.is_member_name_taken("$VALUES", "()[LMain;"));
+    assert_eq!(
+      writer.synthetic_name("$VALUES", "[LMain;"),
+      Ok("$VALUES2".to_string())
+    );
+    // Methods are only checked against methods
+    assert_eq!(
+      writer.synthetic_name("$VALUES", "()[LMain;"),
+      Ok("$VALUES".to_string())
+    );
+  }
+
+  #[test]
+  fn test_custom_synthetic_namer() {
+    #[derive(Debug)]
+    struct CountingNamer(u32);
+
+    impl SyntheticNamer for CountingNamer {
+      fn name(&mut self, base: &str, _: &str, is_taken: &dyn Fn(&str) -> bool) -> String {
+        loop {
+          let name = format!("{base}${}", self.0);
+
+          self.0 += 1;
+
+          if !is_taken(&name) {
+            return name;
+          }
+        }
+      }
+    }
+
+    #[derive(Debug)]
+    struct ConstantNamer;
+
+    impl SyntheticNamer for ConstantNamer {
+      fn name(&mut self, base: &str, _: &str, _: &dyn Fn(&str) -> bool) -> String {
+        base.to_owned()
+      }
+    }
+
+    let mut writer = class_writer(ClassWriter::new().synthetic_namer(CountingNamer(0)));
+
+    assert!(writer
+      .visit_method(MethodAccessFlag::Private, "lambda$1", "()V", None, &[])
+      .is_ok());
+    assert_eq!(
+      writer.synthetic_name("lambda", "()V"),
+      Ok("lambda$0".to_string())
+    );
+    assert_eq!(
+      writer.synthetic_name("lambda", "()V"),
+      Ok("lambda$2".to_string())
+    );
+
+    let mut writer = class_writer(ClassWriter::new().synthetic_namer(ConstantNamer));
+
+    assert!(writer
+      .visit_field(FieldAccessFlag::Private, "$kapiProbes", "[Z", None)
+      .is_ok());
+    assert!(matches!(
+      writer.synthetic_name("$kapiProbes", "[Z"),
+      Err(KapiError::DuplicateMember { name, .. }) if name == "$kapiProbes"
+    ));
+  }
+
+  #[test]
+  fn test_extensions_not_emitted() {
+    #[derive(Debug, PartialEq)]
+    struct SourceNode(u32);
+
+    let mut plain = class_writer(ClassWriter::new());
+    let mut extended = class_writer(ClassWriter::new());
+
+    extended.extensions_mut().insert(SourceNode(7));
+
+    for writer in [&mut plain, &mut extended] {
+      writer.visit_source("Main.java");
+      writer.visit_end();
+    }
+
+    assert_eq!(extended.extensions().get(), Some(&SourceNode(7)));
+    assert_eq!(plain.to_bytes(), extended.to_bytes());
+  }
+
+  #[test]
+  fn test_method_extensions_not_emitted() {
+    #[derive(Debug, PartialEq)]
+    struct SourceNode(u32);
+
+    let mut plain = class_writer(ClassWriter::new());
+    let mut extended = class_writer(ClassWriter::new());
+
+    for writer in [&mut plain, &mut extended] {
+      for name in ["a", "b"] {
+        writer
+          .visit_method(MethodAccessFlag::Abstract, name, "()V", None, &[])
+          .unwrap();
+      }
+
+      writer.visit_end();
+    }
+
+    extended
+      .methods_mut()
+      .nth(1)
+      .unwrap()
+      .extensions_mut()
+      .insert(SourceNode(7));
+
+    assert_eq!(
+      extended
+        .methods()
+        .map(|method| method.extensions().get::<SourceNode>())
+        .collect::<Vec<_>>(),
+      [None, Some(&SourceNode(7))]
+    );
+    assert!(plain.to_bytes().is_ok());
+    assert_eq!(plain.to_bytes(), extended.to_bytes());
+  }
+
+  #[test]
+  fn test_class_indices() {
+    let mut writer = ClassWriter::new();
+
+    assert_eq!(writer.class_name(), None);
+    assert_eq!(writer.this_class_index(), None);
+    assert_eq!(writer.super_class_index(), None);
+
+    writer = class_writer(writer);
+
+    let this_class = writer.this_class_index().unwrap();
+    let super_class = writer.super_class_index().unwrap();
+
+    writer.visit_source("Main.java");
+    writer.visit_nest_member("Main$Inner");
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+
+    assert_eq!(writer.class_name(), Some("Main"));
+    assert_eq!(u16::from_be_bytes([header[2], header[3]]), this_class);
+    assert_eq!(u16::from_be_bytes([header[4], header[5]]), super_class);
+  }
+
+  #[test]
+  fn test_huge_method_warning() {
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let mut writer = class_writer(
+      ClassWriter::new()
+        .warning_sink(warnings.clone())
+        .huge_method_threshold(100),
+    );
+
+    for (name, len) in [("small", 99), ("huge", 100)] {
+      let mw = writer
+        .visit_method(MethodAccessFlag::Static, name, "()V", None, &[])
+        .unwrap()
+        .unwrap();
+
+      mw.visit_code();
+
+      for _ in 0..len {
+        mw.visit_simple_inst(opcodes::NOP).unwrap();
+      }
+
+      mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+      assert_eq!(mw.current_code_len(), len + 1);
+    }
+
+    writer.visit_end();
+    writer.to_bytes().unwrap();
+
+    assert_eq!(
+      *warnings.borrow(),
+      vec![GenerationWarning::HugeMethod {
+        name: String::from("huge"),
+        descriptor: String::from("()V"),
+        code_len: 101,
+      }]
+    );
+  }
+
+  #[test]
+  fn test_self_inheritance_rejected() {
+    for (super_name, interfaces) in [("Main", &[][..]), ("java/lang/Object", &["Main"][..])] {
+      let mut writer = ClassWriter::new();
+
+      writer
+        .visit(
+          JavaVersion::V17,
+          ClassAccessFlag::Super,
+          "Main",
+          None,
+          super_name,
+          interfaces,
+        )
+        .unwrap();
+      writer.visit_end();
+
+      assert_eq!(
+        writer.to_bytes(),
+        Err(KapiError::StateError(String::from(
+          "Class Main cannot be its own superclass or superinterface"
+        )))
+      );
+    }
+  }
+
+  #[test]
+  fn test_cyclic_hierarchy() {
+    let class = |name: &str, super_name: &str, interfaces: &[&str]| {
+      let mut writer = ClassWriter::new();
+
+      writer
+        .visit(
+          JavaVersion::V17,
+          ClassAccessFlag::Public | ClassAccessFlag::Super,
+          name,
+          None,
+          super_name,
+          interfaces,
+        )
+        .unwrap();
+
+      writer
+    };
+    let a = class("A", "B", &[]);
+    let b = class("B", "A", &["java/io/Serializable"]);
+    let c = class("C", "java/lang/Object", &["D"]);
+    let d = class("D", "java/lang/Object", &["E"]);
+    let e = class("E", "java/lang/Object", &["C"]);
+    let f = class("F", "A", &[]);
+    let g = class("G", "java/lang/Object", &["G"]);
+
+    assert_eq!(b.supertype_names(), ["A", "java/io/Serializable"]);
+    assert_eq!(ClassWriter::new().supertype_names(), Vec::<String>::new());
+    assert_eq!(
+      check_hierarchy([&a, &b]),
+      Err(KapiError::CyclicHierarchy(vec![
+        String::from("A"),
+        String::from("B"),
+        String::from("A"),
+      ]))
+    );
+    assert_eq!(
+      check_hierarchy([&d, &c, &e]).unwrap_err().to_string(),
+      "Cyclic class hierarchy: D -> E -> C -> D"
+    );
+    // Cycle reached from a class outside of it only reports the cycle
+    assert_eq!(
+      check_hierarchy([&f, &a, &b]),
+      Err(KapiError::CyclicHierarchy(vec![
+        String::from("A"),
+        String::from("B"),
+        String::from("A"),
+      ]))
+    );
+    assert_eq!(
+      check_hierarchy([&g]),
+      Err(KapiError::CyclicHierarchy(vec![
+        String::from("G"),
+        String::from("G"),
+      ]))
+    );
+    assert_eq!(check_hierarchy([&a, &c, &d, &f]), Ok(()));
+    assert_eq!(check_hierarchy([]), Ok(()));
+  }
+
+  #[test]
+  fn test_verify_constant_pool() {
+    let mut writer = class_writer(ClassWriter::new().verify_constant_pool());
+    let mw = writer
+      .visit_method(MethodAccessFlag::Static, "run", "()V", None, &[])
+      .unwrap()
+      .unwrap();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    writer
+      .constant_pool
+      .borrow_mut()
+      .put_method_ref("Main", "run", "()")
+      .unwrap();
+    writer.visit_end();
+
+    assert!(matches!(
+      writer.to_bytes(),
+      Err(KapiError::InvalidConstantPool(errors)) if errors.iter().any(|error| matches!(
+        error,
+        ConstantPoolError::InvalidDescriptor { descriptor, .. } if descriptor == "()"
+      ))
+    ));
+  }
+
+  #[test]
+  fn test_class_attributes() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    writer.visit_source("Main.java");
+    writer.visit_signature("<T:Ljava/lang/Object;>Ljava/lang/Object;");
+    writer.visit_deprecated();
+    writer.visit_synthetic();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let cp = writer.constant_pool.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [signature_0, signature_1] = utf8("Signature");
+    let [signature_value_0, signature_value_1] = utf8("<T:Ljava/lang/Object;>Ljava/lang/Object;");
+    let [deprecated_0, deprecated_1] = utf8("Deprecated");
+    let [synthetic_0, synthetic_1] = utf8("Synthetic");
+    let [source_0, source_1] = utf8("SourceFile");
+    let [source_value_0, source_value_1] = utf8("Main.java");
+
+    // Skips access_flags, this_class, super_class, interfaces, fields and methods
+    #[rustfmt::skip]
+    assert_eq!(
+      &class_header(&writer, &bytes)[12..],
+      &[
+        0, 4,
+        signature_0, signature_1, 0, 0, 0, 2, signature_value_0, signature_value_1,
+        deprecated_0, deprecated_1, 0, 0, 0, 0,
+        synthetic_0, synthetic_1, 0, 0, 0, 0,
+        source_0, source_1, 0, 0, 0, 2, source_value_0, source_value_1,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_duplicate_field_rejected() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    assert!(writer
+      .visit_field(FieldAccessFlag::Private, "value", "I", None)
+      .is_ok());
+    assert!(writer
+      .visit_field(FieldAccessFlag::Private, "value", "J", None)
+      .is_ok());
+    assert_eq!(
+      writer
+        .visit_field(FieldAccessFlag::Public, "value", "I", None)
+        .err(),
+      Some(KapiError::DuplicateMember {
+        name: String::from("value"),
+        descriptor: String::from("I"),
+      })
+    );
+  }
+
+  #[test]
+  fn test_type_annotation_target() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    assert!(writer
+      .visit_type_annotation(
+        TargetInfo::Supertype { index: 65535 },
+        &[TypePathStep::TypeArgument(0)],
+        "LNonNull;",
+        true,
+      )
+      .is_ok());
+    assert!(matches!(
+      writer.visit_type_annotation(TargetInfo::MethodReturn, &[], "LNonNull;", true),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_module_descriptor() {
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Module,
+        "module-info",
+        None,
+        "",
+        &[],
+      )
+      .unwrap();
+
+    let mw = writer
+      .visit_module("app", ModuleAccessFlag::empty(), None)
+      .unwrap();
+
+    mw.visit_require("java.base", RequiresAccessFlag::Mandated, None);
+    mw.visit_package("app/api");
+    mw.visit_end();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+
+    assert_eq!(writer.super_class_index(), Some(0));
+    assert_eq!(&header[4..6], &[0, 0]);
+    // Module and ModulePackages
+    assert_eq!(&header[12..14], &[0, 2]);
+  }
+
+  #[test]
+  fn test_inner_classes() {
+    let mut writer = class_writer(ClassWriter::new());
+
+    writer.visit_inner_class(
+      "java/util/Map$Entry",
+      Some("java/util/Map"),
+      Some("Entry"),
+      NestedClassAccessFlag::Public
+        | NestedClassAccessFlag::Static
+        | NestedClassAccessFlag::Interface
+        | NestedClassAccessFlag::Abstract,
+    );
+    writer.visit_inner_class(
+      "java/util/Map$Entry",
+      None,
+      None,
+      NestedClassAccessFlag::empty(),
+    );
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+    let mut cp = writer.constant_pool.borrow_mut();
+    let [inner_classes_0, inner_classes_1] = cp.get_utf8("InnerClasses").unwrap().to_be_bytes();
+    let [entry_0, entry_1] = cp.put_class("java/util/Map$Entry").unwrap().to_be_bytes();
+    let [map_0, map_1] = cp.put_class("java/util/Map").unwrap().to_be_bytes();
+    let [entry_name_0, entry_name_1] = cp.put_utf8("Entry").unwrap().to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      &header[12..],
+      &[
+        0, 1,
+        inner_classes_0, inner_classes_1, 0, 0, 0, 10, 0, 1,
+        entry_0, entry_1, map_0, map_1, entry_name_0, entry_name_1, 0x06, 0x09,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_force_emit_empty() {
+    let mut writer = class_writer(ClassWriter::new().force_emit_empty());
+    let mw = writer
+      .visit_method(MethodAccessFlag::Static, "run", "()V", None, &[])
+      .unwrap()
+      .unwrap();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+    let cp = writer.constant_pool.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [run_0, run_1] = utf8("run");
+    let [descriptor_0, descriptor_1] = utf8("()V");
+    let [code_0, code_1] = utf8("Code");
+    let [line_numbers_0, line_numbers_1] = utf8("LineNumberTable");
+    let [local_variables_0, local_variables_1] = utf8("LocalVariableTable");
+    let [exceptions_0, exceptions_1] = utf8("Exceptions");
+    let [inner_classes_0, inner_classes_1] = utf8("InnerClasses");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      &header[10..],
+      &[
+        0, 1,
+        0x00, 0x08, run_0, run_1, descriptor_0, descriptor_1, 0, 2,
+        code_0, code_1, 0, 0, 0, 29, 0, 0, 0, 0, 0, 0, 0, 1, opcodes::RETURN, 0, 0, 0, 2,
+        line_numbers_0, line_numbers_1, 0, 0, 0, 2, 0, 0,
+        local_variables_0, local_variables_1, 0, 0, 0, 2, 0, 0,
+        exceptions_0, exceptions_1, 0, 0, 0, 2, 0, 0,
+        0, 1,
+        inner_classes_0, inner_classes_1, 0, 0, 0, 2, 0, 0,
+      ]
+    );
+    assert_eq!(writer.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_bootstrap_methods() {
+    let mut writer = class_writer(ClassWriter::new().compute(ComputeOption::Maxs));
+    let invoke = Handle::new(
+      RefKind::InvokeStatic,
+      "java/lang/invoke/ConstantBootstraps",
+      "invoke",
+      "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;Ljava/lang/invoke/MethodHandle;[Ljava/lang/Object;)Ljava/lang/Object;",
+      false,
+    )
+    .unwrap();
+    let parse_long = Handle::new(
+      RefKind::InvokeStatic,
+      "java/lang/Long",
+      "parseLong",
+      "(Ljava/lang/String;)J",
+      false,
+    )
+    .unwrap();
+    let dynamic = ConstantDynamic::new(
+      "value",
+      "J",
+      invoke.clone(),
+      vec![
+        LdcConstant::MethodHandle(parse_long.clone()),
+        LdcConstant::String(String::from("7")),
+      ],
+    )
+    .unwrap();
+    let mw = writer
+      .visit_method(MethodAccessFlag::Static, "value", "()J", None, &[])
+      .unwrap()
+      .unwrap();
+
+    mw.visit_code();
+    mw.visit_ldc_inst(LdcConstant::Dynamic(dynamic.clone()))
+      .unwrap();
+    mw.visit_ldc_inst(LdcConstant::Dynamic(dynamic)).unwrap();
+    mw.visit_simple_inst(opcodes::LADD).unwrap();
+    mw.visit_simple_inst(opcodes::LRETURN).unwrap();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let mut cp = writer.constant_pool.borrow_mut();
+    let [bootstrap_methods_0, bootstrap_methods_1] =
+      cp.get_utf8("BootstrapMethods").unwrap().to_be_bytes();
+    let [invoke_0, invoke_1] = cp.put_method_handle(&invoke).unwrap().to_be_bytes();
+    let [parse_long_0, parse_long_1] = cp.put_method_handle(&parse_long).unwrap().to_be_bytes();
+    let [string_0, string_1] = cp.put_string("7").unwrap().to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      &bytes[bytes.len() - 18..],
+      &[
+        0, 1,
+        bootstrap_methods_0, bootstrap_methods_1, 0, 0, 0, 10, 0, 1,
+        invoke_0, invoke_1, 0, 2, parse_long_0, parse_long_1, string_0, string_1,
+      ]
+    );
+
+    drop(cp);
+
+    assert_eq!(writer.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_many_interfaces() {
+    let interfaces = (0..1000).map(|i| format!("Marker{i}")).collect::<Vec<_>>();
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &interfaces.iter().map(String::as_str).collect::<Vec<_>>(),
+      )
+      .unwrap();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+    let mut cp = writer.constant_pool.borrow_mut();
+
+    assert_eq!(header[6..8], 1000u16.to_be_bytes());
+    assert_eq!(
+      header[8..2008],
+      interfaces
+        .iter()
+        .flat_map(|interface| cp.put_class(interface).unwrap().to_be_bytes())
+        .collect::<Vec<_>>()
+    );
+
+    drop(cp);
+
+    assert_eq!(writer.compute_size(), bytes.len());
+
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &["Marker", "java/io/Serializable", "Marker"],
+      )
+      .unwrap();
+
+    assert_eq!(
+      writer.to_bytes(),
+      Err(KapiError::StateError(String::from(
+        "Interface Marker is listed more than once"
+      )))
+    );
+  }
+
+  #[test]
+  fn test_too_many_interfaces() {
+    let interfaces = (0..40000)
+      .map(|i| format!("Interface{i}"))
+      .collect::<Vec<_>>();
+    let interfaces = interfaces.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut writer = ClassWriter::new();
+
+    assert_eq!(
+      writer.visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &interfaces,
+      ),
+      Err(KapiError::ConstantPoolOverflow)
+    );
+    assert_eq!(writer.class_name(), None);
+
+    writer.visit_source("Main.java");
+
+    assert!(matches!(writer.to_bytes(), Err(KapiError::StateError(_))));
+    assert!(matches!(
+      ClassWriter::new().visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &["Marker"; 65536],
+      ),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_invalid_access_flags() {
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Interface | ClassAccessFlag::Abstract,
+        "Runnable",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+
+    assert!(matches!(
+      writer.visit_field(FieldAccessFlag::Public, "COUNT", "I", None),
+      Err(KapiError::ArgError(_))
+    ));
+    assert_eq!(
+      writer
+        .visit_method(
+          MethodAccessFlag::Private | MethodAccessFlag::Abstract,
+          "run",
+          "()V",
+          None,
+          &[],
+        )
+        .err(),
+      Some(KapiError::ArgError(String::from(
+        "Method access flags Private cannot be set along with Abstract"
+      )))
+    );
+
+    let mut writer = ClassWriter::new();
+
+    assert_eq!(
+      writer.visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Final | ClassAccessFlag::Abstract,
+        "Main",
+        None,
+        "java/lang/Object",
+        &[],
+      ),
+      Err(KapiError::ArgError(String::from(
+        "Class access flags Final | Abstract are mutually exclusive"
+      )))
+    );
+    assert_eq!(writer.class_name(), None);
+  }
+
+  #[test]
+  fn test_unknown_access_flags_retained() {
+    let mut access = ClassAccessFlag::from_bits_retain(0x0821);
+    let mut writer = ClassWriter::new();
+
+    access.remove(ClassAccessFlag::Public);
+    access.insert(ClassAccessFlag::Final);
+
+    assert!(access.contains(ClassAccessFlag::Super));
+    assert_eq!(access.bits(), 0x0830);
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        access,
+        "Main",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+    writer
+      .visit_field(
+        FieldAccessFlag::Private | FieldAccessFlag::from_bits_retain(0x0100),
+        "value",
+        "I",
+        None,
+      )
+      .unwrap();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+
+    assert_eq!(header[0..2], [0x08, 0x30]);
+    assert_eq!(header[10..12], [0x01, 0x02]);
+  }
+
+  #[test]
+  fn test_collect_inner_classes() {
+    let mut writer = ClassWriter::new().collect_inner_classes();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &["Main$Inner$Deep", "Main$1", "Main$2Local"],
+      )
+      .unwrap();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+    let mut cp = writer.constant_pool.borrow_mut();
+    let [deep_0, deep_1] = cp.put_class("Main$Inner$Deep").unwrap().to_be_bytes();
+    let [inner_0, inner_1] = cp.put_class("Main$Inner").unwrap().to_be_bytes();
+    let [anonymous_0, anonymous_1] = cp.put_class("Main$1").unwrap().to_be_bytes();
+    let [local_0, local_1] = cp.put_class("Main$2Local").unwrap().to_be_bytes();
+    let [main_0, main_1] = cp.put_class("Main").unwrap().to_be_bytes();
+    let [deep_name_0, deep_name_1] = cp.put_utf8("Deep").unwrap().to_be_bytes();
+    let [inner_name_0, inner_name_1] = cp.put_utf8("Inner").unwrap().to_be_bytes();
+    let [local_name_0, local_name_1] = cp.put_utf8("Local").unwrap().to_be_bytes();
+
+    // Skips access_flags, this_class, super_class, interfaces, fields,
+    // methods, attributes_count and InnerClasses attribute header
+    #[rustfmt::skip]
+    assert_eq!(
+      &header[26..],
+      &[
+        0, 4,
+        deep_0, deep_1, inner_0, inner_1, deep_name_0, deep_name_1, 0, 0,
+        anonymous_0, anonymous_1, 0, 0, 0, 0, 0, 0,
+        local_0, local_1, 0, 0, local_name_0, local_name_1, 0, 0,
+        inner_0, inner_1, main_0, main_1, inner_name_0, inner_name_1, 0, 0,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_permitted_subclasses() {
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Interface | ClassAccessFlag::Abstract,
+        "Shape",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+    writer.visit_permitted_subclass("Circle");
+    writer.visit_permitted_subclass("Square");
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let header = class_header(&writer, &bytes);
+    let mut cp = writer.constant_pool.borrow_mut();
+    let [permitted_0, permitted_1] = cp.get_utf8("PermittedSubclasses").unwrap().to_be_bytes();
+    let [circle_0, circle_1] = cp.put_class("Circle").unwrap().to_be_bytes();
+    let [square_0, square_1] = cp.put_class("Square").unwrap().to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      &header[12..],
+      &[
+        0, 1,
+        permitted_0, permitted_1, 0, 0, 0, 6, 0, 2,
+        circle_0, circle_1, square_0, square_1,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_nest_attributes() {
+    let mut host = class_writer(ClassWriter::new());
+
+    host.visit_nest_member("Main$Inner");
+    host.visit_end();
+
+    let mut member = ClassWriter::new();
+
+    member
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Super,
+        "Main$Inner",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+    member.visit_nest_host("Main");
+    member.visit_end();
+
+    let host_bytes = host.to_bytes().unwrap();
+    let member_bytes = member.to_bytes().unwrap();
+    let host_header = class_header(&host, &host_bytes);
+    let member_header = class_header(&member, &member_bytes);
+    let mut host_cp = host.constant_pool.borrow_mut();
+    let [members_0, members_1] = host_cp.get_utf8("NestMembers").unwrap().to_be_bytes();
+    let [inner_0, inner_1] = host_cp.put_class("Main$Inner").unwrap().to_be_bytes();
+    let mut member_cp = member.constant_pool.borrow_mut();
+    let [host_attr_0, host_attr_1] = member_cp.get_utf8("NestHost").unwrap().to_be_bytes();
+    let [main_0, main_1] = member_cp.put_class("Main").unwrap().to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      &host_header[12..],
+      &[0, 1, members_0, members_1, 0, 0, 0, 4, 0, 1, inner_0, inner_1]
+    );
+    #[rustfmt::skip]
+    assert_eq!(
+      &member_header[12..],
+      &[0, 1, host_attr_0, host_attr_1, 0, 0, 0, 2, main_0, main_1]
+    );
+
+    drop(member_cp);
+    member.visit_nest_member("Main$Other");
+
+    assert!(matches!(member.to_bytes(), Err(KapiError::StateError(_))));
+  }
+
+  #[test]
+  fn test_compute_size_matches_output() {
+    let mut writer = class_writer(ClassWriter::new().compute(ComputeOption::Maxs));
+
+    writer.visit_signature("<T:Ljava/lang/Object;>Ljava/lang/Object;");
+    writer.visit_source("Main.java");
+    writer.visit_debug_extension("SMAP");
+    writer.visit_deprecated();
+    writer.visit_synthetic();
+    writer.visit_nest_host("Outer");
+    writer.visit_outer_class("Outer", Some("run"), Some("()V"));
+    writer.visit_inner_class(
+      "Main$Inner",
+      Some("Main"),
+      Some("Inner"),
+      NestedClassAccessFlag::Private,
+    );
+    writer.visit_permitted_subclass("Sub");
+    writer
+      .visit_type_annotation(
+        TargetInfo::ClassTypeParameter { index: 0 },
+        &[],
+        "LA;",
+        true,
+      )
+      .unwrap()
+      .unwrap()
+      .visit_end();
+    writer
+      .visit_record_component("x", "I", None)
+      .unwrap()
+      .visit_annotation("LA;", true)
+      .unwrap()
+      .visit_end();
+    writer
+      .visit_module("app", ModuleAccessFlag::Open, None)
+      .unwrap()
+      .visit_package("app/api");
+
+    let fw = writer
+      .visit_field(FieldAccessFlag::Static, "value", "J", Some("J"))
+      .unwrap()
+      .unwrap();
+
+    fw.visit_constant(ConstantValue::Long(1)).unwrap();
+    fw.visit_annotation("LA;", false).unwrap().visit_end();
+    fw.visit_deprecated();
+    fw.visit_synthetic();
+    fw.visit_end();
+
+    let mw = writer
+      .visit_method(
+        MethodAccessFlag::Static,
+        "run",
+        "(I)I",
+        Some("(I)I"),
+        &["java/lang/Exception"],
+      )
+      .unwrap()
+      .unwrap();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_parameter(Some("value"), ParameterAccessFlag::Final)
+      .unwrap();
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_line_number(1, &start).unwrap();
+    mw.visit_iinc_inst(300, 1);
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::IRETURN).unwrap();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_local_variable("value", "I", Some("I"), &start, &end, 0)
+      .unwrap();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+
+    assert_eq!(writer.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_record_components() {
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V16,
+        ClassAccessFlag::Final | ClassAccessFlag::Super,
+        "Point",
+        None,
+        "java/lang/Record",
+        &[],
+      )
+      .unwrap();
+    writer
+      .visit_record_component("x", "I", None)
+      .unwrap()
+      .visit_end();
+    writer
+      .visit_record_component(
+        "tags",
+        "Ljava/util/List;",
+        Some("Ljava/util/List<Ljava/lang/String;>;"),
+      )
+      .unwrap()
+      .visit_end();
+    writer.visit_end();
+
+    let bytes = writer.to_bytes().unwrap();
+    let cp = writer.constant_pool.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [record_0, record_1] = utf8("Record");
+    let [x_0, x_1] = utf8("x");
+    let [int_0, int_1] = utf8("I");
+    let [tags_0, tags_1] = utf8("tags");
+    let [list_0, list_1] = utf8("Ljava/util/List;");
+    let [signature_0, signature_1] = utf8("Signature");
+    let [signature_value_0, signature_value_1] = utf8("Ljava/util/List<Ljava/lang/String;>;");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      &class_header(&writer, &bytes)[12..],
+      &[
+        0, 1,
+        record_0, record_1, 0, 0, 0, 22, 0, 2,
+        x_0, x_1, int_0, int_1, 0, 0,
+        tags_0, tags_1, list_0, list_1, 0, 1,
+        signature_0, signature_1, 0, 0, 0, 2, signature_value_0, signature_value_1,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_feature_versions() {
+    type Visit = fn(&mut ClassWriter);
+
+    let features: [(Feature, JavaVersion, JavaVersion, Visit); 6] = [
+      (
+        Feature::MethodHandles,
+        JavaVersion::V1_6,
+        JavaVersion::V1_7,
+        |writer| {
+          writer
+            .constant_pool
+            .borrow_mut()
+            .put_method_type("()V")
+            .unwrap();
+        },
+      ),
+      (
+        Feature::Module,
+        JavaVersion::V1_8,
+        JavaVersion::V9,
+        |writer| {
+          writer.visit_module("kapi", ModuleAccessFlag::empty(), None);
+        },
+      ),
+      (
+        Feature::NestMates,
+        JavaVersion::V10,
+        JavaVersion::V11,
+        |writer| writer.visit_nest_host("Outer"),
+      ),
+      (
+        Feature::ConstantDynamic,
+        JavaVersion::V10,
+        JavaVersion::V11,
+        |writer| {
+          let null_constant = Handle::new(
+            RefKind::InvokeStatic,
+            "java/lang/invoke/ConstantBootstraps",
+            "nullConstant",
+            "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;)Ljava/lang/Object;",
+            false,
+          )
+          .unwrap();
+          let dynamic =
+            ConstantDynamic::new("value", "Ljava/lang/Object;", null_constant, vec![]).unwrap();
+
+          writer
+            .constant_pool
+            .borrow_mut()
+            .put_dynamic(&dynamic)
+            .unwrap();
+        },
+      ),
+      (
+        Feature::Record,
+        JavaVersion::V15,
+        JavaVersion::V16,
+        |writer| {
+          writer.visit_record_component("x", "I", None);
+        },
+      ),
+      (
+        Feature::PermittedSubclasses,
+        JavaVersion::V16,
+        JavaVersion::V17,
+        |writer| writer.visit_permitted_subclass("Sub"),
+      ),
+    ];
+
+    for (feature, too_low, minimum, visit) in features {
+      assert!(!too_low.supports(feature), "{feature:?}");
+      assert!(minimum.supports(feature), "{feature:?}");
+
+      for (version, supported) in [(too_low, false), (minimum, true)] {
+        let mut writer = ClassWriter::new();
+
+        writer
+          .visit(
+            version,
+            ClassAccessFlag::Final | ClassAccessFlag::Super,
+            "Main",
+            None,
+            "java/lang/Object",
+            &[],
+          )
+          .unwrap();
+        visit(&mut writer);
+        writer.visit_end();
+
+        assert_eq!(
+          writer.to_bytes().is_ok(),
+          supported,
+          "{feature:?} {version:?}"
+        );
+      }
+    }
+
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V15,
+        ClassAccessFlag::Final | ClassAccessFlag::Super,
+        "Point",
+        None,
+        "java/lang/Record",
+        &[],
+      )
+      .unwrap();
+    writer.visit_record_component("x", "I", None);
+
+    let error = writer.to_bytes().unwrap_err();
+
+    assert_eq!(
+      error,
+      KapiError::UnsupportedFeature {
+        feature: Feature::Record,
+        major_version: 59,
+        minor_version: 0,
+      }
+    );
+    assert_eq!(
+      error.to_string(),
+      "Class file version 59.0 does not support Record attribute, at least 60.0 or 58.65535 is needed"
+    );
+  }
+
+  #[test]
+  fn test_record_version() {
+    for (version, supported) in [
+      (JavaVersion::V15, false),
+      (
+        JavaVersion::Custom {
+          minor: 0xFFFF,
+          major: 59,
+        },
+        true,
+      ),
+      (
+        JavaVersion::Custom {
+          minor: 0,
+          major: 60,
+        },
+        true,
+      ),
+    ] {
+      let mut writer = ClassWriter::new();
+
+      writer
+        .visit(
+          version,
+          ClassAccessFlag::Final | ClassAccessFlag::Super,
+          "Point",
+          None,
+          "java/lang/Record",
+          &[],
+        )
+        .unwrap();
+      writer.visit_record_component("x", "I", None);
+      writer.visit_end();
+
+      assert_eq!(writer.to_bytes().is_ok(), supported);
+    }
+  }
+}
@@ -1,14 +1,38 @@
-use indexmap::IndexMap;
+use indexmap::{
+  IndexMap,
+  IndexSet,
+};
 
-use crate::byte_vec::{
-  ByteVec,
-  ByteVector,
-  ToBytes,
+use crate::{
+  attrs,
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    ToBytes,
+  },
+  error::{
+    ConstantPoolError,
+    KapiError,
+    KapiResult,
+  },
+  handle::{
+    Handle,
+    RefKind,
+  },
+  method::{
+    ConstantDynamic,
+    LdcConstant,
+  },
+  types::{
+    parse_field_descriptor,
+    parse_method_descriptor,
+  },
 };
 
+/// Kind of a constant, discriminants are the tags of constant_pool entries.
 #[repr(u8)]
-#[derive(Debug)]
-pub(crate) enum ConstantTag {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConstantTag {
   Utf8 = 1,
   Integer = 3,
   Float = 4,
@@ -28,7 +52,7 @@ pub(crate) enum ConstantTag {
   Package = 20,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Constant {
   Utf8(String),
   Integer(i32),
@@ -49,8 +73,33 @@ pub enum Constant {
   Package(u16),
 }
 
+impl ConstantTag {
+  /// Gets the name of the kind as spelled in JVMS, e.g. `Fieldref`.
+  pub const fn name(&self) -> &'static str {
+    match self {
+      ConstantTag::Utf8 => "Utf8",
+      ConstantTag::Integer => "Integer",
+      ConstantTag::Float => "Float",
+      ConstantTag::Long => "Long",
+      ConstantTag::Double => "Double",
+      ConstantTag::Class => "Class",
+      ConstantTag::String => "String",
+      ConstantTag::FieldRef => "Fieldref",
+      ConstantTag::MethodRef => "Methodref",
+      ConstantTag::InterfaceMethodRef => "InterfaceMethodref",
+      ConstantTag::NameAndType => "NameAndType",
+      ConstantTag::MethodHandle => "MethodHandle",
+      ConstantTag::MethodType => "MethodType",
+      ConstantTag::Dynamic => "Dynamic",
+      ConstantTag::InvokeDynamic => "InvokeDynamic",
+      ConstantTag::Module => "Module",
+      ConstantTag::Package => "Package",
+    }
+  }
+}
+
 impl Constant {
-  pub(crate) const fn tag(&self) -> ConstantTag {
+  pub const fn tag(&self) -> ConstantTag {
     match self {
       Constant::Utf8(..) => ConstantTag::Utf8,
       Constant::Integer(..) => ConstantTag::Integer,
@@ -72,7 +121,8 @@ impl Constant {
     }
   }
 
-  pub(crate) const fn size(&self) -> u16 {
+  /// Gets the amount of constant pool slots the constant occupies.
+  pub const fn size(&self) -> u16 {
     match self {
       Constant::Long(..) | Constant::Double(..) => 2,
       _ => 1,
@@ -80,25 +130,40 @@ impl Constant {
   }
 }
 
+impl Constant {
+  /// Gets the amount of bytes the constant takes in class file, including
+  /// its tag.
+  pub(crate) fn compute_size(&self) -> usize {
+    match self {
+      Constant::Utf8(string) => 3 + cesu8::to_java_cesu8(string).len(),
+      Constant::Class(_)
+      | Constant::String(_)
+      | Constant::MethodType(_)
+      | Constant::Module(_)
+      | Constant::Package(_) => 3,
+      Constant::MethodHandle(..) => 4,
+      Constant::Long(_) | Constant::Double(_) => 9,
+      _ => 5,
+    }
+  }
+}
+
 impl ToBytes for Constant {
-  fn put_bytes(&self, vec: &mut ByteVec) {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
     vec.push_u8(self.tag() as u8);
 
     match self {
       Constant::Utf8(string) => {
-        let bytes = cesu8::to_java_cesu8(string);
-
-        vec.push_u16(bytes.len() as u16);
-        vec.push_u8s(&bytes);
+        vec.push_utf8(string)?;
       }
       Constant::Integer(val) => {
-        vec.push_u8s(&val.to_be_bytes());
+        vec.push_i32(*val);
       }
       Constant::Float(val) => {
         vec.push_u8s(val);
       }
       Constant::Long(val) => {
-        vec.push_u8s(&val.to_be_bytes());
+        vec.push_i64(*val);
       }
       Constant::Double(val) => {
         vec.push_u8s(val);
@@ -106,77 +171,152 @@ impl ToBytes for Constant {
       Constant::Class(index) => {
         vec.push_u16(*index);
       }
-      Constant::String(index) => {
+      Constant::String(index)
+      | Constant::MethodType(index)
+      | Constant::Module(index)
+      | Constant::Package(index) => {
         vec.push_u16(*index);
       }
-      _ => todo!(),
+      Constant::FieldRef(index_0, index_1)
+      | Constant::MethodRef(index_0, index_1)
+      | Constant::InterfaceMethodRef(index_0, index_1)
+      | Constant::NameAndType(index_0, index_1)
+      | Constant::Dynamic(index_0, index_1)
+      | Constant::InvokeDynamic(index_0, index_1) => {
+        vec.push_u16(*index_0).push_u16(*index_1);
+      }
+      Constant::MethodHandle(reference_kind, reference_index) => {
+        vec.push_u8(*reference_kind).push_u16(*reference_index);
+      }
     }
+
+    Ok(())
   }
 }
 
+/// A Fieldref, Methodref or InterfaceMethodref constant with its
+/// referenced strings resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResolvedMember<'a> {
+  /// Internal name of the class or interface declaring the member.
+  pub owner: &'a str,
+  pub name: &'a str,
+  pub descriptor: &'a str,
+}
+
+/// A Dynamic or InvokeDynamic constant with its NameAndType resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResolvedDynamic<'a> {
+  /// Index of the bootstrap method in attribute BootstrapMethods.
+  pub bootstrap_method_attr_index: u16,
+  pub name: &'a str,
+  pub descriptor: &'a str,
+}
+
+/// Constant pool of a class file. A single pool is shared by the class
+/// writer and all of its member writers through `Rc<RefCell<_>>`, so
+/// constants are interned once where they are first visited. Constants are
+/// only ever appended and never move, hence indices already encoded into
+/// code or attributes stay valid and never need to be patched.
+///
+/// Constants are put through writers only, a read-only view is available
+/// through [ClassWriter::constant_pool](crate::class::ClassWriter::constant_pool).
 #[derive(Debug)]
-pub(crate) struct ConstantPool {
+pub struct ConstantPool {
   pool: IndexMap<Constant, u16>,
   index: u16,
+  // Position in `pool` of the constant at each index, None for index 0 and
+  // slots following Long or Double constants
+  slots: Vec<Option<usize>>,
+  // Whether a constant has been refused for not fitting into the pool
+  overflowed: bool,
+  // Entries of attribute BootstrapMethods, (bootstrap_method_ref, bootstrap_arguments)
+  bootstrap_methods: IndexSet<(u16, Vec<u16>)>,
 }
 
 impl ConstantPool {
-  fn put(&mut self, constant: Constant) -> u16 {
+  /// Puts a constant unless an equal one exists, returns its index. Fails
+  /// once constants no longer fit into 65535 slots, after which every put
+  /// and writing the pool fail as well, so visitors which cannot report
+  /// errors may drop them and still leave the class unwritable.
+  fn put(&mut self, constant: Constant) -> KapiResult<u16> {
     if let Some(index) = self.pool.get(&constant) {
-      *index
-    } else {
-      let index = self.index;
-      self.index += constant.size();
-      self.pool.insert(constant, index);
-      index
+      return Ok(*index);
     }
+
+    let index = self.index;
+
+    self.index = match index.checked_add(constant.size()) {
+      Some(next_index) if !self.overflowed => next_index,
+      _ => {
+        self.overflowed = true;
+        return Err(KapiError::ConstantPoolOverflow);
+      }
+    };
+    self.slots.push(Some(self.pool.len()));
+    if constant.size() == 2 {
+      self.slots.push(None);
+    }
+    self.pool.insert(constant, index);
+
+    Ok(index)
   }
 
-  pub(crate) fn put_utf8<T>(&mut self, utf8: T) -> u16
+  pub(crate) fn put_utf8<T>(&mut self, utf8: T) -> KapiResult<u16>
   where
     T: Into<String>,
   {
     self.put(Constant::Utf8(utf8.into()))
   }
 
-  pub(crate) fn put_integer(&mut self, integer: i32) -> u16 {
+  pub(crate) fn put_integer(&mut self, integer: i32) -> KapiResult<u16> {
     self.put(Constant::Integer(integer))
   }
 
-  pub(crate) fn put_float(&mut self, float: f32) -> u16 {
+  pub(crate) fn put_float(&mut self, float: f32) -> KapiResult<u16> {
     self.put(Constant::Float(float.to_be_bytes()))
   }
 
-  pub(crate) fn put_long(&mut self, long: i64) -> u16 {
+  pub(crate) fn put_long(&mut self, long: i64) -> KapiResult<u16> {
     self.put(Constant::Long(long))
   }
 
-  pub(crate) fn put_double(&mut self, double: f64) -> u16 {
+  pub(crate) fn put_double(&mut self, double: f64) -> KapiResult<u16> {
     self.put(Constant::Double(double.to_be_bytes()))
   }
 
-  pub(crate) fn put_class(&mut self, class_name: &str) -> u16 {
-    let utf8 = self.put_utf8(class_name);
+  pub(crate) fn put_class(&mut self, class_name: &str) -> KapiResult<u16> {
+    let utf8 = self.put_utf8(class_name)?;
 
     self.put(Constant::Class(utf8))
   }
 
-  pub(crate) fn put_string(&mut self, string: &str) -> u16 {
-    let utf8 = self.put_utf8(string);
+  pub(crate) fn put_string(&mut self, string: &str) -> KapiResult<u16> {
+    let utf8 = self.put_utf8(string)?;
 
     self.put(Constant::String(utf8))
   }
 
-  pub(crate) fn put_field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
-    let class = self.put_utf8(class);
-    let name_and_type = self.put_name_and_type(name, descriptor);
+  pub(crate) fn put_field_ref(
+    &mut self,
+    class: &str,
+    name: &str,
+    descriptor: &str,
+  ) -> KapiResult<u16> {
+    let class = self.put_class(class)?;
+    let name_and_type = self.put_name_and_type(name, descriptor)?;
 
     self.put(Constant::FieldRef(class, name_and_type))
   }
 
-  pub(crate) fn put_method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
-    let class = self.put_utf8(class);
-    let name_and_type = self.put_name_and_type(name, descriptor);
+  pub(crate) fn put_method_ref(
+    &mut self,
+    class: &str,
+    name: &str,
+    descriptor: &str,
+  ) -> KapiResult<u16> {
+    let class = self.put_class(class)?;
+    let name_and_type = self.put_name_and_type(name, descriptor)?;
 
     self.put(Constant::MethodRef(class, name_and_type))
   }
@@ -186,28 +326,456 @@ impl ConstantPool {
     class: &str,
     name: &str,
     descriptor: &str,
-  ) -> u16 {
-    let class = self.put_utf8(class);
-    let name_and_type = self.put_name_and_type(name, descriptor);
+  ) -> KapiResult<u16> {
+    let class = self.put_class(class)?;
+    let name_and_type = self.put_name_and_type(name, descriptor)?;
 
     self.put(Constant::InterfaceMethodRef(class, name_and_type))
   }
 
-  pub(crate) fn put_name_and_type(&mut self, name: &str, descriptor: &str) -> u16 {
-    let name = self.put_utf8(name);
-    let descriptor = self.put_utf8(descriptor);
+  pub(crate) fn put_name_and_type(&mut self, name: &str, descriptor: &str) -> KapiResult<u16> {
+    let name = self.put_utf8(name)?;
+    let descriptor = self.put_utf8(descriptor)?;
 
     self.put(Constant::NameAndType(name, descriptor))
   }
 
-  pub(crate) fn get(&self, index: u16) -> Option<&Constant> {
-    self.pool.iter().find(|(_, &idx)| idx == index).map(|(constant, _)| constant)
+  pub(crate) fn put_method_handle(&mut self, handle: &Handle) -> KapiResult<u16> {
+    let reference_index = if handle.kind().is_field_kind() {
+      self.put_field_ref(handle.owner(), handle.name(), handle.descriptor())
+    } else if handle.is_interface() {
+      self.put_interface_method_ref(handle.owner(), handle.name(), handle.descriptor())
+    } else {
+      self.put_method_ref(handle.owner(), handle.name(), handle.descriptor())
+    }?;
+
+    self.put(Constant::MethodHandle(
+      handle.kind().into(),
+      reference_index,
+    ))
+  }
+
+  pub(crate) fn put_method_type(&mut self, descriptor: &str) -> KapiResult<u16> {
+    let utf8 = self.put_utf8(descriptor)?;
+
+    self.put(Constant::MethodType(utf8))
+  }
+
+  /// Puts a constant loadable by `ldc` family instructions or passed as a
+  /// bootstrap argument, see JVMS 4.4.
+  pub(crate) fn put_loadable(&mut self, constant: &LdcConstant) -> KapiResult<u16> {
+    match constant {
+      LdcConstant::Int(int) => self.put_integer(*int),
+      LdcConstant::Float(float) => self.put_float(*float),
+      LdcConstant::Long(long) => self.put_long(*long),
+      LdcConstant::Double(double) => self.put_double(*double),
+      LdcConstant::String(string) => self.put_string(string),
+      LdcConstant::Class(class_name) => self.put_class(class_name),
+      LdcConstant::MethodType(descriptor) => {
+        parse_method_descriptor(descriptor)?;
+        self.put_method_type(descriptor)
+      }
+      LdcConstant::MethodHandle(handle) => self.put_method_handle(handle),
+      LdcConstant::Dynamic(dynamic) => self.put_dynamic(dynamic),
+    }
+  }
+
+  pub(crate) fn put_dynamic(&mut self, dynamic: &ConstantDynamic) -> KapiResult<u16> {
+    let bootstrap_method_attr_index =
+      self.put_bootstrap_method(dynamic.bootstrap_method(), dynamic.bootstrap_arguments())?;
+    let name_and_type = self.put_name_and_type(dynamic.name(), dynamic.descriptor())?;
+
+    self.put(Constant::Dynamic(
+      bootstrap_method_attr_index,
+      name_and_type,
+    ))
+  }
+
+  /// Puts an entry of attribute BootstrapMethods, returns its index in the
+  /// attribute. Identical entries are shared.
+  pub(crate) fn put_bootstrap_method(
+    &mut self,
+    bootstrap_method: &Handle,
+    bootstrap_arguments: &[LdcConstant],
+  ) -> KapiResult<u16> {
+    let bootstrap_method_ref = self.put_method_handle(bootstrap_method)?;
+    let bootstrap_arguments = bootstrap_arguments
+      .iter()
+      .map(|argument| self.put_loadable(argument))
+      .collect::<KapiResult<Vec<_>>>()?;
+
+    self.put_utf8(attrs::BOOTSTRAP_METHODS)?;
+
+    let entry = (bootstrap_method_ref, bootstrap_arguments);
+
+    if let Some(index) = self.bootstrap_methods.get_index_of(&entry) {
+      return Ok(index as u16);
+    }
+
+    if self.bootstrap_methods.len() == u16::MAX as usize {
+      return Err(KapiError::ArgError(String::from(
+        "Attribute BootstrapMethods cannot hold more than 65535 entries",
+      )));
+    }
+
+    let (index, _) = self.bootstrap_methods.insert_full(entry);
+
+    Ok(index as u16)
+  }
+
+  /// Iterates over constants along with their indices in index order. The
+  /// slot following a Long or Double constant holds no constant and is
+  /// skipped.
+  pub fn iter(&self) -> impl Iterator<Item = (u16, &Constant)> {
+    self.pool.iter().map(|(constant, &index)| (index, constant))
+  }
+
+  /// Iterates over indices holding a constant in index order.
+  pub fn indices(&self) -> impl Iterator<Item = u16> + '_ {
+    self.pool.values().copied()
+  }
+
+  /// Iterates over constants of given kind along with their indices.
+  pub fn constants_of(&self, tag: ConstantTag) -> impl Iterator<Item = (u16, &Constant)> {
+    self
+      .iter()
+      .filter(move |(_, constant)| constant.tag() == tag)
+  }
+
+  /// Whether any constant of given kind has been put.
+  pub fn contains_tag(&self, tag: ConstantTag) -> bool {
+    self.constants_of(tag).next().is_some()
   }
 
-  pub(crate) fn get_tag(&self, index: u16) -> Option<ConstantTag> {
+  pub(crate) fn has_bootstrap_methods(&self) -> bool {
+    !self.bootstrap_methods.is_empty()
+  }
+
+  /// Gets the size of attribute BootstrapMethods, including its header.
+  pub(crate) fn compute_bootstrap_methods_size(&self) -> usize {
+    8 + self
+      .bootstrap_methods
+      .iter()
+      .map(|(_, bootstrap_arguments)| 4 + 2 * bootstrap_arguments.len())
+      .sum::<usize>()
+  }
+
+  pub(crate) fn put_bootstrap_methods(&self, vec: &mut ByteVec) {
+    vec
+      .push_u16(self.get_utf8(attrs::BOOTSTRAP_METHODS).unwrap())
+      .push_u32(self.compute_bootstrap_methods_size() as u32 - 6)
+      .push_u16(self.bootstrap_methods.len() as u16);
+
+    for (bootstrap_method_ref, bootstrap_arguments) in &self.bootstrap_methods {
+      vec
+        .push_u16(*bootstrap_method_ref)
+        .push_u16(bootstrap_arguments.len() as u16);
+
+      for bootstrap_argument in bootstrap_arguments {
+        vec.push_u16(*bootstrap_argument);
+      }
+    }
+  }
+
+  pub(crate) fn put_module(&mut self, module_name: &str) -> KapiResult<u16> {
+    let utf8 = self.put_utf8(module_name)?;
+
+    self.put(Constant::Module(utf8))
+  }
+
+  pub(crate) fn put_package(&mut self, package_name: &str) -> KapiResult<u16> {
+    let utf8 = self.put_utf8(package_name)?;
+
+    self.put(Constant::Package(utf8))
+  }
+
+  /// Gets constant_pool_count to emit, which is one greater than the largest
+  /// index and counts the unusable slot 0, see JVMS 4.1.
+  pub fn constant_pool_count(&self) -> u16 {
+    self.index
+  }
+
+  /// Gets the constant at given index, [None] if the index holds no constant.
+  pub fn get(&self, index: u16) -> Option<&Constant> {
+    let position = self.slots.get(index as usize).copied().flatten()?;
+
+    self.pool.get_index(position).map(|(constant, _)| constant)
+  }
+
+  /// Gets the names of all Class constants in insertion order.
+  pub(crate) fn class_names(&self) -> Vec<String> {
+    self
+      .constants_of(ConstantTag::Class)
+      .filter_map(|(_, constant)| match constant {
+        Constant::Class(index) => self.resolve_utf8(*index).map(str::to_owned),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// Resolves a Utf8 constant, [None] if there is no Utf8 constant at
+  /// `index`. The same applies to other `resolve_*` functions.
+  pub fn resolve_utf8(&self, index: u16) -> Option<&str> {
+    match self.get(index)? {
+      Constant::Utf8(utf8) => Some(utf8),
+      _ => None,
+    }
+  }
+
+  /// Resolves a Class constant into its internal name.
+  pub fn resolve_class_name(&self, index: u16) -> Option<&str> {
+    match self.get(index)? {
+      Constant::Class(name_index) => self.resolve_utf8(*name_index),
+      _ => None,
+    }
+  }
+
+  /// Resolves a NameAndType constant into its name and descriptor.
+  pub fn resolve_name_and_type(&self, index: u16) -> Option<(&str, &str)> {
+    match self.get(index)? {
+      Constant::NameAndType(name_index, descriptor_index) => Some((
+        self.resolve_utf8(*name_index)?,
+        self.resolve_utf8(*descriptor_index)?,
+      )),
+      _ => None,
+    }
+  }
+
+  /// Resolves a Fieldref constant.
+  pub fn resolve_field_ref(&self, index: u16) -> Option<ResolvedMember<'_>> {
+    match self.get(index)? {
+      Constant::FieldRef(class_index, name_and_type_index) => {
+        self.resolve_member(*class_index, *name_and_type_index)
+      }
+      _ => None,
+    }
+  }
+
+  /// Resolves a Methodref or InterfaceMethodref constant.
+  pub fn resolve_method_ref(&self, index: u16) -> Option<ResolvedMember<'_>> {
+    match self.get(index)? {
+      Constant::MethodRef(class_index, name_and_type_index)
+      | Constant::InterfaceMethodRef(class_index, name_and_type_index) => {
+        self.resolve_member(*class_index, *name_and_type_index)
+      }
+      _ => None,
+    }
+  }
+
+  /// Resolves a Dynamic or InvokeDynamic constant, the bootstrap method is
+  /// left as an index into BootstrapMethods attribute.
+  pub fn resolve_dynamic(&self, index: u16) -> Option<ResolvedDynamic<'_>> {
+    match self.get(index)? {
+      Constant::Dynamic(bootstrap_method_attr_index, name_and_type_index)
+      | Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index) => {
+        let (name, descriptor) = self.resolve_name_and_type(*name_and_type_index)?;
+
+        Some(ResolvedDynamic {
+          bootstrap_method_attr_index: *bootstrap_method_attr_index,
+          name,
+          descriptor,
+        })
+      }
+      _ => None,
+    }
+  }
+
+  fn resolve_member(
+    &self,
+    class_index: u16,
+    name_and_type_index: u16,
+  ) -> Option<ResolvedMember<'_>> {
+    let (name, descriptor) = self.resolve_name_and_type(name_and_type_index)?;
+
+    Some(ResolvedMember {
+      owner: self.resolve_class_name(class_index)?,
+      name,
+      descriptor,
+    })
+  }
+
+  /// Gets the kind of constant at given index.
+  pub fn get_tag(&self, index: u16) -> Option<ConstantTag> {
     self.get(index).map(Constant::tag)
   }
 
+  /// Checks every index referenced by constants exists and has the expected
+  /// kind, and descriptors referenced by NameAndType and MethodType are well
+  /// formed. Collects all problems instead of stopping at the first one.
+  pub(crate) fn verify(&self) -> Result<(), Vec<ConstantPoolError>> {
+    let mut errors = Vec::new();
+
+    for (index, constant) in self.iter() {
+      let mut expect = |referenced: u16, expected: &[ConstantTag]| {
+        self.verify_reference(index, referenced, expected, &mut errors)
+      };
+
+      match constant {
+        Constant::Class(name_index)
+        | Constant::String(name_index)
+        | Constant::Module(name_index)
+        | Constant::Package(name_index) => {
+          expect(*name_index, &[ConstantTag::Utf8]);
+        }
+        Constant::FieldRef(class_index, name_and_type_index) => {
+          expect(*class_index, &[ConstantTag::Class]);
+
+          if expect(*name_and_type_index, &[ConstantTag::NameAndType]) {
+            self.verify_descriptor(*name_and_type_index, false, &mut errors);
+          }
+        }
+        Constant::MethodRef(class_index, name_and_type_index)
+        | Constant::InterfaceMethodRef(class_index, name_and_type_index) => {
+          expect(*class_index, &[ConstantTag::Class]);
+
+          if expect(*name_and_type_index, &[ConstantTag::NameAndType]) {
+            self.verify_descriptor(*name_and_type_index, true, &mut errors);
+          }
+        }
+        Constant::NameAndType(name_index, descriptor_index) => {
+          expect(*name_index, &[ConstantTag::Utf8]);
+          expect(*descriptor_index, &[ConstantTag::Utf8]);
+        }
+        Constant::MethodHandle(reference_kind, reference_index) => {
+          let expected: &[ConstantTag] = match RefKind::try_from(*reference_kind) {
+            Ok(kind) if kind.is_field_kind() => &[ConstantTag::FieldRef],
+            Ok(kind) => match kind.expects_interface() {
+              Some(true) => &[ConstantTag::InterfaceMethodRef],
+              Some(false) => &[ConstantTag::MethodRef],
+              None => &[ConstantTag::MethodRef, ConstantTag::InterfaceMethodRef],
+            },
+            Err(_) => {
+              errors.push(ConstantPoolError::InvalidReferenceKind {
+                index,
+                kind: *reference_kind,
+              });
+              continue;
+            }
+          };
+
+          expect(*reference_index, expected);
+        }
+        Constant::MethodType(descriptor_index) => {
+          if expect(*descriptor_index, &[ConstantTag::Utf8]) {
+            self.verify_utf8_descriptor(*descriptor_index, true, &mut errors);
+          }
+        }
+        Constant::Dynamic(bootstrap_method_attr_index, name_and_type_index)
+        | Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index) => {
+          if expect(*name_and_type_index, &[ConstantTag::NameAndType]) {
+            self.verify_descriptor(
+              *name_and_type_index,
+              matches!(constant, Constant::InvokeDynamic(..)),
+              &mut errors,
+            );
+          }
+
+          if *bootstrap_method_attr_index as usize >= self.bootstrap_methods.len() {
+            errors.push(ConstantPoolError::MissingBootstrapMethod {
+              index,
+              bootstrap_method_attr_index: *bootstrap_method_attr_index,
+            });
+          }
+        }
+        Constant::Utf8(..)
+        | Constant::Integer(..)
+        | Constant::Float(..)
+        | Constant::Long(..)
+        | Constant::Double(..) => {}
+      }
+    }
+
+    if errors.is_empty() {
+      Ok(())
+    } else {
+      Err(errors)
+    }
+  }
+
+  /// Checks constant at `referenced` is one of `expected` kinds, returns
+  /// whether it is.
+  fn verify_reference(
+    &self,
+    referenced_by: u16,
+    referenced: u16,
+    expected: &[ConstantTag],
+    errors: &mut Vec<ConstantPoolError>,
+  ) -> bool {
+    let Some(tag) = self.get_tag(referenced) else {
+      let is_padding = referenced
+        .checked_sub(1)
+        .and_then(|index| self.get(index))
+        .is_some_and(|constant| constant.size() == 2);
+
+      errors.push(if is_padding {
+        ConstantPoolError::PaddingSlot {
+          index: referenced,
+          referenced_by,
+        }
+      } else {
+        ConstantPoolError::MissingIndex {
+          index: referenced,
+          referenced_by,
+        }
+      });
+
+      return false;
+    };
+
+    if expected.contains(&tag) {
+      return true;
+    }
+
+    errors.push(ConstantPoolError::UnexpectedTag {
+      index: referenced,
+      referenced_by,
+      expected: expected[0].name(),
+      found: tag.name(),
+    });
+
+    false
+  }
+
+  fn verify_descriptor(
+    &self,
+    name_and_type_index: u16,
+    is_method: bool,
+    errors: &mut Vec<ConstantPoolError>,
+  ) {
+    if let Some(Constant::NameAndType(_, descriptor_index)) = self.get(name_and_type_index) {
+      self.verify_utf8_descriptor(*descriptor_index, is_method, errors);
+    }
+  }
+
+  fn verify_utf8_descriptor(
+    &self,
+    descriptor_index: u16,
+    is_method: bool,
+    errors: &mut Vec<ConstantPoolError>,
+  ) {
+    let Some(Constant::Utf8(descriptor)) = self.get(descriptor_index) else {
+      return;
+    };
+    let is_valid = if is_method {
+      parse_method_descriptor(descriptor).is_ok()
+    } else {
+      parse_field_descriptor(descriptor).is_ok()
+    };
+
+    if !is_valid {
+      errors.push(ConstantPoolError::InvalidDescriptor {
+        index: descriptor_index,
+        descriptor: descriptor.clone(),
+      });
+    }
+  }
+
+  /// Gets the amount of bytes constant pool takes in class file, including
+  /// constant_pool_count.
+  pub(crate) fn compute_size(&self) -> usize {
+    2 + self.pool.keys().map(Constant::compute_size).sum::<usize>()
+  }
+
   pub(crate) fn get_utf8<T>(&self, utf8: T) -> Option<u16>
   where
     T: Into<String>,
@@ -223,16 +791,302 @@ impl Default for ConstantPool {
     Self {
       pool: Default::default(),
       index: 1,
+      slots: vec![None],
+      overflowed: false,
+      bootstrap_methods: Default::default(),
     }
   }
 }
 
 impl ToBytes for ConstantPool {
-  fn put_bytes(&self, vec: &mut ByteVec) {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    if self.overflowed {
+      return Err(KapiError::ConstantPoolOverflow);
+    }
+
     vec.push_u16(self.index);
 
     for (constant, _) in &self.pool {
-      constant.put_bytes(vec);
+      constant.put_bytes(vec)?;
     }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    byte_vec::{
+      ByteVec,
+      ToBytes,
+    },
+    constant::{
+      Constant,
+      ConstantPool,
+      ConstantTag,
+      ResolvedDynamic,
+      ResolvedMember,
+    },
+    error::{
+      ConstantPoolError,
+      KapiError,
+    },
+    handle::{
+      Handle,
+      RefKind,
+    },
+    method::{
+      ConstantDynamic,
+      LdcConstant,
+    },
+  };
+
+  #[test]
+  fn test_iter_skips_padding() {
+    let mut cp = ConstantPool::default();
+    let long = cp.put_long(1).unwrap();
+    let utf8 = cp.put_utf8("Main").unwrap();
+    let class = cp.put_class("Main").unwrap();
+    let double = cp.put_double(2.0).unwrap();
+    let integer = cp.put_integer(3).unwrap();
+    let last_long = cp.put_long(4).unwrap();
+
+    assert_eq!(
+      cp.indices().collect::<Vec<_>>(),
+      vec![long, utf8, class, double, integer, last_long]
+    );
+    assert_eq!((long, utf8, double, integer, last_long), (1, 3, 5, 7, 8));
+    assert_eq!(
+      cp.iter().collect::<Vec<_>>(),
+      vec![
+        (1, &Constant::Long(1)),
+        (3, &Constant::Utf8(String::from("Main"))),
+        (4, &Constant::Class(3)),
+        (5, &Constant::Double(2f64.to_be_bytes())),
+        (7, &Constant::Integer(3)),
+        (8, &Constant::Long(4)),
+      ]
+    );
+    assert_eq!(
+      cp.constants_of(ConstantTag::Long)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>(),
+      vec![1, 8]
+    );
+    assert!(cp.contains_tag(ConstantTag::Double));
+    assert!(!cp.contains_tag(ConstantTag::Float));
+    assert_eq!(cp.get(0), None);
+    assert_eq!(cp.get(2), None);
+    assert_eq!(cp.get(6), None);
+    assert_eq!(cp.get(9), None);
+    assert_eq!(cp.get(10), None);
+    assert!(cp
+      .iter()
+      .all(|(index, constant)| cp.get(index) == Some(constant)));
+  }
+
+  #[test]
+  fn test_resolve_references() {
+    let mut cp = ConstantPool::default();
+    let field_ref = cp.put_field_ref("Main", "value", "J").unwrap();
+    let method_ref = cp
+      .put_method_ref("java/lang/Object", "<init>", "()V")
+      .unwrap();
+    let interface_method_ref = cp
+      .put_interface_method_ref("java/util/List", "size", "()I")
+      .unwrap();
+    let name_and_type = cp
+      .put_name_and_type("apply", "()Ljava/util/function/Function;")
+      .unwrap();
+    let invoke_dynamic = cp.put(Constant::InvokeDynamic(3, name_and_type)).unwrap();
+    let class = cp.put_class("Main").unwrap();
+    let class_name = cp.put_utf8("Main").unwrap();
+
+    assert_eq!(
+      cp.resolve_field_ref(field_ref),
+      Some(ResolvedMember {
+        owner: "Main",
+        name: "value",
+        descriptor: "J",
+      })
+    );
+    assert_eq!(
+      cp.resolve_method_ref(method_ref),
+      Some(ResolvedMember {
+        owner: "java/lang/Object",
+        name: "<init>",
+        descriptor: "()V",
+      })
+    );
+    assert_eq!(
+      cp.resolve_method_ref(interface_method_ref),
+      Some(ResolvedMember {
+        owner: "java/util/List",
+        name: "size",
+        descriptor: "()I",
+      })
+    );
+    assert_eq!(
+      cp.resolve_dynamic(invoke_dynamic),
+      Some(ResolvedDynamic {
+        bootstrap_method_attr_index: 3,
+        name: "apply",
+        descriptor: "()Ljava/util/function/Function;",
+      })
+    );
+    assert_eq!(cp.resolve_class_name(class), Some("Main"));
+
+    // Mismatched kinds and dangling indices resolve to nothing
+    assert_eq!(cp.resolve_field_ref(method_ref), None);
+    assert_eq!(cp.resolve_method_ref(field_ref), None);
+    assert_eq!(cp.resolve_class_name(class_name), None);
+    assert_eq!(cp.resolve_name_and_type(100), None);
+  }
+
+  #[test]
+  fn test_verify_constant_pool() {
+    let mut cp = ConstantPool::default();
+
+    cp.put_method_ref("Main", "run", "()V").unwrap();
+    cp.put_field_ref("Main", "value", "J").unwrap();
+    cp.put_string("text").unwrap();
+
+    assert_eq!(cp.verify(), Ok(()));
+
+    let long = cp.put_long(0).unwrap();
+    let name = cp.put_utf8("value").unwrap();
+    let bad_class = cp.put(Constant::Class(long)).unwrap();
+    let padding = cp.put(Constant::String(long + 1)).unwrap();
+    let missing = cp.put(Constant::NameAndType(name, 100)).unwrap();
+    let bad_descriptor = cp.put_utf8("(I").unwrap();
+    let bad_name_and_type = cp.put(Constant::NameAndType(name, bad_descriptor)).unwrap();
+    let bad_field_ref = cp
+      .put(Constant::FieldRef(bad_class, bad_name_and_type))
+      .unwrap();
+    let bad_handle = cp.put(Constant::MethodHandle(10, bad_field_ref)).unwrap();
+
+    assert_eq!(
+      cp.verify(),
+      Err(vec![
+        ConstantPoolError::UnexpectedTag {
+          index: long,
+          referenced_by: bad_class,
+          expected: "Utf8",
+          found: "Long",
+        },
+        ConstantPoolError::PaddingSlot {
+          index: long + 1,
+          referenced_by: padding,
+        },
+        ConstantPoolError::MissingIndex {
+          index: 100,
+          referenced_by: missing,
+        },
+        ConstantPoolError::InvalidDescriptor {
+          index: bad_descriptor,
+          descriptor: String::from("(I"),
+        },
+        ConstantPoolError::InvalidReferenceKind {
+          index: bad_handle,
+          kind: 10,
+        },
+      ])
+    );
+  }
+
+  #[test]
+  fn test_put_dynamic() {
+    let mut cp = ConstantPool::default();
+    let invoke = Handle::new(
+      RefKind::InvokeStatic,
+      "java/lang/invoke/ConstantBootstraps",
+      "invoke",
+      "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;Ljava/lang/invoke/MethodHandle;[Ljava/lang/Object;)Ljava/lang/Object;",
+      false,
+    )
+    .unwrap();
+    let value_of = Handle::new(
+      RefKind::InvokeStatic,
+      "java/lang/Integer",
+      "valueOf",
+      "(I)Ljava/lang/Integer;",
+      false,
+    )
+    .unwrap();
+    let dynamic = |name: &str, value: i32| {
+      ConstantDynamic::new(
+        name,
+        "Ljava/lang/Integer;",
+        invoke.clone(),
+        vec![
+          LdcConstant::MethodHandle(value_of.clone()),
+          LdcConstant::Int(value),
+        ],
+      )
+      .unwrap()
+    };
+
+    let answer = cp.put_dynamic(&dynamic("answer", 42)).unwrap();
+    // Shares the bootstrap method with different name
+    let renamed = cp.put_dynamic(&dynamic("renamed", 42)).unwrap();
+    let other = cp.put_dynamic(&dynamic("other", 7)).unwrap();
+    // Dynamic constants may be bootstrap arguments as well
+    let nested = ConstantDynamic::new(
+      "nested",
+      "Ljava/lang/Object;",
+      invoke.clone(),
+      vec![
+        LdcConstant::MethodHandle(value_of.clone()),
+        LdcConstant::Dynamic(dynamic("answer", 42)),
+      ],
+    )
+    .unwrap();
+    let nested = cp.put_dynamic(&nested).unwrap();
+
+    let bootstrap_method_attr_index = |index| {
+      cp.resolve_dynamic(index)
+        .unwrap()
+        .bootstrap_method_attr_index
+    };
+
+    assert_eq!(bootstrap_method_attr_index(answer), 0);
+    assert_eq!(bootstrap_method_attr_index(renamed), 0);
+    assert_eq!(bootstrap_method_attr_index(other), 1);
+    assert_eq!(bootstrap_method_attr_index(nested), 2);
+    assert_eq!(cp.bootstrap_methods[2].1[1], answer);
+    assert_eq!(cp.verify(), Ok(()));
+
+    let name_and_type = cp.put_name_and_type("missing", "I").unwrap();
+    let missing = cp.put(Constant::Dynamic(3, name_and_type)).unwrap();
+
+    assert_eq!(
+      cp.verify(),
+      Err(vec![ConstantPoolError::MissingBootstrapMethod {
+        index: missing,
+        bootstrap_method_attr_index: 3,
+      }])
+    );
+    assert!(ConstantDynamic::new("void", "V", invoke, vec![]).is_err());
+  }
+
+  #[test]
+  fn test_constant_pool_overflow() {
+    let mut cp = ConstantPool::default();
+    let main = cp.put_utf8("Main").unwrap();
+
+    for integer in 1..u16::MAX - 2 {
+      cp.put_integer(integer as i32).unwrap();
+    }
+
+    assert_eq!(cp.put_long(0), Err(KapiError::ConstantPoolOverflow));
+    // Overflow is sticky, even though an Integer constant still fits
+    assert_eq!(cp.put_integer(0), Err(KapiError::ConstantPoolOverflow));
+    assert_eq!(cp.put_integer(1), Ok(2));
+    assert_eq!(cp.put_utf8("Main"), Ok(main));
+    assert_eq!(
+      cp.put_bytes(&mut ByteVec::new()),
+      Err(KapiError::ConstantPoolOverflow)
+    );
   }
 }
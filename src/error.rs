@@ -0,0 +1,362 @@
+use std::{
+  cell::RefCell,
+  error::Error,
+  fmt::{
+    Display,
+    Formatter,
+  },
+  rc::Rc,
+};
+
+use crate::{
+  class::Feature,
+  types::Type,
+};
+
+pub type KapiResult<T> = Result<T, KapiError>;
+
+/// Errors reported by writers when the requested output cannot be produced.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KapiError {
+  /// An argument passed to a writer is invalid on its own.
+  ArgError(String),
+  /// A writer was asked to do something its current state doesn't allow.
+  StateError(String),
+  /// A field or method descriptor is malformed, see JVMS 4.3.
+  InvalidDescriptor { descriptor: String, reason: String },
+  /// Bytes end at `offset` while `needed` bytes are to be read.
+  UnexpectedEnd {
+    offset: usize,
+    needed: usize,
+    remaining: usize,
+  },
+  /// Instruction at bytecode offset `offset` pops more values than the
+  /// operand stack of size `stack_size` holds.
+  StackUnderflow {
+    offset: u32,
+    opcode: u8,
+    stack_size: i32,
+    delta: i32,
+  },
+  /// Constant pool verification failed, see
+  /// [ClassWriter::verify_constant_pool](crate::class::ClassWriter::verify_constant_pool).
+  InvalidConstantPool(Vec<ConstantPoolError>),
+  /// Constants put into the constant pool need more than 65535 slots, see
+  /// JVMS 4.1.
+  ConstantPoolOverflow,
+  /// Superclass and superinterface edges form a cycle, the path starts and
+  /// ends with the same class, see
+  /// [check_hierarchy](crate::class::check_hierarchy).
+  CyclicHierarchy(Vec<String>),
+  /// `opcode` is passed to a visit method which only takes instructions of
+  /// given `kind`.
+  UnexpectedOpcode { opcode: u8, kind: InstructionKind },
+  /// A member with `name` and `descriptor` has already been visited.
+  DuplicateMember { name: String, descriptor: String },
+  /// Class file version `major_version`.`minor_version` is too old for a
+  /// visited `feature`.
+  UnsupportedFeature {
+    feature: Feature,
+    major_version: u16,
+    minor_version: u16,
+  },
+  /// `index` holds no constant in a constant pool of `len` slots, which
+  /// counts the unusable slot 0.
+  ConstantIndexOutOfBounds { index: u16, len: u16 },
+  /// Constant at `index` is of kind `found` while a constant of kind
+  /// `expected` is required.
+  UnexpectedConstant {
+    index: u16,
+    expected: &'static str,
+    found: &'static str,
+  },
+  /// Instruction at bytecode offset `at` works on values of type `found`
+  /// while `expected` is required.
+  TypeMismatch {
+    expected: Type,
+    found: Type,
+    at: u32,
+  },
+  /// An error raised while writing method `name` with `descriptor`.
+  InMethod {
+    name: String,
+    descriptor: String,
+    source: Box<KapiError>,
+  },
+}
+
+impl KapiError {
+  /// Gets the error without context added by wrapping errors such as
+  /// [KapiError::InMethod].
+  pub fn root_cause(&self) -> &KapiError {
+    match self {
+      KapiError::InMethod { source, .. } => source.root_cause(),
+      error => error,
+    }
+  }
+
+  /// Whether the error is caused by an argument which is invalid on its own.
+  pub fn is_arg_error(&self) -> bool {
+    matches!(
+      self.root_cause(),
+      KapiError::ArgError(_)
+        | KapiError::InvalidDescriptor { .. }
+        | KapiError::UnexpectedOpcode { .. }
+    )
+  }
+
+  /// Whether the error is caused by state of writers or written bytes.
+  pub fn is_state_error(&self) -> bool {
+    matches!(
+      self.root_cause(),
+      KapiError::StateError(_)
+        | KapiError::UnexpectedEnd { .. }
+        | KapiError::StackUnderflow { .. }
+        | KapiError::InvalidConstantPool(_)
+        | KapiError::ConstantPoolOverflow
+        | KapiError::CyclicHierarchy(_)
+        | KapiError::DuplicateMember { .. }
+        | KapiError::UnsupportedFeature { .. }
+        | KapiError::ConstantIndexOutOfBounds { .. }
+        | KapiError::UnexpectedConstant { .. }
+        | KapiError::TypeMismatch { .. }
+    )
+  }
+}
+
+impl Display for KapiError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      KapiError::ArgError(message) => write!(f, "Argument error: {message}"),
+      KapiError::StateError(message) => write!(f, "State error: {message}"),
+      KapiError::InvalidDescriptor { descriptor, reason } => {
+        write!(f, "Malformed descriptor `{descriptor}`: {reason}")
+      }
+      KapiError::UnexpectedEnd {
+        offset,
+        needed,
+        remaining,
+      } => write!(
+        f,
+        "Unexpected end of bytes at offset {offset}, {needed} bytes needed but {remaining} remaining"
+      ),
+      KapiError::StackUnderflow {
+        offset,
+        opcode,
+        stack_size,
+        delta,
+      } => write!(
+        f,
+        "Operand stack underflow at bytecode offset {offset}, instruction {opcode} changes stack size {stack_size} by {delta}"
+      ),
+      KapiError::InvalidConstantPool(errors) => {
+        write!(f, "Malformed constant pool: ")?;
+
+        for (i, error) in errors.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+
+          write!(f, "{error}")?;
+        }
+
+        Ok(())
+      }
+      KapiError::ConstantPoolOverflow => write!(
+        f,
+        "Constant pool overflow, constants need more than 65535 slots"
+      ),
+      KapiError::CyclicHierarchy(path) => {
+        write!(f, "Cyclic class hierarchy: {}", path.join(" -> "))
+      }
+      KapiError::UnexpectedOpcode { opcode, kind } => {
+        write!(f, "Opcode {opcode} is not {kind}")
+      }
+      KapiError::DuplicateMember { name, descriptor } => {
+        write!(f, "Member {name} {descriptor} is already visited")
+      }
+      KapiError::UnsupportedFeature {
+        feature,
+        major_version,
+        minor_version,
+      } => {
+        write!(
+          f,
+          "Class file version {major_version}.{minor_version} does not support {}, at least {}.0",
+          feature.description(),
+          feature.major_version()
+        )?;
+
+        if let Some(preview_major) = feature.preview_major_version() {
+          write!(f, " or {preview_major}.65535")?;
+        }
+
+        write!(f, " is needed")
+      }
+      KapiError::ConstantIndexOutOfBounds { index, len } => write!(
+        f,
+        "Constant pool index {index} holds no constant, constant pool has {len} slots"
+      ),
+      KapiError::UnexpectedConstant {
+        index,
+        expected,
+        found,
+      } => write!(f, "Constant at index {index} is {found}, expected {expected}"),
+      KapiError::TypeMismatch { expected, found, at } => write!(
+        f,
+        "Instruction at bytecode offset {at} works on type {} while type {} is expected",
+        found.descriptor(),
+        expected.descriptor()
+      ),
+      KapiError::InMethod {
+        name,
+        descriptor,
+        source,
+      } => write!(f, "Method {name}{descriptor}: {source}"),
+    }
+  }
+}
+
+impl Error for KapiError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      KapiError::InvalidConstantPool(errors) => errors.first().map(|error| error as &dyn Error),
+      KapiError::InMethod { source, .. } => Some(source.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+/// Kinds of instructions accepted by each [MethodVisitor](crate::method::MethodVisitor)
+/// method visiting instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstructionKind {
+  /// See [MethodVisitor::visit_simple_inst](crate::method::MethodVisitor::visit_simple_inst).
+  NoOperand,
+  /// See [MethodVisitor::visit_int_inst](crate::method::MethodVisitor::visit_int_inst).
+  Int,
+  /// See [MethodVisitor::visit_field_inst](crate::method::MethodVisitor::visit_field_inst).
+  Field,
+  /// See [MethodVisitor::visit_method_inst](crate::method::MethodVisitor::visit_method_inst).
+  Method,
+  /// See [MethodVisitor::visit_type_inst](crate::method::MethodVisitor::visit_type_inst).
+  Type,
+  /// See [MethodVisitor::visit_var_inst](crate::method::MethodVisitor::visit_var_inst).
+  LocalVariable,
+  /// See [MethodVisitor::visit_jump_inst](crate::method::MethodVisitor::visit_jump_inst).
+  Jump,
+}
+
+impl Display for InstructionKind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let kind = match self {
+      InstructionKind::NoOperand => "an instruction without operands",
+      InstructionKind::Int => "an int instruction",
+      InstructionKind::Field => "a field instruction",
+      InstructionKind::Method => "a method instruction",
+      InstructionKind::Type => "a type instruction",
+      InstructionKind::LocalVariable => "a local variable instruction",
+      InstructionKind::Jump => "a jump instruction",
+    };
+
+    write!(f, "{kind}")
+  }
+}
+
+/// Shared collection of [GenerationWarning]s, see
+/// [ClassWriter::warning_sink](crate::class::ClassWriter::warning_sink).
+pub type WarningSink = Rc<RefCell<Vec<GenerationWarning>>>;
+
+/// Notices about generated class files which are still valid, but differ
+/// from what has been visited.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GenerationWarning {
+  /// A conditional branch at bytecode offset `pc` is too far away from its
+  /// target and has been inverted to jump over a `goto_w` to the target.
+  BranchInverted { pc: u32 },
+  /// A method's code is longer than the configured threshold, see
+  /// [ClassWriter::huge_method_threshold](crate::class::ClassWriter::huge_method_threshold).
+  HugeMethod {
+    name: String,
+    descriptor: String,
+    code_len: u32,
+  },
+}
+
+/// A dangling or mistyped cross-reference found by constant pool
+/// verification, see
+/// [ClassWriter::verify_constant_pool](crate::class::ClassWriter::verify_constant_pool).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConstantPoolError {
+  /// Constant at `referenced_by` refers to an index with no constant.
+  MissingIndex { index: u16, referenced_by: u16 },
+  /// Constant at `referenced_by` refers to the unusable slot following a
+  /// Long or Double constant.
+  PaddingSlot { index: u16, referenced_by: u16 },
+  /// Constant at `referenced_by` refers to a constant of another kind.
+  UnexpectedTag {
+    index: u16,
+    referenced_by: u16,
+    expected: &'static str,
+    found: &'static str,
+  },
+  /// Utf8 constant at `index` is used as a descriptor but is malformed.
+  InvalidDescriptor { index: u16, descriptor: String },
+  /// MethodHandle constant at `index` has a reference kind out of 1 to 9.
+  InvalidReferenceKind { index: u16, kind: u8 },
+  /// Dynamic or InvokeDynamic constant at `index` refers to a missing entry
+  /// of attribute BootstrapMethods.
+  MissingBootstrapMethod {
+    index: u16,
+    bootstrap_method_attr_index: u16,
+  },
+}
+
+impl Display for ConstantPoolError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ConstantPoolError::MissingIndex {
+        index,
+        referenced_by,
+      } => write!(
+        f,
+        "constant #{referenced_by} refers to missing constant #{index}"
+      ),
+      ConstantPoolError::PaddingSlot {
+        index,
+        referenced_by,
+      } => write!(
+        f,
+        "constant #{referenced_by} refers to #{index}, which is the second slot of a Long or Double"
+      ),
+      ConstantPoolError::UnexpectedTag {
+        index,
+        referenced_by,
+        expected,
+        found,
+      } => write!(
+        f,
+        "constant #{referenced_by} expects {expected} at #{index}, but found {found}"
+      ),
+      ConstantPoolError::InvalidDescriptor { index, descriptor } => {
+        write!(
+          f,
+          "constant #{index} is not a valid descriptor: {descriptor}"
+        )
+      }
+      ConstantPoolError::InvalidReferenceKind { index, kind } => {
+        write!(f, "constant #{index} has invalid reference kind {kind}")
+      }
+      ConstantPoolError::MissingBootstrapMethod {
+        index,
+        bootstrap_method_attr_index,
+      } => write!(
+        f,
+        "constant #{index} refers to missing bootstrap method {bootstrap_method_attr_index}"
+      ),
+    }
+  }
+}
+
+impl Error for ConstantPoolError {}
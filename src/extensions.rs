@@ -0,0 +1,96 @@
+use std::{
+  any::{
+    Any,
+    TypeId,
+  },
+  collections::HashMap,
+  fmt::{
+    Debug,
+    Formatter,
+  },
+};
+
+/// User data attached to a writer, keyed by its type. Extensions never
+/// affect emitted class files.
+#[derive(Default)]
+pub struct Extensions {
+  map: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Extensions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Inserts a value, returns the previous value of the same type if any.
+  pub fn insert<T: 'static>(&mut self, value: T) -> Option<T> {
+    self
+      .map
+      .insert(TypeId::of::<T>(), Box::new(value))
+      .and_then(|previous| previous.downcast().ok())
+      .map(|previous| *previous)
+  }
+
+  pub fn get<T: 'static>(&self) -> Option<&T> {
+    self
+      .map
+      .get(&TypeId::of::<T>())
+      .and_then(|value| value.downcast_ref())
+  }
+
+  pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+    self
+      .map
+      .get_mut(&TypeId::of::<T>())
+      .and_then(|value| value.downcast_mut())
+  }
+
+  pub fn remove<T: 'static>(&mut self) -> Option<T> {
+    self
+      .map
+      .remove(&TypeId::of::<T>())
+      .and_then(|value| value.downcast().ok())
+      .map(|value| *value)
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+}
+
+// Stored values are not required to be Debug
+impl Debug for Extensions {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Extensions")
+      .field("len", &self.map.len())
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::extensions::Extensions;
+
+  #[test]
+  fn test_extensions() {
+    #[derive(Debug, PartialEq)]
+    struct NodeId(u32);
+
+    let mut extensions = Extensions::new();
+
+    assert_eq!(extensions.insert(NodeId(1)), None);
+    assert_eq!(extensions.insert(String::from("pass")), None);
+    assert_eq!(extensions.insert(NodeId(2)), Some(NodeId(1)));
+    assert_eq!(extensions.len(), 2);
+
+    extensions.get_mut::<NodeId>().unwrap().0 += 1;
+
+    assert_eq!(extensions.get::<NodeId>(), Some(&NodeId(3)));
+    assert_eq!(extensions.remove::<String>(), Some(String::from("pass")));
+    assert_eq!(extensions.get::<String>(), None);
+  }
+}
@@ -0,0 +1,569 @@
+use std::{
+  cell::RefCell,
+  rc::Rc,
+};
+
+use crate::{
+  access_flag::FieldAccessFlag,
+  annotation::{
+    compute_annotations_size,
+    compute_type_annotations_size,
+    put_annotations,
+    put_type_annotations,
+    AnnotationVisitor,
+    AnnotationWriter,
+    TargetInfo,
+    TypeAnnotationWriter,
+    TypePathStep,
+  },
+  attrs,
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    SizeComputable,
+    ToBytes,
+  },
+  constant::ConstantPool,
+  error::{
+    KapiError,
+    KapiResult,
+  },
+};
+
+/// Value of a ConstantValue attribute, see JVMS 4.7.2.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantValue {
+  Int(i32),
+  Float(f32),
+  Long(i64),
+  Double(f64),
+  String(String),
+}
+
+pub trait FieldVisitor {
+  fn inner(&mut self) -> Option<&mut dyn FieldVisitor> {
+    None
+  }
+
+  /// Visits the initial value of a static field, the type of `value` must
+  /// match the field descriptor, see JVMS 4.7.2.
+  fn visit_constant(&mut self, value: ConstantValue) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_constant(value)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn visit_signature(&mut self, signature: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_signature(signature);
+    }
+  }
+
+  fn visit_annotation(
+    &mut self,
+    descriptor: &str,
+    visible: bool,
+  ) -> Option<&mut dyn AnnotationVisitor> {
+    if let Some(inner) = self.inner() {
+      inner.visit_annotation(descriptor, visible)
+    } else {
+      None
+    }
+  }
+
+  fn visit_deprecated(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_deprecated();
+    }
+  }
+
+  fn visit_synthetic(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_synthetic();
+    }
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if let Some(inner) = self.inner() {
+      inner.visit_type_annotation(target_info, type_path, descriptor, visible)
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn visit_end(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_end();
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct FieldWriter {
+  constant_pool: Rc<RefCell<ConstantPool>>,
+  access: FieldAccessFlag,
+  name_index: u16,
+  descriptor: String,
+  descriptor_index: u16,
+  // Attribute ConstantValue
+  constant_value_index: Option<u16>,
+  // Attribute Signature
+  signature_index: Option<u16>,
+  // Attribute RuntimeVisibleAnnotations
+  visible_annotations: Vec<AnnotationWriter>,
+  // Attribute RuntimeInvisibleAnnotations
+  invisible_annotations: Vec<AnnotationWriter>,
+  // Ka-Pi Specified
+  deprecated: bool,
+  // Attribute Synthetic
+  synthetic: bool,
+  // Attribute RuntimeVisibleTypeAnnotations
+  visible_type_annotations: Vec<TypeAnnotationWriter>,
+  // Attribute RuntimeInvisibleTypeAnnotations
+  invisible_type_annotations: Vec<TypeAnnotationWriter>,
+}
+
+impl FieldWriter {
+  pub(crate) fn new(
+    constant_pool: Rc<RefCell<ConstantPool>>,
+    access: FieldAccessFlag,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+  ) -> KapiResult<Self> {
+    let mut cp = constant_pool.borrow_mut();
+    let name_index = cp.put_utf8(name)?;
+    let descriptor_index = cp.put_utf8(descriptor)?;
+
+    drop(cp);
+
+    let mut fw = Self {
+      constant_pool,
+      access,
+      name_index,
+      descriptor: descriptor.to_owned(),
+      descriptor_index,
+      constant_value_index: None,
+      signature_index: None,
+      visible_annotations: Vec::new(),
+      invisible_annotations: Vec::new(),
+      deprecated: false,
+      synthetic: false,
+      visible_type_annotations: Vec::new(),
+      invisible_type_annotations: Vec::new(),
+    };
+
+    if let Some(signature) = signature {
+      fw.visit_signature(signature);
+    }
+
+    Ok(fw)
+  }
+}
+
+impl FieldVisitor for FieldWriter {
+  fn visit_constant(&mut self, value: ConstantValue) -> KapiResult<()> {
+    let valid = matches!(
+      (&value, self.descriptor.as_str()),
+      (ConstantValue::Int(_), "I" | "S" | "C" | "B" | "Z")
+        | (ConstantValue::Float(_), "F")
+        | (ConstantValue::Long(_), "J")
+        | (ConstantValue::Double(_), "D")
+        | (ConstantValue::String(_), "Ljava/lang/String;")
+    );
+
+    if !valid {
+      return Err(KapiError::ArgError(format!(
+        "Constant value {value:?} cannot initialize field of type {}",
+        self.descriptor
+      )));
+    }
+
+    let mut cp = self.constant_pool.borrow_mut();
+
+    cp.put_utf8(attrs::CONSTANT_VALUE)?;
+    self.constant_value_index = Some(match value {
+      ConstantValue::Int(int) => cp.put_integer(int),
+      ConstantValue::Float(float) => cp.put_float(float),
+      ConstantValue::Long(long) => cp.put_long(long),
+      ConstantValue::Double(double) => cp.put_double(double),
+      ConstantValue::String(string) => cp.put_string(&string),
+    }?);
+
+    Ok(())
+  }
+
+  fn visit_signature(&mut self, signature: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::SIGNATURE);
+    self.signature_index = cp.put_utf8(signature).ok();
+  }
+
+  fn visit_annotation(
+    &mut self,
+    descriptor: &str,
+    visible: bool,
+  ) -> Option<&mut dyn AnnotationVisitor> {
+    let annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_ANNOTATIONS)
+        .ok()?;
+      &mut self.visible_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_ANNOTATIONS)
+        .ok()?;
+      &mut self.invisible_annotations
+    };
+
+    annotations.push(AnnotationWriter::new(self.constant_pool.clone(), descriptor).ok()?);
+
+    annotations
+      .last_mut()
+      .map(|aw| aw as &mut dyn AnnotationVisitor)
+  }
+
+  fn visit_deprecated(&mut self) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::DEPRECATED);
+    self.deprecated = true;
+  }
+
+  fn visit_synthetic(&mut self) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::SYNTHETIC);
+    self.synthetic = true;
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if !target_info.is_field_target() {
+      return Err(KapiError::ArgError(format!(
+        "Type annotation target {target_info:?} is not applicable to field"
+      )));
+    }
+
+    let type_annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.visible_type_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.invisible_type_annotations
+    };
+
+    type_annotations.push(TypeAnnotationWriter::new(
+      self.constant_pool.clone(),
+      target_info,
+      type_path,
+      descriptor,
+    )?);
+
+    Ok(
+      type_annotations
+        .last_mut()
+        .map(|taw| taw as &mut dyn AnnotationVisitor),
+    )
+  }
+}
+
+impl ToBytes for FieldWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    let cp = self.constant_pool.borrow();
+
+    vec
+      .push_u16(self.access.bits())
+      .push_u16(self.name_index)
+      .push_u16(self.descriptor_index)
+      .push_u16(self.attributes_count() as u16);
+
+    if let Some(constant_value_index) = self.constant_value_index {
+      vec
+        .push_u16(cp.get_utf8(attrs::CONSTANT_VALUE).unwrap())
+        .push_u32(2)
+        .push_u16(constant_value_index);
+    }
+
+    if let Some(signature_index) = self.signature_index {
+      vec
+        .push_u16(cp.get_utf8(attrs::SIGNATURE).unwrap())
+        .push_u32(2)
+        .push_u16(signature_index);
+    }
+
+    put_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_ANNOTATIONS,
+      &self.visible_annotations,
+    )?;
+    put_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_ANNOTATIONS,
+      &self.invisible_annotations,
+    )?;
+
+    if self.deprecated {
+      vec
+        .push_u16(cp.get_utf8(attrs::DEPRECATED).unwrap())
+        .push_u32(0);
+    }
+
+    if self.synthetic {
+      vec
+        .push_u16(cp.get_utf8(attrs::SYNTHETIC).unwrap())
+        .push_u32(0);
+    }
+
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS,
+      &self.visible_type_annotations,
+      &Ok,
+    )?;
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS,
+      &self.invisible_type_annotations,
+      &Ok,
+    )?;
+
+    Ok(())
+  }
+}
+
+impl SizeComputable for FieldWriter {
+  fn compute_size(&self) -> usize {
+    let mut size = 8;
+
+    if self.constant_value_index.is_some() {
+      size += 8;
+    }
+
+    if self.signature_index.is_some() {
+      size += 8;
+    }
+
+    size += compute_annotations_size(&self.visible_annotations);
+    size += compute_annotations_size(&self.invisible_annotations);
+
+    if self.deprecated {
+      size += 6;
+    }
+
+    if self.synthetic {
+      size += 6;
+    }
+
+    size += compute_type_annotations_size(&self.visible_type_annotations);
+    size += compute_type_annotations_size(&self.invisible_type_annotations);
+
+    size
+  }
+
+  fn attributes_count(&self) -> usize {
+    let mut count = 0;
+
+    if self.constant_value_index.is_some() {
+      count += 1;
+    }
+
+    if self.signature_index.is_some() {
+      count += 1;
+    }
+
+    if !self.visible_annotations.is_empty() {
+      count += 1;
+    }
+
+    if !self.invisible_annotations.is_empty() {
+      count += 1;
+    }
+
+    if self.deprecated {
+      count += 1;
+    }
+
+    if self.synthetic {
+      count += 1;
+    }
+
+    if !self.visible_type_annotations.is_empty() {
+      count += 1;
+    }
+
+    if !self.invisible_type_annotations.is_empty() {
+      count += 1;
+    }
+
+    count
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    cell::RefCell,
+    rc::Rc,
+  };
+
+  use crate::{
+    access_flag::FieldAccessFlag,
+    annotation::AnnotationValue,
+    byte_vec::{
+      SizeComputable,
+      ToBytes,
+    },
+    constant::ConstantPool,
+    error::KapiError,
+    field::{
+      ConstantValue,
+      FieldVisitor,
+      FieldWriter,
+    },
+  };
+
+  fn field_writer(descriptor: &str) -> FieldWriter {
+    FieldWriter::new(
+      Rc::new(RefCell::new(ConstantPool::default())),
+      FieldAccessFlag::Static | FieldAccessFlag::Final,
+      "VALUE",
+      descriptor,
+      None,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_field_attributes() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+    let mut fw = FieldWriter::new(
+      cp.clone(),
+      FieldAccessFlag::Private | FieldAccessFlag::Static | FieldAccessFlag::Final,
+      "CACHE",
+      "Ljava/util/Map;",
+      Some("Ljava/util/Map<Ljava/lang/String;Ljava/lang/Integer;>;"),
+    )
+    .unwrap();
+
+    fw.visit_deprecated();
+
+    let aw = fw.visit_annotation("Ljava/lang/Deprecated;", true).unwrap();
+
+    aw.visit("since", AnnotationValue::String(String::from("1.0")));
+    aw.visit_end();
+    fw.visit_end();
+
+    let mut bytes = Vec::new();
+
+    fw.put_bytes(&mut bytes).unwrap();
+
+    let cp = cp.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [name_0, name_1] = utf8("CACHE");
+    let [descriptor_0, descriptor_1] = utf8("Ljava/util/Map;");
+    let [signature_0, signature_1] = utf8("Signature");
+    let [signature_value_0, signature_value_1] =
+      utf8("Ljava/util/Map<Ljava/lang/String;Ljava/lang/Integer;>;");
+    let [annotations_0, annotations_1] = utf8("RuntimeVisibleAnnotations");
+    let [type_0, type_1] = utf8("Ljava/lang/Deprecated;");
+    let [since_0, since_1] = utf8("since");
+    let [since_value_0, since_value_1] = utf8("1.0");
+    let [deprecated_0, deprecated_1] = utf8("Deprecated");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      bytes,
+      vec![
+        0x00, 0x1A,
+        name_0, name_1,
+        descriptor_0, descriptor_1,
+        0, 3,
+        signature_0, signature_1, 0, 0, 0, 2, signature_value_0, signature_value_1,
+        annotations_0, annotations_1, 0, 0, 0, 11, 0, 1,
+        type_0, type_1, 0, 1,
+        since_0, since_1, b's', since_value_0, since_value_1,
+        deprecated_0, deprecated_1, 0, 0, 0, 0,
+      ]
+    );
+    assert_eq!(fw.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_constant_value_type() {
+    for (descriptor, value) in [
+      ("I", ConstantValue::Int(1)),
+      ("S", ConstantValue::Int(1)),
+      ("C", ConstantValue::Int(1)),
+      ("B", ConstantValue::Int(1)),
+      ("Z", ConstantValue::Int(1)),
+      ("F", ConstantValue::Float(1.0)),
+      ("J", ConstantValue::Long(1)),
+      ("D", ConstantValue::Double(1.0)),
+      (
+        "Ljava/lang/String;",
+        ConstantValue::String(String::from("1")),
+      ),
+    ] {
+      let mut fw = field_writer(descriptor);
+
+      assert_eq!(fw.visit_constant(value), Ok(()));
+      assert!(fw.constant_value_index.is_some());
+    }
+
+    for (descriptor, value) in [
+      ("I", ConstantValue::Long(1)),
+      ("J", ConstantValue::Int(1)),
+      ("F", ConstantValue::Double(1.0)),
+      ("D", ConstantValue::Float(1.0)),
+      ("Ljava/lang/String;", ConstantValue::Int(1)),
+      (
+        "Ljava/lang/Object;",
+        ConstantValue::String(String::from("1")),
+      ),
+    ] {
+      let mut fw = field_writer(descriptor);
+
+      assert!(matches!(
+        fw.visit_constant(value),
+        Err(KapiError::ArgError(_))
+      ));
+      assert_eq!(fw.constant_value_index, None);
+    }
+
+    assert_eq!(
+      field_writer("I").visit_constant(ConstantValue::Long(1)),
+      Err(KapiError::ArgError(String::from(
+        "Constant value Long(1) cannot initialize field of type I"
+      )))
+    );
+  }
+}
@@ -0,0 +1,244 @@
+use crate::{
+  error::{
+    KapiError,
+    KapiResult,
+  },
+  types::{
+    parse_field_descriptor,
+    parse_method_descriptor,
+    Type,
+  },
+};
+
+/// Kind of a method handle, see JVMS 5.4.3.5.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RefKind {
+  GetField = 1,
+  GetStatic = 2,
+  PutField = 3,
+  PutStatic = 4,
+  InvokeVirtual = 5,
+  InvokeStatic = 6,
+  InvokeSpecial = 7,
+  NewInvokeSpecial = 8,
+  InvokeInterface = 9,
+}
+
+impl RefKind {
+  /// Whether the handle reads or writes a field.
+  pub const fn is_field_kind(&self) -> bool {
+    matches!(
+      self,
+      Self::GetField | Self::GetStatic | Self::PutField | Self::PutStatic
+    )
+  }
+
+  /// Whether the handle invokes a method or constructor.
+  pub const fn is_method_kind(&self) -> bool {
+    !self.is_field_kind()
+  }
+
+  /// Whether the referenced member must be an interface method
+  /// (`Some(true)`), must not be one (`Some(false)`), or may be either since
+  /// class file version 52.0 (`None`).
+  pub const fn expects_interface(&self) -> Option<bool> {
+    match self {
+      Self::InvokeInterface => Some(true),
+      Self::InvokeStatic | Self::InvokeSpecial => None,
+      _ => Some(false),
+    }
+  }
+}
+
+impl TryFrom<u8> for RefKind {
+  type Error = KapiError;
+
+  fn try_from(kind: u8) -> Result<Self, Self::Error> {
+    Ok(match kind {
+      1 => Self::GetField,
+      2 => Self::GetStatic,
+      3 => Self::PutField,
+      4 => Self::PutStatic,
+      5 => Self::InvokeVirtual,
+      6 => Self::InvokeStatic,
+      7 => Self::InvokeSpecial,
+      8 => Self::NewInvokeSpecial,
+      9 => Self::InvokeInterface,
+      _ => {
+        return Err(KapiError::ArgError(format!(
+          "Reference kind {kind} is out of 1 to 9"
+        )))
+      }
+    })
+  }
+}
+
+impl From<RefKind> for u8 {
+  fn from(kind: RefKind) -> Self {
+    kind as u8
+  }
+}
+
+/// A method handle referencing a field or method, used by MethodHandle
+/// constants. Constructing one validates the reference kind against the
+/// referenced member, see JVMS 4.4.8.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Handle {
+  kind: RefKind,
+  owner: String,
+  name: String,
+  descriptor: String,
+  is_interface: bool,
+}
+
+impl Handle {
+  pub fn new(
+    kind: RefKind,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+    is_interface: bool,
+  ) -> KapiResult<Self> {
+    let violation = if kind.is_field_kind() {
+      parse_field_descriptor(descriptor)
+        .err()
+        .map(|_| "field handle kinds require a field descriptor")
+    } else {
+      match parse_method_descriptor(descriptor) {
+        Err(_) => Some("method handle kinds require a method descriptor"),
+        Ok((_, return_type)) => match kind {
+          RefKind::NewInvokeSpecial if name != "<init>" || return_type != Type::Void => {
+            Some("NewInvokeSpecial requires name <init> and void return type")
+          }
+          _ if kind.expects_interface() == Some(false) && is_interface => {
+            Some("NewInvokeSpecial and InvokeVirtual cannot reference an interface method")
+          }
+          _ if kind.expects_interface() == Some(true) && !is_interface => {
+            Some("InvokeInterface requires an interface method")
+          }
+          RefKind::NewInvokeSpecial => None,
+          _ if name == "<init>" || name == "<clinit>" => {
+            Some("only NewInvokeSpecial may reference an instance initialization method")
+          }
+          _ => None,
+        },
+      }
+    };
+
+    if let Some(violation) = violation {
+      return Err(KapiError::ArgError(format!(
+        "Invalid {kind:?} handle {owner}.{name}{descriptor}: {violation}"
+      )));
+    }
+
+    Ok(Self {
+      kind,
+      owner: owner.to_owned(),
+      name: name.to_owned(),
+      descriptor: descriptor.to_owned(),
+      is_interface,
+    })
+  }
+
+  pub fn kind(&self) -> RefKind {
+    self.kind
+  }
+
+  pub fn owner(&self) -> &str {
+    &self.owner
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn descriptor(&self) -> &str {
+    &self.descriptor
+  }
+
+  pub fn is_interface(&self) -> bool {
+    self.is_interface
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    constant::ConstantPool,
+    error::KapiError,
+    handle::{
+      Handle,
+      RefKind,
+    },
+  };
+
+  #[test]
+  fn test_handle_validation() {
+    for (kind, name, descriptor, is_interface) in [
+      (RefKind::GetField, "value", "I", false),
+      (RefKind::GetStatic, "INSTANCE", "LMain;", false),
+      (RefKind::PutField, "value", "[J", false),
+      (RefKind::PutStatic, "INSTANCE", "LMain;", false),
+      (RefKind::InvokeVirtual, "run", "()V", false),
+      (RefKind::InvokeStatic, "of", "(I)LMain;", true),
+      (RefKind::InvokeSpecial, "run", "()V", false),
+      (RefKind::NewInvokeSpecial, "<init>", "(I)V", false),
+      (RefKind::InvokeInterface, "run", "()V", true),
+    ] {
+      assert!(
+        Handle::new(kind, "Main", name, descriptor, is_interface).is_ok(),
+        "{kind:?}"
+      );
+    }
+
+    for (kind, name, descriptor, is_interface) in [
+      (RefKind::GetField, "value", "()V", false),
+      (RefKind::PutStatic, "value", "V", false),
+      (RefKind::InvokeVirtual, "run", "I", false),
+      (RefKind::InvokeVirtual, "run", "()V", true),
+      (RefKind::InvokeStatic, "<clinit>", "()V", false),
+      (RefKind::InvokeSpecial, "<init>", "()V", false),
+      (RefKind::NewInvokeSpecial, "create", "()V", false),
+      (RefKind::NewInvokeSpecial, "<init>", "()I", false),
+      (RefKind::NewInvokeSpecial, "<init>", "()V", true),
+      (RefKind::InvokeInterface, "run", "()V", false),
+    ] {
+      assert!(
+        matches!(
+          Handle::new(kind, "Main", name, descriptor, is_interface),
+          Err(KapiError::ArgError(_))
+        ),
+        "{kind:?} {name}{descriptor}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_ref_kind_conversions() {
+    for value in 1..=9u8 {
+      let kind = RefKind::try_from(value).unwrap();
+
+      assert_eq!(u8::from(kind), value);
+      assert_ne!(kind.is_field_kind(), kind.is_method_kind());
+    }
+
+    assert!(RefKind::PutStatic.is_field_kind());
+    assert!(RefKind::NewInvokeSpecial.is_method_kind());
+    assert_eq!(RefKind::InvokeInterface.expects_interface(), Some(true));
+    assert_eq!(RefKind::InvokeVirtual.expects_interface(), Some(false));
+    assert_eq!(RefKind::InvokeStatic.expects_interface(), None);
+    assert!(matches!(RefKind::try_from(0), Err(KapiError::ArgError(_))));
+    assert!(matches!(RefKind::try_from(10), Err(KapiError::ArgError(_))));
+  }
+
+  #[test]
+  fn test_put_method_handle() {
+    let mut cp = ConstantPool::default();
+    let handle = Handle::new(RefKind::InvokeInterface, "Main", "run", "()V", true).unwrap();
+    let index = cp.put_method_handle(&handle).unwrap();
+
+    assert_eq!(cp.put_method_handle(&handle).unwrap(), index);
+    assert_eq!(cp.verify(), Ok(()));
+  }
+}
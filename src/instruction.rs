@@ -0,0 +1,900 @@
+use std::collections::{
+  HashMap,
+  HashSet,
+};
+
+use crate::{
+  byte_reader::ByteReader,
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+  },
+  constant::{
+    Constant,
+    ConstantPool,
+  },
+  error::{
+    KapiError,
+    KapiResult,
+  },
+  opcodes::*,
+  types::{
+    parse_field_descriptor,
+    parse_method_descriptor,
+    Type,
+  },
+};
+
+/// A decoded view of a single instruction inside a code array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Instruction {
+  pub(crate) offset: u32,
+  pub(crate) opcode: u8,
+  pub(crate) len: u32,
+}
+
+impl Instruction {
+  /// Whether execution can never continue to the next instruction.
+  pub(crate) const fn is_terminal(&self) -> bool {
+    matches!(
+      self.opcode,
+      GOTO
+        | GOTO_W
+        | RET
+        | TABLESWITCH
+        | LOOKUPSWITCH
+        | IRETURN
+        | LRETURN
+        | FRETURN
+        | DRETURN
+        | ARETURN
+        | RETURN
+        | ATHROW
+    )
+  }
+
+  /// Gets all absolute jump targets of current instruction, including every
+  /// target of switch instructions.
+  pub(crate) fn jump_targets(&self, code: &[u8]) -> KapiResult<Vec<u32>> {
+    let offset = self.offset as usize;
+    let relative_to = |relative_offset: i32| self.offset.wrapping_add(relative_offset as u32);
+
+    match self.opcode {
+      IFEQ..=JSR | IFNULL | IFNONNULL => {
+        Ok(vec![relative_to(read_u16(code, offset + 1)? as i16 as i32)])
+      }
+      GOTO_W | JSR_W => Ok(vec![relative_to(read_i32(code, offset + 1)?)]),
+      TABLESWITCH => {
+        let operands = switch_operands_offset(offset);
+        let low = read_i32(code, operands + 4)?;
+        let high = read_i32(code, operands + 8)?;
+        let mut targets = vec![relative_to(read_i32(code, operands)?)];
+
+        for i in 0..(high as i64 - low as i64 + 1) as usize {
+          targets.push(relative_to(read_i32(code, operands + 12 + 4 * i)?));
+        }
+
+        Ok(targets)
+      }
+      LOOKUPSWITCH => {
+        let operands = switch_operands_offset(offset);
+        let npairs = read_i32(code, operands + 4)? as usize;
+        let mut targets = vec![relative_to(read_i32(code, operands)?)];
+
+        for i in 0..npairs {
+          targets.push(relative_to(read_i32(code, operands + 12 + 8 * i)?));
+        }
+
+        Ok(targets)
+      }
+      _ => Ok(vec![]),
+    }
+  }
+
+  /// Gets the local variable slot and its size accessed by current
+  /// instruction, if any.
+  pub(crate) fn local_variable(&self, code: &[u8]) -> KapiResult<Option<(u16, u16)>> {
+    let offset = self.offset as usize;
+    let size = |opcode: u8| match opcode {
+      LLOAD | DLOAD | LSTORE | DSTORE => 2,
+      _ => 1,
+    };
+
+    let local = match self.opcode {
+      ILOAD..=ALOAD | ISTORE..=ASTORE | RET => Some((code[offset + 1] as u16, size(self.opcode))),
+      ILOAD_0..=ALOAD_3 => {
+        let index = (self.opcode - ILOAD_0) % 4;
+        let opcode = ILOAD + (self.opcode - ILOAD_0) / 4;
+
+        Some((index as u16, size(opcode)))
+      }
+      ISTORE_0..=ASTORE_3 => {
+        let index = (self.opcode - ISTORE_0) % 4;
+        let opcode = ISTORE + (self.opcode - ISTORE_0) / 4;
+
+        Some((index as u16, size(opcode)))
+      }
+      IINC => Some((code[offset + 1] as u16, 1)),
+      WIDE => {
+        let opcode = read_u8(code, offset + 1)?;
+
+        Some((read_u16(code, offset + 2)?, size(opcode)))
+      }
+      _ => None,
+    };
+
+    Ok(local)
+  }
+
+  /// Gets the amount of operand stack words changed after executing current
+  /// instruction.
+  pub(crate) fn stack_size_delta(&self, code: &[u8], cp: &ConstantPool) -> KapiResult<i32> {
+    let offset = self.offset as usize;
+
+    let delta = match self.opcode {
+      NOP => 0,
+      ACONST_NULL..=ICONST_5 => 1,
+      LCONST_0..=LCONST_1 => 2,
+      FCONST_0..=FCONST_2 => 1,
+      DCONST_0..=DCONST_1 => 2,
+      BIPUSH | SIPUSH | LDC | LDC_W => 1,
+      LDC2_W => 2,
+      ILOAD | FLOAD | ALOAD => 1,
+      LLOAD | DLOAD => 2,
+      ILOAD_0..=ILOAD_3 | FLOAD_0..=FLOAD_3 | ALOAD_0..=ALOAD_3 => 1,
+      LLOAD_0..=LLOAD_3 | DLOAD_0..=DLOAD_3 => 2,
+      IALOAD | FALOAD | AALOAD | BALOAD | CALOAD | SALOAD => -1,
+      LALOAD | DALOAD => 0,
+      ISTORE | FSTORE | ASTORE => -1,
+      LSTORE | DSTORE => -2,
+      ISTORE_0..=ISTORE_3 | FSTORE_0..=FSTORE_3 | ASTORE_0..=ASTORE_3 => -1,
+      LSTORE_0..=LSTORE_3 | DSTORE_0..=DSTORE_3 => -2,
+      IASTORE | FASTORE | AASTORE | BASTORE | CASTORE | SASTORE => -3,
+      LASTORE | DASTORE => -4,
+      POP => -1,
+      POP2 => -2,
+      DUP | DUP_X1 | DUP_X2 => 1,
+      DUP2 | DUP2_X1 | DUP2_X2 => 2,
+      SWAP => 0,
+      IADD..=DREM => {
+        // Operators are ordered as I, L, F, D for each operation
+        if (self.opcode - IADD).is_multiple_of(2) {
+          -1
+        } else {
+          -2
+        }
+      }
+      INEG..=DNEG => 0,
+      ISHL..=LUSHR => -1,
+      IAND | IOR | IXOR => -1,
+      LAND | LOR | LXOR => -2,
+      IINC => 0,
+      I2L | I2D | F2L | F2D => 1,
+      I2F | L2D | F2I | D2L | I2B | I2C | I2S => 0,
+      L2I | L2F | D2I | D2F => -1,
+      LCMP | DCMPL | DCMPG => -3,
+      FCMPL | FCMPG => -1,
+      IFEQ..=IFLE => -1,
+      IF_ICMPEQ..=IF_ACMPNE => -2,
+      GOTO | GOTO_W | RET => 0,
+      JSR | JSR_W => 1,
+      TABLESWITCH | LOOKUPSWITCH => -1,
+      IRETURN | FRETURN | ARETURN => -1,
+      LRETURN | DRETURN => -2,
+      RETURN => 0,
+      GETSTATIC..=PUTFIELD => {
+        let size =
+          parse_field_descriptor(ref_descriptor(cp, read_u16(code, offset + 1)?)?)?.size() as i32;
+
+        match self.opcode {
+          GETSTATIC => size,
+          PUTSTATIC => -size,
+          GETFIELD => size - 1,
+          _ => -size - 1,
+        }
+      }
+      INVOKEVIRTUAL..=INVOKEDYNAMIC => {
+        let descriptor = ref_descriptor(cp, read_u16(code, offset + 1)?)?;
+        let (parameter_types, return_type) = parse_method_descriptor(descriptor)?;
+        let arguments_size = parameter_types.iter().map(Type::size).sum::<u16>();
+        let delta = return_type.size() as i32 - arguments_size as i32;
+
+        match self.opcode {
+          INVOKESTATIC | INVOKEDYNAMIC => delta,
+          _ => delta - 1,
+        }
+      }
+      NEW => 1,
+      NEWARRAY | ANEWARRAY | ARRAYLENGTH | CHECKCAST | INSTANCEOF => 0,
+      ATHROW | MONITORENTER | MONITOREXIT => -1,
+      WIDE => match read_u8(code, offset + 1)? {
+        ILOAD | FLOAD | ALOAD => 1,
+        LLOAD | DLOAD => 2,
+        ISTORE | FSTORE | ASTORE => -1,
+        LSTORE | DSTORE => -2,
+        _ => 0,
+      },
+      MULTIANEWARRAY => 1 - read_u8(code, offset + 3)? as i32,
+      IFNULL | IFNONNULL => -1,
+      opcode => {
+        return Err(KapiError::StateError(format!(
+          "Unknown opcode {opcode} at bytecode offset {offset}"
+        )))
+      }
+    };
+
+    Ok(delta)
+  }
+}
+
+/// Iterates over every instruction in a code array.
+pub(crate) struct Instructions<'a> {
+  code: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Instructions<'a> {
+  pub(crate) fn new(code: &'a [u8]) -> Self {
+    Self { code, offset: 0 }
+  }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+  type Item = KapiResult<Instruction>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.offset >= self.code.len() {
+      return None;
+    }
+
+    let instruction = instruction_at(self.code, self.offset as u32);
+
+    match &instruction {
+      Ok(instruction) => self.offset += instruction.len as usize,
+      Err(_) => self.offset = self.code.len(),
+    }
+
+    Some(instruction)
+  }
+}
+
+/// Decodes the instruction starting at given bytecode offset.
+pub(crate) fn instruction_at(code: &[u8], offset: u32) -> KapiResult<Instruction> {
+  let pos = offset as usize;
+  let opcode = read_u8(code, pos)?;
+
+  let len = match opcode {
+    NOP..=DCONST_1 => 1,
+    BIPUSH => 2,
+    SIPUSH => 3,
+    LDC => 2,
+    LDC_W | LDC2_W => 3,
+    ILOAD..=ALOAD => 2,
+    ILOAD_0..=SALOAD => 1,
+    ISTORE..=ASTORE => 2,
+    ISTORE_0..=LXOR => 1,
+    IINC => 3,
+    I2L..=DCMPG => 1,
+    IFEQ..=JSR => 3,
+    RET => 2,
+    TABLESWITCH => {
+      let operands = switch_operands_offset(pos);
+      let low = read_i32(code, operands + 4)?;
+      let high = read_i32(code, operands + 8)?;
+
+      (operands - pos) + 12 + 4 * (high as i64 - low as i64 + 1).max(0) as usize
+    }
+    LOOKUPSWITCH => {
+      let operands = switch_operands_offset(pos);
+      let npairs = read_i32(code, operands + 4)?;
+
+      (operands - pos) + 8 + 8 * npairs.max(0) as usize
+    }
+    IRETURN..=RETURN => 1,
+    GETSTATIC..=INVOKESTATIC => 3,
+    INVOKEINTERFACE | INVOKEDYNAMIC => 5,
+    NEW => 3,
+    NEWARRAY => 2,
+    ANEWARRAY => 3,
+    ARRAYLENGTH | ATHROW => 1,
+    CHECKCAST | INSTANCEOF => 3,
+    MONITORENTER | MONITOREXIT => 1,
+    WIDE => {
+      if read_u8(code, pos + 1)? == IINC {
+        6
+      } else {
+        4
+      }
+    }
+    MULTIANEWARRAY => 4,
+    IFNULL | IFNONNULL => 3,
+    GOTO_W | JSR_W => 5,
+    _ => {
+      return Err(KapiError::StateError(format!(
+        "Unknown opcode {opcode} at bytecode offset {offset}"
+      )))
+    }
+  };
+
+  if pos + len > code.len() {
+    return Err(KapiError::StateError(format!(
+      "Instruction at bytecode offset {offset} exceeds code length {}",
+      code.len()
+    )));
+  }
+
+  Ok(Instruction {
+    offset,
+    opcode,
+    len: len as u32,
+  })
+}
+
+/// Whether the instruction consists of its opcode only.
+pub(crate) const fn takes_no_operands(opcode: u8) -> bool {
+  matches!(
+    opcode,
+    NOP..=DCONST_1
+      | ILOAD_0..=SALOAD
+      | ISTORE_0..=LXOR
+      | I2L..=DCMPG
+      | IRETURN..=RETURN
+      | ARRAYLENGTH
+      | ATHROW
+      | MONITORENTER
+      | MONITOREXIT
+  )
+}
+
+/// Whether the instruction branches to a single label.
+pub(crate) const fn is_jump(opcode: u8) -> bool {
+  matches!(opcode, IFEQ..=JSR | IFNULL | IFNONNULL | GOTO_W | JSR_W)
+}
+
+/// Gets the opcode branching on the opposite condition of a conditional
+/// branch instruction.
+pub(crate) const fn inverse_branch(opcode: u8) -> u8 {
+  if opcode >= IFNULL {
+    opcode ^ 1
+  } else {
+    ((opcode + 1) ^ 1) - 1
+  }
+}
+
+/// Code rewritten by [expand_jumps].
+#[derive(Debug)]
+pub(crate) struct ExpandedCode {
+  pub(crate) code: ByteVec,
+  /// Bytecode offsets of conditional branches inverted to jump over a
+  /// `goto_w`.
+  pub(crate) inverted_branches: Vec<u32>,
+  // Maps instruction offsets of original code, including code length, to
+  // offsets of rewritten code
+  offsets: Vec<u32>,
+}
+
+impl ExpandedCode {
+  /// Gets the rewritten bytecode offset of an instruction, or code length,
+  /// from the original code.
+  pub(crate) fn offset(&self, offset: u32) -> u32 {
+    self.offsets[offset as usize]
+  }
+}
+
+/// Rewrites jumps whose distance does not fit in 2 bytes, `far_jumps` maps
+/// such jumps' offsets to their target since their operands can't hold it.
+///
+/// `goto` and `jsr` are replaced by `goto_w` and `jsr_w`, conditional branches
+/// have no wide form so they are inverted to jump over a `goto_w` to the
+/// original target. Expanding a jump moves following code, which may push
+/// other jumps out of range, hence the layout is computed until no more jump
+/// needs to be expanded.
+pub(crate) fn expand_jumps(code: &[u8], far_jumps: &HashMap<u32, u32>) -> KapiResult<ExpandedCode> {
+  let instructions = Instructions::new(code).collect::<KapiResult<Vec<_>>>()?;
+  let mut targets = Vec::with_capacity(instructions.len());
+
+  for instruction in &instructions {
+    if let Some(target) = far_jumps.get(&instruction.offset) {
+      targets.push(vec![*target]);
+    } else {
+      targets.push(instruction.jump_targets(code)?);
+    }
+
+    if let Some(target) = targets
+      .last()
+      .unwrap()
+      .iter()
+      .find(|target| **target as usize > code.len())
+    {
+      return Err(KapiError::StateError(format!(
+        "Jump at bytecode offset {} targets {target} beyond code length {}",
+        instruction.offset,
+        code.len()
+      )));
+    }
+  }
+
+  let mut expanded = far_jumps.keys().copied().collect::<HashSet<_>>();
+  let mut offsets = vec![0; code.len() + 1];
+
+  loop {
+    let mut pc = 0;
+
+    for instruction in &instructions {
+      offsets[instruction.offset as usize] = pc;
+
+      pc += match instruction.opcode {
+        GOTO | JSR if expanded.contains(&instruction.offset) => 5,
+        IFEQ..=IF_ACMPNE | IFNULL | IFNONNULL if expanded.contains(&instruction.offset) => 8,
+        TABLESWITCH | LOOKUPSWITCH => {
+          let padding =
+            switch_operands_offset(instruction.offset as usize) - instruction.offset as usize - 1;
+
+          instruction.len - padding as u32
+            + (switch_operands_offset(pc as usize) - pc as usize - 1) as u32
+        }
+        _ => instruction.len,
+      };
+    }
+
+    offsets[code.len()] = pc;
+
+    let mut changed = false;
+
+    for (instruction, targets) in instructions.iter().zip(&targets) {
+      if matches!(instruction.opcode, IFEQ..=JSR | IFNULL | IFNONNULL)
+        && !expanded.contains(&instruction.offset)
+      {
+        let relative_offset =
+          offsets[targets[0] as usize] as i64 - offsets[instruction.offset as usize] as i64;
+
+        if relative_offset < i16::MIN as i64 || relative_offset > i16::MAX as i64 {
+          expanded.insert(instruction.offset);
+          changed = true;
+        }
+      }
+    }
+
+    if !changed {
+      break;
+    }
+  }
+
+  let mut expanded_code = ByteVec::with_capacity(offsets[code.len()] as usize);
+  let mut inverted_branches = Vec::new();
+
+  for (instruction, targets) in instructions.iter().zip(&targets) {
+    let offset = instruction.offset as usize;
+    let pc = offsets[offset];
+    let relative_to = |target: u32, from: u32| offsets[target as usize].wrapping_sub(from);
+
+    match instruction.opcode {
+      GOTO | JSR if expanded.contains(&instruction.offset) => {
+        let opcode = if instruction.opcode == GOTO {
+          GOTO_W
+        } else {
+          JSR_W
+        };
+
+        expanded_code
+          .push_u8(opcode)
+          .push_u32(relative_to(targets[0], pc));
+      }
+      IFEQ..=IF_ACMPNE | IFNULL | IFNONNULL if expanded.contains(&instruction.offset) => {
+        inverted_branches.push(pc);
+        expanded_code
+          .push_u8(inverse_branch(instruction.opcode))
+          .push_u16(8)
+          .push_u8(GOTO_W)
+          .push_u32(relative_to(targets[0], pc + 3));
+      }
+      IFEQ..=JSR | IFNULL | IFNONNULL => {
+        expanded_code
+          .push_u8(instruction.opcode)
+          .push_u16(relative_to(targets[0], pc) as u16);
+      }
+      GOTO_W | JSR_W => {
+        expanded_code
+          .push_u8(instruction.opcode)
+          .push_u32(relative_to(targets[0], pc));
+      }
+      TABLESWITCH | LOOKUPSWITCH => {
+        let operands = switch_operands_offset(offset);
+
+        expanded_code.push_u8(instruction.opcode);
+
+        while expanded_code.len() < switch_operands_offset(pc as usize) {
+          expanded_code.push_u8(0);
+        }
+
+        expanded_code.push_u32(relative_to(targets[0], pc));
+
+        if instruction.opcode == TABLESWITCH {
+          expanded_code.push_u8s(&code[operands + 4..operands + 12]);
+
+          for target in &targets[1..] {
+            expanded_code.push_u32(relative_to(*target, pc));
+          }
+        } else {
+          expanded_code.push_u8s(&code[operands + 4..operands + 8]);
+
+          for (i, target) in targets[1..].iter().enumerate() {
+            expanded_code
+              .push_u8s(&code[operands + 8 + 8 * i..operands + 12 + 8 * i])
+              .push_u32(relative_to(*target, pc));
+          }
+        }
+      }
+      _ => {
+        expanded_code.push_u8s(&code[offset..offset + instruction.len as usize]);
+      }
+    }
+  }
+
+  Ok(ExpandedCode {
+    code: expanded_code,
+    inverted_branches,
+    offsets,
+  })
+}
+
+/// Gets the offset of the first operand of switch instructions, which is
+/// aligned to 4 bytes from the start of the code.
+const fn switch_operands_offset(offset: usize) -> usize {
+  (offset + 4) & !3
+}
+
+fn reader_at(code: &[u8], at: usize) -> KapiResult<ByteReader<'_>> {
+  let mut reader = ByteReader::new(code);
+
+  reader.set_offset(at)?;
+
+  Ok(reader)
+}
+
+fn read_u8(code: &[u8], at: usize) -> KapiResult<u8> {
+  reader_at(code, at)?.read_u8()
+}
+
+fn read_u16(code: &[u8], at: usize) -> KapiResult<u16> {
+  reader_at(code, at)?.read_u16()
+}
+
+fn read_i32(code: &[u8], at: usize) -> KapiResult<i32> {
+  reader_at(code, at)?.read_i32()
+}
+
+/// Gets the constant at given index, which must hold a constant.
+fn constant_at(cp: &ConstantPool, index: u16) -> KapiResult<&Constant> {
+  cp.get(index)
+    .ok_or_else(|| KapiError::ConstantIndexOutOfBounds {
+      index,
+      len: cp.constant_pool_count(),
+    })
+}
+
+/// Resolves the descriptor of a field, method or invokedynamic reference.
+fn ref_descriptor(cp: &ConstantPool, index: u16) -> KapiResult<&str> {
+  let unexpected =
+    |index: u16, expected: &'static str, found: &Constant| KapiError::UnexpectedConstant {
+      index,
+      expected,
+      found: found.tag().name(),
+    };
+  let name_and_type_index = match constant_at(cp, index)? {
+    Constant::FieldRef(_, name_and_type_index)
+    | Constant::MethodRef(_, name_and_type_index)
+    | Constant::InterfaceMethodRef(_, name_and_type_index)
+    | Constant::InvokeDynamic(_, name_and_type_index) => *name_and_type_index,
+    constant => return Err(unexpected(index, "member reference", constant)),
+  };
+  let descriptor_index = match constant_at(cp, name_and_type_index)? {
+    Constant::NameAndType(_, descriptor_index) => *descriptor_index,
+    constant => return Err(unexpected(name_and_type_index, "NameAndType", constant)),
+  };
+
+  match constant_at(cp, descriptor_index)? {
+    Constant::Utf8(descriptor) => Ok(descriptor),
+    constant => Err(unexpected(descriptor_index, "Utf8", constant)),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+
+  use crate::{
+    constant::ConstantPool,
+    error::KapiError,
+    instruction::{
+      expand_jumps,
+      instruction_at,
+      ref_descriptor,
+      switch_operands_offset,
+      Instructions,
+    },
+    opcodes::*,
+    types::Type,
+  };
+
+  /// Encodes a representative instance of given opcode at bytecode offset
+  /// `pc`, branches jump to themselves and switches have a single case.
+  fn encode(opcode: u8, pc: usize) -> Vec<u8> {
+    let mut bytes = vec![opcode];
+
+    match opcode {
+      BIPUSH | LDC | ILOAD..=ALOAD | ISTORE..=ASTORE | RET | NEWARRAY => bytes.push(1),
+      SIPUSH | LDC_W | LDC2_W | IINC | GETSTATIC..=INVOKESTATIC | NEW | ANEWARRAY => {
+        bytes.extend([0, 1])
+      }
+      IFEQ..=JSR | IFNULL | IFNONNULL => bytes.extend([0, 0]),
+      INVOKEINTERFACE => bytes.extend([0, 1, 1, 0]),
+      INVOKEDYNAMIC => bytes.extend([0, 1, 0, 0]),
+      CHECKCAST | INSTANCEOF => bytes.extend([0, 1]),
+      MULTIANEWARRAY => bytes.extend([0, 1, 2]),
+      GOTO_W | JSR_W => bytes.extend([0, 0, 0, 0]),
+      WIDE => bytes.extend([ILOAD, 1, 0]),
+      TABLESWITCH | LOOKUPSWITCH => {
+        bytes.resize(switch_operands_offset(pc) - pc, 0);
+        // Default and the only case jump back to the switch itself
+        bytes.extend([0; 16]);
+
+        if opcode == LOOKUPSWITCH {
+          // npairs
+          bytes[switch_operands_offset(pc) - pc + 7] = 1;
+        }
+      }
+      _ => {}
+    }
+
+    bytes
+  }
+
+  /// Instruction lengths in bytes as listed in JVMS 6.5, indexed by opcode.
+  /// Switches are variable length and marked as 0, wide is listed by its
+  /// iload form.
+  #[rustfmt::skip]
+  const JVMS_LENGTHS: [u32; 202] = [
+    // 0x00
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x10
+    2, 3, 2, 3, 3, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1,
+    // 0x20
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x30
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1,
+    // 0x40
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x50
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x60
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x70
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x80
+    1, 1, 1, 1, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    // 0x90
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 3, 3, 3, 3, 3, 3, 3,
+    // 0xA0
+    3, 3, 3, 3, 3, 3, 3, 3, 3, 2, 0, 0, 1, 1, 1, 1,
+    // 0xB0
+    1, 1, 3, 3, 3, 3, 3, 3, 3, 5, 5, 3, 2, 3, 1, 1,
+    // 0xC0
+    3, 3, 1, 1, 4, 4, 3, 3, 5, 5,
+  ];
+
+  #[test]
+  fn test_opcode_round_trip() {
+    let mut code = Vec::new();
+    let mut expected = Vec::new();
+
+    for opcode in NOP..=JSR_W {
+      let pc = code.len();
+      let len = match opcode {
+        // Padding to a 4-byte boundary, then default, low and high, and a
+        // single jump offset; or default, npairs and a single pair
+        TABLESWITCH | LOOKUPSWITCH => 1 + (3 - pc as u32 % 4) + 16,
+        _ => JVMS_LENGTHS[opcode as usize],
+      };
+      let bytes = encode(opcode, pc);
+
+      assert_eq!(bytes.len() as u32, len, "Opcode {opcode}");
+
+      expected.push((pc as u32, opcode, len));
+      code.extend(bytes);
+    }
+
+    // Wide iinc has its own length
+    expected.push((code.len() as u32, WIDE, 6));
+    code.extend([WIDE, IINC, 0, 1, 0xFF, 0xFF]);
+
+    let decoded = Instructions::new(&code)
+      .map(|instruction| {
+        instruction.map(|instruction| (instruction.offset, instruction.opcode, instruction.len))
+      })
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+
+    assert_eq!(decoded, expected);
+    assert_eq!(*expand_jumps(&code, &HashMap::new()).unwrap().code, code);
+  }
+
+  #[test]
+  fn test_ref_descriptor_errors() {
+    let mut cp = ConstantPool::default();
+    let field = cp.put_field_ref("Main", "value", "J").unwrap();
+    let long = cp.put_long(0).unwrap();
+    let utf8 = cp.put_utf8("Main").unwrap();
+    let len = cp.constant_pool_count();
+
+    assert_eq!(ref_descriptor(&cp, field), Ok("J"));
+
+    for index in [0, long + 1, len] {
+      assert_eq!(
+        ref_descriptor(&cp, index),
+        Err(KapiError::ConstantIndexOutOfBounds { index, len })
+      );
+    }
+
+    let error = ref_descriptor(&cp, utf8).unwrap_err();
+
+    assert_eq!(
+      error,
+      KapiError::UnexpectedConstant {
+        index: utf8,
+        expected: "member reference",
+        found: "Utf8",
+      }
+    );
+    assert!(error.is_state_error());
+  }
+
+  #[test]
+  fn test_wide_jump_operands() {
+    // goto_w jumping back to the nop before it, then a jsr_w forward to the
+    // trailing return
+    let code = [
+      NOP, GOTO_W, 0xFF, 0xFF, 0xFF, 0xFF, JSR_W, 0, 0, 0, 5, RETURN,
+    ];
+    let instructions = Instructions::new(&code)
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+
+    assert_eq!(
+      instructions
+        .iter()
+        .map(|instruction| instruction.offset)
+        .collect::<Vec<_>>(),
+      [0, 1, 6, 11]
+    );
+    assert_eq!(instructions[1].jump_targets(&code), Ok(vec![0]));
+    assert_eq!(instructions[2].jump_targets(&code), Ok(vec![11]));
+
+    // Truncated operand never reads past code length
+    assert!(matches!(
+      instruction_at(&code[..4], 1),
+      Err(KapiError::StateError(_))
+    ));
+  }
+
+  #[test]
+  fn test_operator_stack_effects() {
+    use Type::*;
+
+    let cp = ConstantPool::default();
+    let numeric = [Int, Long, Float, Double];
+    // Binary and unary operators are ordered as I, L, F, D for each operation
+    let mut operators = (IADD..=DREM)
+      .map(|opcode| {
+        let typ = numeric[((opcode - IADD) % 4) as usize].clone();
+
+        (opcode, vec![typ.clone(), typ.clone()], typ)
+      })
+      .chain((INEG..=DNEG).map(|opcode| {
+        let typ = numeric[(opcode - INEG) as usize].clone();
+
+        (opcode, vec![typ.clone()], typ)
+      }))
+      .collect::<Vec<_>>();
+
+    operators.extend([
+      // Shifts pop an int shift distance over the value
+      (ISHL, vec![Int, Int], Int),
+      (LSHL, vec![Long, Int], Long),
+      (ISHR, vec![Int, Int], Int),
+      (LSHR, vec![Long, Int], Long),
+      (IUSHR, vec![Int, Int], Int),
+      (LUSHR, vec![Long, Int], Long),
+      (IAND, vec![Int, Int], Int),
+      (LAND, vec![Long, Long], Long),
+      (IOR, vec![Int, Int], Int),
+      (LOR, vec![Long, Long], Long),
+      (IXOR, vec![Int, Int], Int),
+      (LXOR, vec![Long, Long], Long),
+      (I2L, vec![Int], Long),
+      (I2F, vec![Int], Float),
+      (I2D, vec![Int], Double),
+      (L2I, vec![Long], Int),
+      (L2F, vec![Long], Float),
+      (L2D, vec![Long], Double),
+      (F2I, vec![Float], Int),
+      (F2L, vec![Float], Long),
+      (F2D, vec![Float], Double),
+      (D2I, vec![Double], Int),
+      (D2L, vec![Double], Long),
+      (D2F, vec![Double], Float),
+      (I2B, vec![Int], Int),
+      (I2C, vec![Int], Int),
+      (I2S, vec![Int], Int),
+      (LCMP, vec![Long, Long], Int),
+      (FCMPL, vec![Float, Float], Int),
+      (FCMPG, vec![Float, Float], Int),
+      (DCMPL, vec![Double, Double], Int),
+      (DCMPG, vec![Double, Double], Int),
+    ]);
+
+    // Every operator except iinc, which doesn't touch operand stack
+    assert_eq!(operators.len(), (IADD..=DCMPG).count() - 1);
+
+    for (opcode, operands, result) in operators {
+      let code = [opcode];
+      let expected =
+        result.size() as i32 - operands.iter().map(|typ| typ.size() as i32).sum::<i32>();
+
+      assert_eq!(
+        instruction_at(&code, 0)
+          .unwrap()
+          .stack_size_delta(&code, &cp),
+        Ok(expected),
+        "opcode {opcode}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_constant_stack_effects() {
+    use Type::*;
+
+    let cp = ConstantPool::default();
+    let mut constants = (ICONST_M1..=ICONST_5)
+      .map(|opcode| (opcode, Int))
+      .chain((LCONST_0..=LCONST_1).map(|opcode| (opcode, Long)))
+      .chain((FCONST_0..=FCONST_2).map(|opcode| (opcode, Float)))
+      .chain((DCONST_0..=DCONST_1).map(|opcode| (opcode, Double)))
+      .collect::<Vec<_>>();
+
+    constants.extend([
+      (ACONST_NULL, Object(String::from("java/lang/Object"))),
+      (BIPUSH, Int),
+      (SIPUSH, Int),
+      // Loadable constants of ldc and ldc_w are all category 1
+      (LDC, Float),
+      (LDC_W, Object(String::from("java/lang/String"))),
+      (LDC2_W, Double),
+    ]);
+
+    assert_eq!(constants.len(), (ACONST_NULL..=LDC2_W).count());
+
+    for (opcode, result) in constants {
+      let code = encode(opcode, 0);
+
+      assert_eq!(
+        instruction_at(&code, 0)
+          .unwrap()
+          .stack_size_delta(&code, &cp),
+        Ok(result.size() as i32),
+        "opcode {opcode}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_unknown_opcodes_rejected() {
+    for opcode in JSR_W + 1..=u8::MAX {
+      assert!(matches!(
+        instruction_at(&[opcode], 0),
+        Err(KapiError::StateError(_))
+      ));
+    }
+  }
+}
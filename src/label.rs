@@ -1,12 +1,19 @@
 use bitflags::bitflags;
 
-use crate::byte_vec::{
-  ByteVec,
-  ByteVector,
+use crate::{
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    PatchHandle,
+  },
+  error::{
+    KapiError,
+    KapiResult,
+  },
 };
 
 bitflags! {
-  #[derive(Debug, Clone)]
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
   pub(crate) struct LabelFlag: u8 {
     const DebugOnly = 1;
     const JumpTarget = 2;
@@ -15,12 +22,6 @@ bitflags! {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum FowardRefType {
-  Short,
-  Wide,
-}
-
 impl Default for LabelFlag {
   fn default() -> Self {
     Self::empty()
@@ -30,12 +31,8 @@ impl Default for LabelFlag {
 #[derive(Debug, Default, Clone)]
 pub struct Label {
   flags: LabelFlag,
-  line_numbers: Vec<u16>,
   bytecode_offset: u32,
-  foward_reference: Vec<(u32, FowardRefType, u32)>,
-  input_stack_size: u16,
-  output_stack_size: u16,
-  output_stack_max: u16,
+  foward_reference: Vec<(u32, PatchHandle)>,
 }
 
 impl Label {
@@ -43,6 +40,11 @@ impl Label {
     Self::default()
   }
 
+  /// Whether the label has been visited, hence its bytecode offset is known.
+  pub fn is_resolved(&self) -> bool {
+    self.flags.contains(LabelFlag::Resolved)
+  }
+
   pub(crate) fn offset(&self) -> u32 {
     if !self.flags.contains(LabelFlag::Resolved) {
       panic!("Label offset position has not been resolved yet")
@@ -55,82 +57,155 @@ impl Label {
     &self.flags
   }
 
-  pub(crate) fn add_line_number(&mut self, line_number: u16) {
-    self.line_numbers.push(line_number);
-  }
-
-  pub(crate) fn put(&mut self, code: &mut ByteVec, source_inst_bytecode_offset: u32, wide_ref: bool) {
+  pub(crate) fn put(
+    &mut self,
+    code: &mut ByteVec,
+    source_inst_bytecode_offset: u32,
+    wide_ref: bool,
+  ) {
     if !self.flags.contains(LabelFlag::Resolved) {
+      let ref_handle = code.reserve_slot(if wide_ref { 4 } else { 2 });
+
+      self.add_foward_ref(source_inst_bytecode_offset, ref_handle);
+    } else {
       if wide_ref {
-        self.add_foward_ref(
-          source_inst_bytecode_offset,
-          FowardRefType::Wide,
-          code.len() as u32,
+        code.push_u32(
+          self
+            .bytecode_offset
+            .wrapping_sub(source_inst_bytecode_offset),
         );
-        code.push_u32(0);
       } else {
-        self.add_foward_ref(
-          source_inst_bytecode_offset,
-          FowardRefType::Short,
-          code.len() as u32,
+        code.push_u16(
+          self
+            .bytecode_offset
+            .wrapping_sub(source_inst_bytecode_offset) as u16,
         );
-        code.push_u16(0);
-      }
-    } else {
-      if wide_ref {
-        code
-          .push_u32(self.bytecode_offset.wrapping_sub(source_inst_bytecode_offset));
-      } else {
-        code
-          .push_u16(self.bytecode_offset.wrapping_sub(source_inst_bytecode_offset) as u16);
       }
     }
   }
 
-  fn add_foward_ref(
-    &mut self,
-    source_inst_bytecode_offset: u32,
-    ref_type: FowardRefType,
-    ref_handle: u32,
-  ) {
-    self.foward_reference.push((source_inst_bytecode_offset, ref_type, ref_handle));
+  fn add_foward_ref(&mut self, source_inst_bytecode_offset: u32, ref_handle: PatchHandle) {
+    self
+      .foward_reference
+      .push((source_inst_bytecode_offset, ref_handle));
+  }
+
+  /// Gets bytecode offsets of instructions referencing the label before it
+  /// was resolved.
+  pub(crate) fn forward_sources(&self) -> impl Iterator<Item = u32> + '_ {
+    self
+      .foward_reference
+      .iter()
+      .map(|(source_inst_bytecode_offset, ..)| *source_inst_bytecode_offset)
   }
 
-  pub(crate) fn resolve(&mut self, code: &mut ByteVec, bytecode_offset: u32) {
+  /// Resolves label at given bytecode offset and patches forward references
+  /// to it. Returns bytecode offsets of short forward references whose
+  /// distance doesn't fit in 2 bytes, these are left unpatched. A label can
+  /// only be resolved once.
+  pub(crate) fn resolve(
+    &mut self,
+    code: &mut ByteVec,
+    bytecode_offset: u32,
+  ) -> KapiResult<Vec<u32>> {
+    if self.is_resolved() {
+      return Err(KapiError::StateError(format!(
+        "Label is already visited at bytecode offset {}",
+        self.bytecode_offset
+      )));
+    }
+
     self.flags |= LabelFlag::Resolved;
     self.bytecode_offset = bytecode_offset;
 
-    for (source_inst_bytecode_offset, ref_type, ref_handle) in &self.foward_reference {
-      let relative_offset = bytecode_offset.wrapping_sub(*source_inst_bytecode_offset);
-
-      if ((relative_offset as i32) < (i16::MIN as i32) || (relative_offset as i32 > (i16::MAX as i32))) && *ref_type == FowardRefType::Short {
-        // reserves 2 more bytes for offset to store when we previously
-        // only allocated 2 bytes
-        for _ in 0..2 {
-          code.insert(*ref_handle as usize, 0);
-        }
-
-        // TODO: This algorithm requires the remaining jump insts to check
-        // if their offset interval intersects with current foward reference,
-        // if so, intersected offset intervals must compute
-      } 
-
-      match ref_type {
-        FowardRefType::Short => {
-          let relative_offset_bytes = (relative_offset as u16).to_be_bytes();
-
-          for i in 0..2 {
-            code[*ref_handle as usize + i] = relative_offset_bytes[i]; 
-          }
-        }
-        FowardRefType::Wide => {
-          let relative_offset_bytes = (relative_offset as u32).to_be_bytes();
-
-          for i in 0..4 {
-            code[*ref_handle as usize + i] = relative_offset_bytes[i]; 
-          }
-        }
-    }
+    let mut far_references = Vec::new();
+
+    for (source_inst_bytecode_offset, ref_handle) in &self.foward_reference {
+      let relative_offset = bytecode_offset
+        .checked_sub(*source_inst_bytecode_offset)
+        .ok_or_else(|| {
+          KapiError::StateError(format!(
+            "Forward reference from bytecode offset {source_inst_bytecode_offset} lies after its label at {bytecode_offset}"
+          ))
+        })?;
+
+      if ref_handle.len() == 4 {
+        code.patch(*ref_handle, &relative_offset.to_be_bytes())?;
+      } else if relative_offset > i16::MAX as u32 {
+        far_references.push(*source_inst_bytecode_offset);
+      } else {
+        code.patch(*ref_handle, &(relative_offset as u16).to_be_bytes())?;
+      }
     }
+
+    Ok(far_references)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    byte_vec::ByteVector,
+    error::KapiError,
+    label::Label,
+  };
+
+  #[test]
+  fn test_resolve_patches_forward_references() {
+    let mut label = Label::new();
+    let mut code = vec![0xA7];
+    let short_ref = code.reserve_slot(2);
+
+    code.push_u8(0xC8);
+
+    let wide_ref = code.reserve_slot(4);
+
+    label.add_foward_ref(0, short_ref);
+    label.add_foward_ref(3, wide_ref);
+
+    assert_eq!(label.resolve(&mut code, 8), Ok(Vec::new()));
+    assert_eq!(code, vec![0xA7, 0, 8, 0xC8, 0, 0, 0, 5]);
+  }
+
+  #[test]
+  fn test_resolve_corrupted_forward_references() {
+    let mut label = Label::new();
+    let mut code = vec![0xA7];
+    let short_ref = code.reserve_slot(2);
+
+    // Operand is truncated
+    code.truncate(2);
+    label.add_foward_ref(0, short_ref);
+
+    assert!(matches!(
+      label.resolve(&mut code, 2),
+      Err(KapiError::StateError(_))
+    ));
+
+    let mut label = Label::new();
+
+    // Source instruction is placed after label
+    label.add_foward_ref(4, short_ref);
+
+    assert!(matches!(
+      label.resolve(&mut code, 2),
+      Err(KapiError::StateError(_))
+    ));
+  }
+
+  #[test]
+  fn test_resolve_twice() {
+    let mut label = Label::new();
+    let mut code = vec![0xA7, 0, 0];
+
+    assert!(!label.is_resolved());
+    assert_eq!(label.resolve(&mut code, 0), Ok(Vec::new()));
+    assert!(label.is_resolved());
+    assert_eq!(
+      label.resolve(&mut code, 3),
+      Err(KapiError::StateError(String::from(
+        "Label is already visited at bytecode offset 0"
+      )))
+    );
   }
 }
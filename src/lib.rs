@@ -2,13 +2,157 @@
 
 // no_std placeholder here
 pub mod access_flag;
+pub mod annotation;
 mod attrs;
+pub mod byte_reader;
 mod byte_vec;
 pub mod class;
-mod frame;
+pub mod constant;
+pub mod error;
+pub mod extensions;
+pub mod field;
+pub mod handle;
+mod instruction;
 pub mod label;
 pub mod method;
-mod constant;
-pub mod types;
+pub mod module;
 pub mod opcodes;
-mod stack_map;
+pub mod record;
+pub mod signature;
+#[doc(hidden)]
+pub mod testkit;
+pub mod types;
+
+#[cfg(test)]
+mod test {
+  use std::{
+    error::Error,
+    fmt::Debug,
+    hash::Hash,
+  };
+
+  use crate::{
+    access_flag::*,
+    class::{
+      ClassVisitor,
+      ClassWriter,
+      ComputeOption,
+      JavaVersion,
+    },
+    error::KapiError,
+    extensions::Extensions,
+    label::Label,
+    method::MethodWriter,
+    opcodes,
+  };
+
+  fn assert_send_sync<T: Send + Sync>() {}
+
+  fn assert_value_type<T: Debug + Clone + Default + PartialEq + Eq + Hash>() {}
+
+  fn assert_version_type<T: Debug + Clone + Copy + PartialEq + Eq + Hash>() {}
+
+  fn assert_debug<T: Debug>() {}
+
+  #[test]
+  fn test_public_type_traits() {
+    assert_value_type::<ClassAccessFlag>();
+    assert_value_type::<FieldAccessFlag>();
+    assert_value_type::<MethodAccessFlag>();
+    assert_value_type::<NestedClassAccessFlag>();
+    assert_value_type::<ParameterAccessFlag>();
+    assert_value_type::<ModuleAccessFlag>();
+    assert_value_type::<RequiresAccessFlag>();
+    assert_value_type::<ExportsAccessFlag>();
+    assert_value_type::<OpensAccessFlag>();
+    assert_version_type::<JavaVersion>();
+    assert_value_type::<ComputeOption>();
+
+    assert_send_sync::<ClassAccessFlag>();
+    assert_send_sync::<JavaVersion>();
+    assert_send_sync::<ComputeOption>();
+    assert_send_sync::<Label>();
+    assert_send_sync::<KapiError>();
+
+    assert_debug::<ClassWriter>();
+    assert_debug::<MethodWriter>();
+    assert_debug::<Label>();
+    assert_debug::<Extensions>();
+
+    fn assert_error<T: Error + Clone + PartialEq>() {}
+
+    assert_error::<KapiError>();
+  }
+
+  #[test]
+  fn test_structured_errors() {
+    let mut writer = ClassWriter::new().compute(ComputeOption::Maxs);
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+
+    let mw = writer
+      .visit_method(MethodAccessFlag::Static, "run", "()V", None, &[])
+      .unwrap()
+      .unwrap();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::POP).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    writer.visit_end();
+
+    let error = writer.to_bytes().unwrap_err();
+    let underflow = KapiError::StackUnderflow {
+      offset: 0,
+      opcode: opcodes::POP,
+      stack_size: 0,
+      delta: -1,
+    };
+
+    assert!(matches!(
+      &error,
+      KapiError::InMethod { name, descriptor, .. } if name == "run" && descriptor == "()V"
+    ));
+    assert_eq!(error.root_cause(), &underflow);
+    assert!(error.is_state_error());
+    assert!(!error.is_arg_error());
+    assert_eq!(error.source().unwrap().to_string(), underflow.to_string());
+    assert_eq!(
+      error.to_string(),
+      "Method run()V: Operand stack underflow at bytecode offset 0, instruction 87 changes stack size 0 by -1"
+    );
+  }
+
+  #[test]
+  fn test_java_version_eq() {
+    assert_eq!(
+      JavaVersion::V17,
+      JavaVersion::Custom {
+        minor: 0,
+        major: 61
+      }
+    );
+    assert_eq!(
+      JavaVersion::V1_1,
+      JavaVersion::Custom {
+        minor: 3,
+        major: 45
+      }
+    );
+    assert_ne!(
+      JavaVersion::V17,
+      JavaVersion::Custom {
+        minor: 65535,
+        major: 61
+      }
+    );
+  }
+}
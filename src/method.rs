@@ -1,11 +1,26 @@
 use std::{
   cell::RefCell,
-  collections::HashMap,
+  collections::{
+    HashMap,
+    HashSet,
+  },
   rc::Rc,
 };
 
 use crate::{
-  access_flag::MethodAccessFlag,
+  access_flag::{
+    MethodAccessFlag,
+    ParameterAccessFlag,
+  },
+  annotation::{
+    compute_type_annotations_size,
+    put_type_annotations,
+    AnnotationVisitor,
+    LocalVariableTarget,
+    TargetInfo,
+    TypeAnnotationWriter,
+    TypePathStep,
+  },
   attrs,
   byte_vec::{
     ByteVec,
@@ -13,252 +28,3714 @@ use crate::{
     SizeComputable,
     ToBytes,
   },
+  class::ComputeOption,
+  constant::ConstantPool,
+  error::{
+    GenerationWarning,
+    InstructionKind,
+    KapiError,
+    KapiResult,
+    WarningSink,
+  },
+  extensions::Extensions,
+  handle::Handle,
+  instruction::{
+    expand_jumps,
+    instruction_at,
+    inverse_branch,
+    is_jump,
+    takes_no_operands,
+    ExpandedCode,
+    Instructions,
+  },
   label::{
     Label,
     LabelFlag,
   },
   opcodes,
-  constant::ConstantPool,
-  types::compute_method_descriptor_sizes,
+  types::{
+    compute_method_descriptor_sizes,
+    parse_field_descriptor,
+    parse_method_descriptor,
+    Type,
+  },
 };
 
+/// Code length in bytes above which HotSpot refuses to JIT compile a method,
+/// see `-XX:-DontCompileHugeMethods`.
+pub const DEFAULT_HUGE_METHOD_THRESHOLD: u32 = 8000;
+
+/// Operand of a `ldc` family instruction, see JVMS 6.5.ldc.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LdcConstant {
+  Int(i32),
+  Float(f32),
+  Long(i64),
+  Double(f64),
+  String(String),
+  /// Internal name, or a descriptor for array types.
+  Class(String),
+  /// Method descriptor.
+  MethodType(String),
+  MethodHandle(Handle),
+  Dynamic(ConstantDynamic),
+}
+
+impl LdcConstant {
+  /// Whether the constant takes two operand stack words, hence is loaded by
+  /// `ldc2_w`.
+  pub fn is_wide(&self) -> bool {
+    match self {
+      LdcConstant::Long(_) | LdcConstant::Double(_) => true,
+      LdcConstant::Dynamic(dynamic) => matches!(dynamic.descriptor(), "J" | "D"),
+      _ => false,
+    }
+  }
+}
+
+/// A dynamically-computed constant, whose value is produced by invoking
+/// its bootstrap method on first resolution, see JVMS 4.4.10.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstantDynamic {
+  name: String,
+  descriptor: String,
+  bootstrap_method: Handle,
+  bootstrap_arguments: Vec<LdcConstant>,
+}
+
+impl ConstantDynamic {
+  /// Creates a dynamic constant of type `descriptor`, which must be a field
+  /// descriptor.
+  pub fn new(
+    name: &str,
+    descriptor: &str,
+    bootstrap_method: Handle,
+    bootstrap_arguments: Vec<LdcConstant>,
+  ) -> KapiResult<Self> {
+    parse_field_descriptor(descriptor)?;
+
+    Ok(Self {
+      name: name.to_owned(),
+      descriptor: descriptor.to_owned(),
+      bootstrap_method,
+      bootstrap_arguments,
+    })
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn descriptor(&self) -> &str {
+    &self.descriptor
+  }
+
+  pub fn bootstrap_method(&self) -> &Handle {
+    &self.bootstrap_method
+  }
+
+  pub fn bootstrap_arguments(&self) -> &[LdcConstant] {
+    &self.bootstrap_arguments
+  }
+}
+
 pub trait MethodVisitor {
   fn inner(&mut self) -> Option<&mut dyn MethodVisitor> {
     None
   }
 
+  /// Visits a formal parameter for MethodParameters attribute, parameters
+  /// are recorded in visiting order. Unnamed parameters have no `name`.
+  fn visit_parameter(&mut self, name: Option<&str>, access: ParameterAccessFlag) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_parameter(name, access)
+    } else {
+      Ok(())
+    }
+  }
+
   fn visit_code(&mut self) {
     if let Some(inner) = self.inner() {
       inner.visit_code();
     }
   }
 
-  fn visit_inst(&mut self, inst: u8) {
+  /// Gets the length of code emitted so far, in bytes. Far jumps may still
+  /// grow the code when it's written.
+  fn current_code_len(&mut self) -> u32 {
+    if let Some(inner) = self.inner() {
+      inner.current_code_len()
+    } else {
+      0
+    }
+  }
+
+  /// Visits an instruction without operands, e.g. `iadd` or `return`.
+  /// Opcodes taking operands must be visited through their dedicated
+  /// methods and are rejected.
+  fn visit_simple_inst(&mut self, opcode: u8) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_simple_inst(opcode)
+    } else {
+      Ok(())
+    }
+  }
+
+  #[deprecated(note = "Use `visit_simple_inst` instead")]
+  fn visit_inst(&mut self, inst: u8) -> KapiResult<()> {
+    self.visit_simple_inst(inst)
+  }
+
+  /// Visits an instruction taking an int operand, which is one of `bipush`,
+  /// `sipush` or `newarray`. The operand of `newarray` is an array type code
+  /// such as [opcodes::T_INT].
+  fn visit_int_inst(&mut self, opcode: u8, operand: i32) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_int_inst(opcode, operand)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn visit_label(&mut self, label: &mut Label) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_label(label)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an instruction branching to `label`, `opcode` must be a jump
+  /// instruction.
+  fn visit_jump_inst(&mut self, opcode: u8, label: &mut Label) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_jump_inst(opcode, label)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an instruction loading or storing a field, which is one of
+  /// `getstatic`, `putstatic`, `getfield` or `putfield`.
+  fn visit_field_inst(
+    &mut self,
+    opcode: u8,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+  ) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_field_inst(opcode, owner, name, descriptor)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an instruction invoking a method, which is one of
+  /// `invokevirtual`, `invokespecial`, `invokestatic` or `invokeinterface`.
+  /// `is_interface` tells whether `owner` is an interface, which
+  /// `invokeinterface` requires.
+  fn visit_method_inst(
+    &mut self,
+    opcode: u8,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+    is_interface: bool,
+  ) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_method_inst(opcode, owner, name, descriptor, is_interface)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an instruction taking a class as operand, which is one of `new`,
+  /// `anewarray`, `checkcast` or `instanceof`. `type_name` is an internal
+  /// name, or a descriptor for array types, see JVMS 4.4.1.
+  fn visit_type_inst(&mut self, opcode: u8, type_name: &str) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_type_inst(opcode, type_name)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an instruction loading a constant, picking `ldc` if the index of
+  /// constant fits in a byte, `ldc_w` otherwise, and `ldc2_w` for long and
+  /// double constants.
+  fn visit_ldc_inst(&mut self, constant: LdcConstant) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_ldc_inst(constant)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an instruction loading or storing a local variable, which is one
+  /// of `iload`, `lload`, `fload`, `dload`, `aload`, their store
+  /// counterparts or `ret`. The shortest form is picked for `index`, e.g.
+  /// `iload_0` or `wide iload`.
+  fn visit_var_inst(&mut self, opcode: u8, index: u16) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_var_inst(opcode, index)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an `iinc` instruction, the `wide` form is used if the local
+  /// variable index or the increment doesn't fit in a byte.
+  fn visit_iinc_inst(&mut self, index: u16, increment: i16) {
+    if let Some(inner) = self.inner() {
+      inner.visit_iinc_inst(index, increment);
+    }
+  }
+
+  /// Boxes the primitive value on top of operand stack into its wrapper
+  /// class, e.g. `int` into `Integer` via `valueOf`.
+  fn visit_box(&mut self, typ: &Type) -> KapiResult<()> {
+    let Some((wrapper, _)) = typ.wrapper() else {
+      return Err(KapiError::ArgError(format!(
+        "Type {typ:?} is not a primitive type and cannot be boxed"
+      )));
+    };
+
+    self.visit_method_inst(
+      opcodes::INVOKESTATIC,
+      wrapper,
+      "valueOf",
+      &format!("({})L{wrapper};", typ.descriptor()),
+      false,
+    )
+  }
+
+  /// Unboxes the wrapper instance on top of operand stack into given
+  /// primitive type, e.g. `Integer` into `int` via `intValue`. The instance
+  /// must already be of the wrapper class.
+  fn visit_unbox(&mut self, typ: &Type) -> KapiResult<()> {
+    let Some((wrapper, unbox_method)) = typ.wrapper() else {
+      return Err(KapiError::ArgError(format!(
+        "Type {typ:?} is not a primitive type and cannot be unboxed"
+      )));
+    };
+
+    self.visit_method_inst(
+      opcodes::INVOKEVIRTUAL,
+      wrapper,
+      unbox_method,
+      &format!("(){}", typ.descriptor()),
+      false,
+    )
+  }
+
+  /// Converts the primitive value on top of operand stack with widening
+  /// primitive conversion, see JLS 5.1.2. Conversions between `byte`,
+  /// `short`, `char` and `int` emit nothing since they share the same
+  /// computational type.
+  fn visit_widening_cast(&mut self, from: &Type, to: &Type) -> KapiResult<()> {
+    let opcode = match (from, to) {
+      (Type::Byte, Type::Short | Type::Int) | (Type::Short | Type::Char, Type::Int) => None,
+      (Type::Byte | Type::Short | Type::Char | Type::Int, Type::Long) => Some(opcodes::I2L),
+      (Type::Byte | Type::Short | Type::Char | Type::Int, Type::Float) => Some(opcodes::I2F),
+      (Type::Byte | Type::Short | Type::Char | Type::Int, Type::Double) => Some(opcodes::I2D),
+      (Type::Long, Type::Float) => Some(opcodes::L2F),
+      (Type::Long, Type::Double) => Some(opcodes::L2D),
+      (Type::Float, Type::Double) => Some(opcodes::F2D),
+      _ => {
+        return Err(KapiError::ArgError(format!(
+          "Conversion from {from:?} to {to:?} is not a widening primitive conversion"
+        )))
+      }
+    };
+
+    if let Some(opcode) = opcode {
+      self.visit_simple_inst(opcode)?;
+    }
+
+    Ok(())
+  }
+
+  /// Converts the primitive value on top of operand stack with narrowing
+  /// primitive conversion, see JLS 5.1.3. Conversions to `byte`, `short` or
+  /// `char` from `long`, `float` or `double` go through `int`.
+  fn visit_narrowing_cast(&mut self, from: &Type, to: &Type) -> KapiResult<()> {
+    let opcodes: &[u8] = match (from, to) {
+      (Type::Short | Type::Char | Type::Int, Type::Byte) => &[opcodes::I2B],
+      (Type::Short | Type::Int, Type::Char) => &[opcodes::I2C],
+      (Type::Char | Type::Int, Type::Short) => &[opcodes::I2S],
+      (Type::Long, Type::Int) => &[opcodes::L2I],
+      (Type::Long, Type::Byte) => &[opcodes::L2I, opcodes::I2B],
+      (Type::Long, Type::Char) => &[opcodes::L2I, opcodes::I2C],
+      (Type::Long, Type::Short) => &[opcodes::L2I, opcodes::I2S],
+      (Type::Float, Type::Int) => &[opcodes::F2I],
+      (Type::Float, Type::Long) => &[opcodes::F2L],
+      (Type::Float, Type::Byte) => &[opcodes::F2I, opcodes::I2B],
+      (Type::Float, Type::Char) => &[opcodes::F2I, opcodes::I2C],
+      (Type::Float, Type::Short) => &[opcodes::F2I, opcodes::I2S],
+      (Type::Double, Type::Int) => &[opcodes::D2I],
+      (Type::Double, Type::Long) => &[opcodes::D2L],
+      (Type::Double, Type::Float) => &[opcodes::D2F],
+      (Type::Double, Type::Byte) => &[opcodes::D2I, opcodes::I2B],
+      (Type::Double, Type::Char) => &[opcodes::D2I, opcodes::I2C],
+      (Type::Double, Type::Short) => &[opcodes::D2I, opcodes::I2S],
+      _ => {
+        return Err(KapiError::ArgError(format!(
+          "Conversion from {from:?} to {to:?} is not a narrowing primitive conversion"
+        )))
+      }
+    };
+
+    for opcode in opcodes {
+      self.visit_simple_inst(*opcode)?;
+    }
+
+    Ok(())
+  }
+
+  fn visit_maxs(&mut self, max_stack: u16, max_locals: u16) {
+    if let Some(inner) = self.inner() {
+      inner.visit_maxs(max_stack, max_locals);
+    }
+  }
+
+  fn visit_table_switch_inst(
+    &mut self,
+    min: i32,
+    max: i32,
+    default: &mut Label,
+    labels: &mut [&mut Label],
+  ) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_table_switch_inst(min, max, default, labels)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn visit_lookup_switch_inst(
+    &mut self,
+    default: &mut Label,
+    pairs: &mut [(i32, &mut Label)],
+  ) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_lookup_switch_inst(default, pairs)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Visits an exception handler at `handler` covering code from `start`
+  /// until `end` exclusively, `exception_type` of [None] catches any
+  /// exception as `finally` blocks do. Labels must be visited beforehand,
+  /// handlers are searched in visiting order, see JVMS 4.7.3.
+  fn visit_try_catch_block(
+    &mut self,
+    start: &Label,
+    end: &Label,
+    handler: &Label,
+    exception_type: Option<&str>,
+  ) -> KapiResult<()> {
+    if let Some(inner) = self.inner() {
+      inner.visit_try_catch_block(start, end, handler, exception_type)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if let Some(inner) = self.inner() {
+      inner.visit_type_annotation(target_info, type_path, descriptor, visible)
+    } else {
+      Ok(None)
+    }
+  }
+
+  /// Visits a type annotation on a local or resource variable, `ranges` are
+  /// (start, end, index) of each live range of the variable, labels must be
+  /// visited beforehand.
+  fn visit_local_variable_annotation(
+    &mut self,
+    target: LocalVariableTarget,
+    ranges: &[(&Label, &Label, u16)],
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
     if let Some(inner) = self.inner() {
-      inner.visit_inst(inst);
+      inner.visit_local_variable_annotation(target, ranges, type_path, descriptor, visible)
+    } else {
+      Ok(None)
     }
   }
 
-  fn visit_label(&mut self, label: &mut Label) {
+  fn visit_line_number(&mut self, line: u16, start: &Label) -> KapiResult<()> {
     if let Some(inner) = self.inner() {
-      inner.visit_label(label);
+      inner.visit_line_number(line, start)
+    } else {
+      Ok(())
     }
   }
 
-  fn visit_jump_inst(&mut self, opcode: u8, label: &mut Label) {
+  fn visit_local_variable(
+    &mut self,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+    start: &Label,
+    end: &Label,
+    index: u16,
+  ) -> KapiResult<()> {
     if let Some(inner) = self.inner() {
-      inner.visit_jump_inst(opcode, label);
+      inner.visit_local_variable(name, descriptor, signature, start, end, index)
+    } else {
+      Ok(())
     }
   }
 }
 
+#[derive(Debug)]
+struct LocalVariable {
+  start_pc: u16,
+  length: u16,
+  name_index: u16,
+  descriptor_index: u16,
+  signature_index: Option<u16>,
+  index: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ExceptionHandler {
+  start_pc: u16,
+  end_pc: u16,
+  handler_pc: u16,
+  // Zero catches any exception
+  catch_type: u16,
+}
+
 #[derive(Debug)]
 pub struct MethodWriter {
   constant_pool: Rc<RefCell<ConstantPool>>,
+  compute: ComputeOption,
   access: MethodAccessFlag,
   name_index: u16,
   descriptor_index: u16,
   signature_index: Option<u16>,
   exception_indicies: Vec<u16>,
+  // Attribute MethodParameters
+  parameters: Vec<(u16, ParameterAccessFlag)>,
   code: ByteVec,
+  arguments_size: u16,
   max_locals: u16,
   max_stacks: u16,
-  // Dynamic computing properties
-  current_locals: u16,
-  current_stacks: u16,
   labels: HashMap<u32, Label>,
+  exception_table: Vec<ExceptionHandler>,
+  // Forward jumps too far away from their targets, (source_offset, target_offset)
+  far_jumps: HashMap<u32, u32>,
+  // Source offsets of forward references to labels not visited yet, once per
+  // reference
+  unresolved_jumps: Vec<u32>,
+  warnings: Option<WarningSink>,
+  huge_method_threshold: u32,
+  // Emits Exceptions, LineNumberTable and LocalVariableTable without entries
+  force_emit_empty: bool,
+  // Debug information, (start_pc, line_number)
+  line_numbers: Vec<(u16, u16)>,
+  local_variables: Vec<LocalVariable>,
+  // Attribute RuntimeVisibleTypeAnnotations
+  visible_type_annotations: Vec<TypeAnnotationWriter>,
+  // Attribute RuntimeInvisibleTypeAnnotations
+  invisible_type_annotations: Vec<TypeAnnotationWriter>,
+  // Attribute RuntimeVisibleTypeAnnotations of Code
+  visible_code_type_annotations: Vec<TypeAnnotationWriter>,
+  // Attribute RuntimeInvisibleTypeAnnotations of Code
+  invisible_code_type_annotations: Vec<TypeAnnotationWriter>,
+  extensions: Extensions,
 }
 
 impl MethodWriter {
+  #[allow(clippy::too_many_arguments)]
   pub(crate) fn new(
     constant_pool: Rc<RefCell<ConstantPool>>,
+    compute: ComputeOption,
+    warnings: Option<WarningSink>,
     access: MethodAccessFlag,
     name: &str,
     descriptor: &str,
     signature: Option<&str>,
     exceptions: &[&str],
-  ) -> Self {
+  ) -> KapiResult<Self> {
+    let (arguments_size, _) =
+      compute_method_descriptor_sizes(descriptor, access.contains(MethodAccessFlag::Static))?;
     let cp = constant_pool.clone();
     let mut cp = cp.borrow_mut();
-    let name_index = cp.put_utf8(name);
-    let descriptor_index = cp.put_utf8(descriptor);
-    let signature_index = signature.map(|signature| cp.put_utf8(signature));
+    let name_index = cp.put_utf8(name)?;
+    let descriptor_index = cp.put_utf8(descriptor)?;
+    let signature_index = signature
+      .map(|signature| {
+        cp.put_utf8(attrs::SIGNATURE)?;
+        cp.put_utf8(signature)
+      })
+      .transpose()?;
     let exception_indicies = exceptions
       .iter()
       .map(|exception| cp.put_class(exception))
-      .collect();
+      .collect::<KapiResult<Vec<_>>>()?;
 
-    let (max_locals, _) =
-      compute_method_descriptor_sizes(descriptor, access.contains(MethodAccessFlag::Static));
+    if !exception_indicies.is_empty() {
+      cp.put_utf8(attrs::EXCEPTIONS)?;
+    }
 
-    Self {
+    Ok(Self {
       constant_pool,
+      compute,
       access,
       name_index,
       descriptor_index,
       signature_index,
       exception_indicies,
+      parameters: Vec::new(),
       code: ByteVec::default(),
-      max_locals,
+      arguments_size,
+      max_locals: arguments_size,
       max_stacks: 0,
-      current_locals: max_locals,
-      current_stacks: 0,
       labels: HashMap::new(),
-    }
+      exception_table: Vec::new(),
+      far_jumps: HashMap::new(),
+      unresolved_jumps: Vec::new(),
+      warnings,
+      huge_method_threshold: DEFAULT_HUGE_METHOD_THRESHOLD,
+      force_emit_empty: false,
+      line_numbers: Vec::new(),
+      local_variables: Vec::new(),
+      visible_type_annotations: Vec::new(),
+      invisible_type_annotations: Vec::new(),
+      visible_code_type_annotations: Vec::new(),
+      invisible_code_type_annotations: Vec::new(),
+      extensions: Extensions::new(),
+    })
   }
 
-  fn code_attributes_count(&self) -> u16 {
-    // TODO
-    let mut count = 0;
-    count
+  /// Gets user data attached to this writer, see [Extensions].
+  pub fn extensions(&self) -> &Extensions {
+    &self.extensions
   }
 
-  fn compute_exception_table_size(&self) -> u32 {
-    2 /* TODO: + 8 * exceptions */
+  /// Gets user data attached to this writer for modification, see
+  /// [Extensions].
+  pub fn extensions_mut(&mut self) -> &mut Extensions {
+    &mut self.extensions
   }
-}
-
-impl MethodVisitor for MethodWriter {
-  fn visit_code(&mut self) {
-    let mut cp = self.constant_pool.borrow_mut();
 
-    cp.put_utf8(attrs::CODE);
-
-    drop(cp);
-
-    let mut label = Label::default();
-
-    self.visit_label(&mut label);
+  /// Sets the code length above which
+  /// [GenerationWarning::HugeMethod] is reported.
+  pub(crate) fn huge_method_threshold(mut self, threshold: u32) -> Self {
+    self.huge_method_threshold = threshold;
+    self
+  }
 
-    self.labels.insert(label.offset(), label);
+  /// Emits Exceptions, and LineNumberTable and LocalVariableTable if method
+  /// has code, even when they have no entries.
+  pub(crate) fn force_emit_empty(mut self) -> Self {
+    let _ = self.constant_pool.borrow_mut().put_utf8(attrs::EXCEPTIONS);
+    self.force_emit_empty = true;
+    self
   }
 
-  fn visit_inst(&mut self, inst: u8) {
-      self.code.push_u8(inst);
+  fn has_exceptions(&self) -> bool {
+    self.force_emit_empty || !self.exception_indicies.is_empty()
   }
 
-  fn visit_label(&mut self, label: &mut Label) {
-    let bytecode_len = self.code.len() as u32;
+  fn has_line_numbers(&self) -> bool {
+    self.force_emit_empty || !self.line_numbers.is_empty()
+  }
 
-    label.resolve(&mut self.code, bytecode_len);
+  fn has_local_variables(&self) -> bool {
+    self.force_emit_empty || !self.local_variables.is_empty()
   }
 
-  fn visit_jump_inst(&mut self, opcode: u8, label: &mut Label) {
-    let bytecode_len = self.code.len() as u32;
-    let base_opcode = if opcode >= opcodes::GOTO_W {
-      opcode - 33
-    } else {
-      opcode
+  /// Reports [GenerationWarning::HugeMethod] to warning sink if emitted code
+  /// is longer than the threshold.
+  fn check_huge_method(&self, cp: &ConstantPool, code_len: u32) {
+    let Some(warnings) = &self.warnings else {
+      return;
     };
 
-    if label.flags().contains(LabelFlag::Resolved)
-      && (label.offset().wrapping_sub(bytecode_len) as i32) < (i16::MIN as i32)
-    {
-      match base_opcode {
-        opcodes::GOTO => {
-          self.code.push_u8(opcodes::GOTO_W);
-        }
-        opcodes::JSR => {
-          self.code.push_u8(opcodes::JSR_W);
-        }
-        _ => {
-          let flipped_branch_opcode = if base_opcode >= opcodes::IFNULL {
-            base_opcode ^ 1
-          } else {
-            ((base_opcode + 1) ^ 1) - 1
-          };
-
-          self
-            .code
-            .push_u8(flipped_branch_opcode)
-            .push_u16(8)
-            .push_u8(opcodes::GOTO_W);
-        }
-      }
-
-      let bytecode_len = self.code.len() as u32;
-
-      label.put(&mut self.code, bytecode_len - 1, true);
-    } else if base_opcode != opcode {
-      self.code.push_u8(opcode);
-
-      let bytecode_len = self.code.len() as u32;
-
-      label.put(&mut self.code, bytecode_len - 1, true);
-    } else {
-      self.code.push_u8(base_opcode);
-
-      let bytecode_len = self.code.len() as u32;
-
-      label.put(&mut self.code, bytecode_len - 1, false);
+    if code_len <= self.huge_method_threshold {
+      return;
     }
-  }
-}
-
-impl ToBytes for MethodWriter {
-  fn put_bytes(&self, vec: &mut ByteVec) {
-    let cp = self.constant_pool.borrow();
-    let attributes_count = self.attributes_count();
 
-    vec.push_u16(self.access.bits());
-    vec.push_u16(self.name_index);
-    vec.push_u16(self.descriptor_index);
-    vec.push_u16(attributes_count as u16);
+    let utf8 = |index| cp.resolve_utf8(index).unwrap_or_default().to_owned();
 
-    if !self.code.is_empty() {
-      let code_attr_size = 10 + self.code.len() as u32 + self.compute_exception_table_size();
+    warnings.borrow_mut().push(GenerationWarning::HugeMethod {
+      name: utf8(self.name_index),
+      descriptor: utf8(self.descriptor_index),
+      code_len,
+    });
+  }
 
-      vec
-        .push_u16(cp.get_utf8(attrs::CODE).unwrap())
-        .push_u32(code_attr_size)
-        .push_u16(self.max_stacks)
-        .push_u16(self.max_locals)
-        .push_u32(self.code.len() as u32)
-        .push_u8s(&self.code);
+  fn local_variable_types(&self) -> impl Iterator<Item = &LocalVariable> {
+    self
+      .local_variables
+      .iter()
+      .filter(|local_variable| local_variable.signature_index.is_some())
+  }
 
-      // TODO: Compute exception table
-      vec.push_u16(0);
+  fn code_attributes_count(&self) -> u16 {
+    let mut count = 0;
 
-      // TODO: Compute attributes
-      vec.push_u16(self.code_attributes_count());
+    if self.has_line_numbers() {
+      count += 1;
     }
-  }
-}
 
-impl SizeComputable for MethodWriter {
-  fn compute_size(&self) -> usize {
-    let mut size = 8;
+    if self.has_local_variables() {
+      count += 1;
+    }
 
-    if self.signature_index.is_some() {
-      size += 8;
+    if self.local_variable_types().next().is_some() {
+      count += 1;
     }
 
-    if !self.exception_indicies.is_empty() {
-      size += 8 + 2 * self.exception_indicies.len();
+    if !self.visible_code_type_annotations.is_empty() {
+      count += 1;
     }
 
-    if !self.code.is_empty() {
-      size += 16 + self.code.len();
+    if !self.invisible_code_type_annotations.is_empty() {
+      count += 1;
     }
 
-    size
+    count
   }
 
-  fn attributes_count(&self) -> usize {
+  fn compute_code_attributes_size(&self) -> u32 {
     let mut size = 0;
 
-    if self.signature_index.is_some() {
-      size += 1;
+    if self.has_line_numbers() {
+      size += 8 + 4 * self.line_numbers.len() as u32;
     }
 
-    if !self.exception_indicies.is_empty() {
-      size += 1;
+    if self.has_local_variables() {
+      size += 8 + 10 * self.local_variables.len() as u32;
     }
 
-    if !self.code.is_empty() {
-      size += 1;
+    let local_variable_types_len = self.local_variable_types().count() as u32;
+
+    if local_variable_types_len != 0 {
+      size += 8 + 10 * local_variable_types_len;
     }
 
+    size += compute_type_annotations_size(&self.visible_code_type_annotations) as u32;
+    size += compute_type_annotations_size(&self.invisible_code_type_annotations) as u32;
+
     size
   }
+
+  fn put_code_attributes(
+    &self,
+    cp: &ConstantPool,
+    vec: &mut ByteVec,
+    expanded_code: Option<&ExpandedCode>,
+  ) -> KapiResult<()> {
+    let offset = |offset: u16| {
+      let offset = expanded_code.map_or(offset as u32, |expanded_code| {
+        expanded_code.offset(offset as u32)
+      });
+
+      u16::try_from(offset).map_err(|_| {
+        KapiError::ArgError(format!(
+          "Bytecode offset {offset} exceeds the limit of 65535"
+        ))
+      })
+    };
+    // Maps live range (start_pc, length) of a local variable
+    let range = |start_pc: u16, length: u16| {
+      let end_pc = start_pc.checked_add(length).ok_or_else(|| {
+        KapiError::ArgError(format!(
+          "Live range at bytecode offset {start_pc} of length {length} exceeds the limit of 65535"
+        ))
+      })?;
+      let start_pc = offset(start_pc)?;
+
+      KapiResult::Ok((start_pc, offset(end_pc)? - start_pc))
+    };
+
+    vec.push_u16(self.code_attributes_count());
+
+    if self.has_line_numbers() {
+      vec
+        .push_u16(cp.get_utf8(attrs::LINE_NUMBER_TABLE).unwrap())
+        .push_u32(2 + 4 * self.line_numbers.len() as u32)
+        .push_u16(self.line_numbers.len() as u16);
+
+      for (start_pc, line_number) in &self.line_numbers {
+        vec.push_u16(offset(*start_pc)?).push_u16(*line_number);
+      }
+    }
+
+    if self.has_local_variables() {
+      vec
+        .push_u16(cp.get_utf8(attrs::LOCAL_VARIABLE_TABLE).unwrap())
+        .push_u32(2 + 10 * self.local_variables.len() as u32)
+        .push_u16(self.local_variables.len() as u16);
+
+      for local_variable in &self.local_variables {
+        let (start_pc, length) = range(local_variable.start_pc, local_variable.length)?;
+
+        vec
+          .push_u16(start_pc)
+          .push_u16(length)
+          .push_u16(local_variable.name_index)
+          .push_u16(local_variable.descriptor_index)
+          .push_u16(local_variable.index);
+      }
+    }
+
+    let local_variable_types_len = self.local_variable_types().count() as u32;
+
+    if local_variable_types_len != 0 {
+      vec
+        .push_u16(cp.get_utf8(attrs::LOCAL_VARIABLE_TYPE_TABLE).unwrap())
+        .push_u32(2 + 10 * local_variable_types_len)
+        .push_u16(local_variable_types_len as u16);
+
+      for local_variable in self.local_variable_types() {
+        let (start_pc, length) = range(local_variable.start_pc, local_variable.length)?;
+
+        vec
+          .push_u16(start_pc)
+          .push_u16(length)
+          .push_u16(local_variable.name_index)
+          .push_u16(local_variable.signature_index.unwrap())
+          .push_u16(local_variable.index);
+      }
+    }
+
+    put_type_annotations(
+      cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS,
+      &self.visible_code_type_annotations,
+      &offset,
+    )?;
+    put_type_annotations(
+      cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS,
+      &self.invisible_code_type_annotations,
+      &offset,
+    )
+  }
+
+  /// Gets max_stack and max_locals to emit, computing them when
+  /// [ComputeOption::Maxs] is set.
+  fn maxs(&self, code: &[u8], exception_table: &[ExceptionHandler]) -> KapiResult<(u16, u16)> {
+    match self.compute {
+      ComputeOption::Nothing => Ok((self.max_stacks, self.max_locals)),
+      ComputeOption::Maxs => self.compute_maxs(code, exception_table),
+    }
+  }
+
+  /// Computes max_stack and max_locals by simulating the operand stack size
+  /// along every reachable path of emitted code. Exception handlers are
+  /// entered with only the thrown exception on the operand stack.
+  fn compute_maxs(
+    &self,
+    code: &[u8],
+    exception_table: &[ExceptionHandler],
+  ) -> KapiResult<(u16, u16)> {
+    let cp = self.constant_pool.borrow();
+    let mut max_locals = self.arguments_size;
+
+    for instruction in Instructions::new(code) {
+      if let Some((index, size)) = instruction?.local_variable(code)? {
+        max_locals = max_locals.max(index + size);
+      }
+    }
+
+    let mut max_stack = if exception_table.is_empty() { 0 } else { 1 };
+    let mut visited = HashMap::new();
+    let mut pending = vec![(0, 0)];
+
+    pending.extend(
+      exception_table
+        .iter()
+        .map(|handler| (handler.handler_pc as u32, 1)),
+    );
+
+    while let Some((offset, stack_size)) = pending.pop() {
+      if offset as usize >= code.len() || visited.insert(offset, stack_size).is_some() {
+        continue;
+      }
+
+      let instruction = instruction_at(code, offset)?;
+      let next_stack_size = stack_size + instruction.stack_size_delta(code, &cp)?;
+
+      if next_stack_size < 0 {
+        return Err(KapiError::StackUnderflow {
+          offset,
+          opcode: instruction.opcode,
+          stack_size,
+          delta: next_stack_size - stack_size,
+        });
+      }
+
+      max_stack = max_stack.max(next_stack_size);
+
+      for target in instruction.jump_targets(code)? {
+        pending.push((target, next_stack_size));
+      }
+
+      match instruction.opcode {
+        // Return address is only pushed onto subroutine's operand stack
+        opcodes::JSR | opcodes::JSR_W => pending.push((offset + instruction.len, stack_size)),
+        _ if !instruction.is_terminal() => {
+          pending.push((offset + instruction.len, next_stack_size))
+        }
+        _ => {}
+      }
+    }
+
+    Ok((max_stack as u16, max_locals))
+  }
+
+  /// Gets exception table with offsets mapped into expanded code. Handler
+  /// ranges must start and end on instruction boundaries, and handlers must
+  /// start on an instruction, see JVMS 4.7.3.
+  fn exception_table(
+    &self,
+    expanded_code: Option<&ExpandedCode>,
+  ) -> KapiResult<Vec<ExceptionHandler>> {
+    if self.exception_table.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let starts = Instructions::new(&self.code)
+      .map(|instruction| instruction.map(|instruction| instruction.offset))
+      .collect::<KapiResult<HashSet<_>>>()?;
+    let offset =
+      |pc: u16| expanded_code.map_or(pc as u32, |expanded_code| expanded_code.offset(pc as u32));
+
+    self
+      .exception_table
+      .iter()
+      .map(|handler| {
+        let is_start = |pc: u16| starts.contains(&(pc as u32));
+
+        if !is_start(handler.start_pc)
+          || !is_start(handler.handler_pc)
+          || !(is_start(handler.end_pc) || handler.end_pc as usize == self.code.len())
+        {
+          return Err(KapiError::StateError(format!(
+            "Exception handler at bytecode offset {} covering {}..{} is not aligned to instructions",
+            handler.handler_pc, handler.start_pc, handler.end_pc
+          )));
+        }
+
+        Ok(ExceptionHandler {
+          start_pc: offset(handler.start_pc) as u16,
+          end_pc: offset(handler.end_pc) as u16,
+          handler_pc: offset(handler.handler_pc) as u16,
+          catch_type: handler.catch_type,
+        })
+      })
+      .collect()
+  }
+
+  /// Checks MethodParameters has an entry for every parameter declared by
+  /// method descriptor, see JVMS 4.7.24.
+  fn check_parameters(&self, cp: &ConstantPool) -> KapiResult<()> {
+    if self.parameters.is_empty() {
+      return Ok(());
+    }
+
+    let descriptor = cp.resolve_utf8(self.descriptor_index).unwrap_or_default();
+    let (parameter_types, _) = parse_method_descriptor(descriptor)?;
+
+    if self.parameters.len() != parameter_types.len() {
+      return Err(KapiError::StateError(format!(
+        "MethodParameters has {} parameters but descriptor {descriptor} declares {}",
+        self.parameters.len(),
+        parameter_types.len()
+      )));
+    }
+
+    Ok(())
+  }
+
+  /// Checks every branch target, including every target of switch
+  /// instructions, lands on the start of an instruction within code.
+  fn check_branch_targets(&self, code: &[u8]) -> KapiResult<()> {
+    let instructions = Instructions::new(code).collect::<KapiResult<Vec<_>>>()?;
+    let starts = instructions
+      .iter()
+      .map(|instruction| instruction.offset)
+      .collect::<HashSet<_>>();
+
+    for instruction in &instructions {
+      for target in instruction.jump_targets(code)? {
+        if !starts.contains(&target) {
+          let violation = if target as usize >= code.len() {
+            format!("is out of code of length {}", code.len())
+          } else {
+            String::from("is not the start of an instruction")
+          };
+
+          return Err(KapiError::StateError(format!(
+            "Branch target {target} of instruction {} at bytecode offset {} {violation}",
+            instruction.opcode, instruction.offset
+          )));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Checks every return instruction matches the return type of method
+  /// descriptor, and execution cannot fall off the end of code.
+  fn check_returns(&self, cp: &ConstantPool, code: &[u8]) -> KapiResult<()> {
+    let descriptor = cp.resolve_utf8(self.descriptor_index).unwrap_or_default();
+    let (_, return_type) = parse_method_descriptor(descriptor)?;
+    let expected = match return_type {
+      Type::Void => opcodes::RETURN,
+      Type::Long => opcodes::LRETURN,
+      Type::Float => opcodes::FRETURN,
+      Type::Double => opcodes::DRETURN,
+      Type::Object(_) | Type::Array(_) => opcodes::ARETURN,
+      _ => opcodes::IRETURN,
+    };
+    let mut last = None;
+
+    for instruction in Instructions::new(code) {
+      let instruction = instruction?;
+      let found = match instruction.opcode {
+        opcodes::IRETURN => Some(Type::Int),
+        opcodes::LRETURN => Some(Type::Long),
+        opcodes::FRETURN => Some(Type::Float),
+        opcodes::DRETURN => Some(Type::Double),
+        opcodes::ARETURN => Some(Type::Object(String::from("java/lang/Object"))),
+        opcodes::RETURN => Some(Type::Void),
+        _ => None,
+      };
+
+      if let Some(found) = found.filter(|_| instruction.opcode != expected) {
+        return Err(KapiError::TypeMismatch {
+          expected: return_type,
+          found,
+          at: instruction.offset,
+        });
+      }
+
+      last = Some(instruction);
+    }
+
+    match last {
+      Some(instruction) if !instruction.is_terminal() => Err(KapiError::StateError(format!(
+        "Execution falls off the end of code after instruction {} at bytecode offset {}",
+        instruction.opcode, instruction.offset
+      ))),
+      _ => Ok(()),
+    }
+  }
+
+  /// Rewrites code if any forward jump is too far away from its target, see
+  /// [expand_jumps]. Inverted conditional branches are reported to warning
+  /// sink.
+  fn expand_far_jumps(&self) -> KapiResult<Option<ExpandedCode>> {
+    if self.far_jumps.is_empty() {
+      return Ok(None);
+    }
+
+    let expanded_code = expand_jumps(&self.code, &self.far_jumps)?;
+
+    if let Some(warnings) = &self.warnings {
+      warnings.borrow_mut().extend(
+        expanded_code
+          .inverted_branches
+          .iter()
+          .map(|pc| GenerationWarning::BranchInverted { pc: *pc }),
+      );
+    }
+
+    Ok(Some(expanded_code))
+  }
+
+  /// Writes Code attribute along with its nested attributes.
+  fn put_code(&self, cp: &ConstantPool, vec: &mut ByteVec) -> KapiResult<()> {
+    self.check_unresolved_jumps()?;
+
+    let expanded_code = self.expand_far_jumps()?;
+    let code = expanded_code
+      .as_ref()
+      .map_or(&self.code, |expanded_code| &expanded_code.code);
+
+    self.check_branch_targets(code)?;
+    self.check_returns(cp, code)?;
+
+    if code.len() > u16::MAX as usize {
+      return Err(KapiError::ArgError(format!(
+        "Code of {} bytes exceeds the limit of 65535 bytes",
+        code.len()
+      )));
+    }
+
+    let exception_table = self.exception_table(expanded_code.as_ref())?;
+    let (max_stack, max_locals) = self.maxs(code, &exception_table)?;
+
+    self.check_huge_method(cp, code.len() as u32);
+
+    vec.push_u16(cp.get_utf8(attrs::CODE).unwrap());
+
+    let code_attr_len = vec.reserve_slot(4);
+
+    vec
+      .push_u16(max_stack)
+      .push_u16(max_locals)
+      .push_u32(code.len() as u32)
+      .push_u8s(code);
+
+    vec.push_u16(exception_table.len() as u16);
+
+    for handler in &exception_table {
+      vec
+        .push_u16(handler.start_pc)
+        .push_u16(handler.end_pc)
+        .push_u16(handler.handler_pc)
+        .push_u16(handler.catch_type);
+    }
+
+    self.put_code_attributes(cp, vec, expanded_code.as_ref())?;
+
+    vec.patch_len(code_attr_len)
+  }
+
+  /// Wraps an error raised while writing code with the name and descriptor
+  /// of current method, so that failures can be told apart when generating
+  /// many methods.
+  fn in_method(&self, cp: &ConstantPool, error: KapiError) -> KapiError {
+    KapiError::InMethod {
+      name: cp
+        .resolve_utf8(self.name_index)
+        .unwrap_or_default()
+        .to_owned(),
+      descriptor: cp
+        .resolve_utf8(self.descriptor_index)
+        .unwrap_or_default()
+        .to_owned(),
+      source: Box::new(error),
+    }
+  }
+
+  /// Gets bytecode offset of a label which must be visited beforehand, since
+  /// debug information can't be patched afterwards.
+  fn resolved_offset(label: &Label) -> KapiResult<u16> {
+    if label.flags().contains(LabelFlag::Resolved) {
+      u16::try_from(label.offset()).map_err(|_| {
+        KapiError::ArgError(format!(
+          "Label at bytecode offset {} exceeds the limit of 65535",
+          label.offset()
+        ))
+      })
+    } else {
+      Err(KapiError::StateError(String::from(
+        "Label must be visited before being referenced by debug information",
+      )))
+    }
+  }
+
+  /// Writes the branch offset from instruction at `source_offset` to label,
+  /// forward references are tracked until the label is visited.
+  fn put_label(&mut self, label: &mut Label, source_offset: u32, wide_ref: bool) {
+    if !label.is_resolved() {
+      self.unresolved_jumps.push(source_offset);
+    }
+
+    label.put(&mut self.code, source_offset, wide_ref);
+  }
+
+  /// Checks every label referenced by jump or switch instructions has been
+  /// visited, otherwise their branch offsets are left as placeholders.
+  fn check_unresolved_jumps(&self) -> KapiResult<()> {
+    if self.unresolved_jumps.is_empty() {
+      return Ok(());
+    }
+
+    let mut source_offsets = self.unresolved_jumps.clone();
+
+    source_offsets.sort_unstable();
+    source_offsets.dedup();
+
+    Err(KapiError::StateError(format!(
+      "Instructions at bytecode offsets {source_offsets:?} reference labels which are never visited"
+    )))
+  }
+
+  /// Pads code with zero bytes until the next 4-byte aligned offset, as
+  /// required by the operands of `tableswitch` and `lookupswitch`.
+  fn put_switch_padding(&mut self) {
+    let padding = (4 - self.code.len() % 4) % 4;
+
+    for _ in 0..padding {
+      self.code.push_u8(0);
+    }
+  }
+}
+
+impl MethodVisitor for MethodWriter {
+  fn visit_parameter(&mut self, name: Option<&str>, access: ParameterAccessFlag) -> KapiResult<()> {
+    // parameters_count of MethodParameters is a u1
+    if self.parameters.len() == u8::MAX as usize {
+      return Err(KapiError::StateError(format!(
+        "MethodParameters cannot have more than {} parameters",
+        u8::MAX
+      )));
+    }
+
+    let mut cp = self.constant_pool.borrow_mut();
+
+    cp.put_utf8(attrs::METHOD_PARAMETERS)?;
+
+    let name_index = name.map_or(Ok(0), |name| cp.put_utf8(name))?;
+
+    drop(cp);
+
+    self.parameters.push((name_index, access));
+
+    Ok(())
+  }
+
+  fn visit_code(&mut self) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::CODE);
+
+    if self.force_emit_empty {
+      let _ = cp.put_utf8(attrs::LINE_NUMBER_TABLE);
+      let _ = cp.put_utf8(attrs::LOCAL_VARIABLE_TABLE);
+    }
+
+    drop(cp);
+
+    let mut label = Label::default();
+
+    self
+      .visit_label(&mut label)
+      .expect("Fresh label has no forward reference to patch");
+
+    self.labels.insert(label.offset(), label);
+  }
+
+  fn current_code_len(&mut self) -> u32 {
+    self.code.len() as u32
+  }
+
+  fn visit_simple_inst(&mut self, opcode: u8) -> KapiResult<()> {
+    if !takes_no_operands(opcode) {
+      return Err(KapiError::UnexpectedOpcode {
+        opcode,
+        kind: InstructionKind::NoOperand,
+      });
+    }
+
+    self.code.push_u8(opcode);
+
+    Ok(())
+  }
+
+  fn visit_int_inst(&mut self, opcode: u8, operand: i32) -> KapiResult<()> {
+    let out_of_range = |name: &str| {
+      KapiError::ArgError(format!(
+        "Operand {operand} of instruction {name} is out of range"
+      ))
+    };
+
+    match opcode {
+      opcodes::BIPUSH => {
+        let operand = i8::try_from(operand).map_err(|_| out_of_range("bipush"))?;
+
+        self.code.push_u8(opcode).push_u8(operand as u8);
+      }
+      opcodes::SIPUSH => {
+        let operand = i16::try_from(operand).map_err(|_| out_of_range("sipush"))?;
+
+        self.code.push_u8(opcode).push_i16(operand);
+      }
+      opcodes::NEWARRAY => {
+        let array_type = u8::try_from(operand)
+          .ok()
+          .filter(|array_type| (opcodes::T_BOOLEAN..=opcodes::T_LONG).contains(array_type))
+          .ok_or_else(|| out_of_range("newarray"))?;
+
+        self.code.push_u8(opcode).push_u8(array_type);
+      }
+      _ => {
+        return Err(KapiError::UnexpectedOpcode {
+          opcode,
+          kind: InstructionKind::Int,
+        })
+      }
+    }
+
+    Ok(())
+  }
+
+  fn visit_field_inst(
+    &mut self,
+    opcode: u8,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+  ) -> KapiResult<()> {
+    if !(opcodes::GETSTATIC..=opcodes::PUTFIELD).contains(&opcode) {
+      return Err(KapiError::UnexpectedOpcode {
+        opcode,
+        kind: InstructionKind::Field,
+      });
+    }
+
+    parse_field_descriptor(descriptor)?;
+
+    let index = self
+      .constant_pool
+      .borrow_mut()
+      .put_field_ref(owner, name, descriptor)?;
+
+    self.code.push_u8(opcode).push_u16(index);
+
+    Ok(())
+  }
+
+  fn visit_label(&mut self, label: &mut Label) -> KapiResult<()> {
+    let bytecode_len = self.code.len() as u32;
+    let far_references = label.resolve(&mut self.code, bytecode_len)?;
+
+    for source_offset in label.forward_sources() {
+      if let Some(position) = self
+        .unresolved_jumps
+        .iter()
+        .position(|unresolved| *unresolved == source_offset)
+      {
+        self.unresolved_jumps.swap_remove(position);
+      }
+    }
+
+    for source_offset in far_references {
+      self.far_jumps.insert(source_offset, bytecode_len);
+    }
+
+    Ok(())
+  }
+
+  fn visit_method_inst(
+    &mut self,
+    opcode: u8,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+    is_interface: bool,
+  ) -> KapiResult<()> {
+    match opcode {
+      opcodes::INVOKEINTERFACE if !is_interface => {
+        return Err(KapiError::ArgError(format!(
+          "Instruction invokeinterface cannot invoke method {name}{descriptor} of class {owner}"
+        )))
+      }
+      opcodes::INVOKEVIRTUAL if is_interface => {
+        return Err(KapiError::ArgError(format!(
+          "Instruction invokevirtual cannot invoke method {name}{descriptor} of interface {owner}"
+        )))
+      }
+      opcodes::INVOKEVIRTUAL..=opcodes::INVOKEINTERFACE => {}
+      _ => {
+        return Err(KapiError::UnexpectedOpcode {
+          opcode,
+          kind: InstructionKind::Method,
+        })
+      }
+    }
+
+    let (parameter_types, _) = parse_method_descriptor(descriptor)?;
+    let mut cp = self.constant_pool.borrow_mut();
+    let index = if is_interface {
+      cp.put_interface_method_ref(owner, name, descriptor)
+    } else {
+      cp.put_method_ref(owner, name, descriptor)
+    }?;
+
+    self.code.push_u8(opcode).push_u16(index);
+
+    if opcode == opcodes::INVOKEINTERFACE {
+      let arguments_size = 1 + parameter_types.iter().map(Type::size).sum::<u16>();
+
+      self.code.push_u8(arguments_size as u8).push_u8(0);
+    }
+
+    Ok(())
+  }
+
+  fn visit_type_inst(&mut self, opcode: u8, type_name: &str) -> KapiResult<()> {
+    match opcode {
+      opcodes::NEW if type_name.starts_with('[') => {
+        return Err(KapiError::ArgError(format!(
+          "Instruction new cannot instantiate array type {type_name}"
+        )))
+      }
+      opcodes::NEW | opcodes::ANEWARRAY | opcodes::CHECKCAST | opcodes::INSTANCEOF => {}
+      _ => {
+        return Err(KapiError::UnexpectedOpcode {
+          opcode,
+          kind: InstructionKind::Type,
+        })
+      }
+    }
+
+    let index = self.constant_pool.borrow_mut().put_class(type_name)?;
+
+    self.code.push_u8(opcode).push_u16(index);
+
+    Ok(())
+  }
+
+  fn visit_ldc_inst(&mut self, constant: LdcConstant) -> KapiResult<()> {
+    let index = self.constant_pool.borrow_mut().put_loadable(&constant)?;
+
+    match u8::try_from(index) {
+      _ if constant.is_wide() => {
+        self.code.push_u8(opcodes::LDC2_W).push_u16(index);
+      }
+      Ok(index) => {
+        self.code.push_u8(opcodes::LDC).push_u8(index);
+      }
+      Err(_) => {
+        self.code.push_u8(opcodes::LDC_W).push_u16(index);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn visit_var_inst(&mut self, opcode: u8, index: u16) -> KapiResult<()> {
+    let compact_base = match opcode {
+      opcodes::ILOAD..=opcodes::ALOAD => opcodes::ILOAD_0 + (opcode - opcodes::ILOAD) * 4,
+      opcodes::ISTORE..=opcodes::ASTORE => opcodes::ISTORE_0 + (opcode - opcodes::ISTORE) * 4,
+      opcodes::RET => opcodes::RET,
+      _ => {
+        return Err(KapiError::UnexpectedOpcode {
+          opcode,
+          kind: InstructionKind::LocalVariable,
+        })
+      }
+    };
+
+    match u8::try_from(index) {
+      // ret has no compact form
+      Ok(index) if index <= 3 && opcode != opcodes::RET => {
+        self.code.push_u8(compact_base + index);
+      }
+      Ok(index) => {
+        self.code.push_u8(opcode).push_u8(index);
+      }
+      Err(_) => {
+        self
+          .code
+          .push_u8(opcodes::WIDE)
+          .push_u8(opcode)
+          .push_u16(index);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn visit_iinc_inst(&mut self, index: u16, increment: i16) {
+    match (u8::try_from(index), i8::try_from(increment)) {
+      (Ok(index), Ok(increment)) => {
+        self
+          .code
+          .push_u8(opcodes::IINC)
+          .push_u8(index)
+          .push_u8(increment as u8);
+      }
+      _ => {
+        self
+          .code
+          .push_u8(opcodes::WIDE)
+          .push_u8(opcodes::IINC)
+          .push_u16(index)
+          .push_i16(increment);
+      }
+    }
+  }
+
+  fn visit_jump_inst(&mut self, opcode: u8, label: &mut Label) -> KapiResult<()> {
+    let bytecode_len = self.code.len() as u32;
+
+    if !is_jump(opcode) {
+      return Err(KapiError::UnexpectedOpcode {
+        opcode,
+        kind: InstructionKind::Jump,
+      });
+    }
+
+    let base_opcode = if opcode >= opcodes::GOTO_W {
+      opcode - 33
+    } else {
+      opcode
+    };
+
+    if label.flags().contains(LabelFlag::Resolved)
+      && (label.offset().wrapping_sub(bytecode_len) as i32) < (i16::MIN as i32)
+    {
+      match base_opcode {
+        opcodes::GOTO => {
+          self.code.push_u8(opcodes::GOTO_W);
+        }
+        opcodes::JSR => {
+          self.code.push_u8(opcodes::JSR_W);
+        }
+        _ => {
+          self
+            .code
+            .push_u8(inverse_branch(base_opcode))
+            .push_u16(8)
+            .push_u8(opcodes::GOTO_W);
+        }
+      }
+
+      let bytecode_len = self.code.len() as u32;
+
+      self.put_label(label, bytecode_len - 1, true);
+    } else if base_opcode != opcode {
+      self.code.push_u8(opcode);
+
+      let bytecode_len = self.code.len() as u32;
+
+      self.put_label(label, bytecode_len - 1, true);
+    } else {
+      self.code.push_u8(base_opcode);
+
+      let bytecode_len = self.code.len() as u32;
+
+      self.put_label(label, bytecode_len - 1, false);
+    }
+
+    Ok(())
+  }
+
+  fn visit_maxs(&mut self, max_stack: u16, max_locals: u16) {
+    if self.compute == ComputeOption::Nothing {
+      self.max_stacks = max_stack;
+      self.max_locals = max_locals;
+    }
+  }
+
+  fn visit_table_switch_inst(
+    &mut self,
+    min: i32,
+    max: i32,
+    default: &mut Label,
+    labels: &mut [&mut Label],
+  ) -> KapiResult<()> {
+    if min > max {
+      return Err(KapiError::ArgError(format!(
+        "Invalid tableswitch range, low {min} is greater than high {max}"
+      )));
+    }
+
+    let expected_labels_len = (max as i64 - min as i64 + 1) as usize;
+
+    if labels.len() != expected_labels_len {
+      return Err(KapiError::ArgError(format!(
+        "Tableswitch with range {min}..={max} expects {expected_labels_len} labels, but got {}",
+        labels.len()
+      )));
+    }
+
+    let bytecode_offset = self.code.len() as u32;
+
+    self.code.push_u8(opcodes::TABLESWITCH);
+    self.put_switch_padding();
+
+    self.put_label(default, bytecode_offset, true);
+
+    self.code.push_i32(min).push_i32(max);
+
+    for label in labels.iter_mut() {
+      self.put_label(label, bytecode_offset, true);
+    }
+
+    Ok(())
+  }
+
+  fn visit_lookup_switch_inst(
+    &mut self,
+    default: &mut Label,
+    pairs: &mut [(i32, &mut Label)],
+  ) -> KapiResult<()> {
+    // Pairs must be emitted in ascending key order, but we leave the caller's
+    // slice untouched and sort indices instead
+    let mut order = (0..pairs.len()).collect::<Vec<_>>();
+
+    order.sort_by_key(|&i| pairs[i].0);
+
+    if let Some(keys) = order
+      .windows(2)
+      .find(|keys| pairs[keys[0]].0 == pairs[keys[1]].0)
+    {
+      return Err(KapiError::ArgError(format!(
+        "Duplicate lookupswitch key {}",
+        pairs[keys[0]].0
+      )));
+    }
+
+    let bytecode_offset = self.code.len() as u32;
+
+    self.code.push_u8(opcodes::LOOKUPSWITCH);
+    self.put_switch_padding();
+
+    self.put_label(default, bytecode_offset, true);
+
+    self.code.push_u32(pairs.len() as u32);
+
+    for i in order {
+      let (key, label) = &mut pairs[i];
+
+      self.code.push_i32(*key);
+      self.put_label(label, bytecode_offset, true);
+    }
+
+    Ok(())
+  }
+
+  fn visit_try_catch_block(
+    &mut self,
+    start: &Label,
+    end: &Label,
+    handler: &Label,
+    exception_type: Option<&str>,
+  ) -> KapiResult<()> {
+    let start_pc = Self::resolved_offset(start)?;
+    let end_pc = Self::resolved_offset(end)?;
+    let handler_pc = Self::resolved_offset(handler)?;
+
+    if end_pc <= start_pc {
+      return Err(KapiError::ArgError(format!(
+        "Exception handler at bytecode offset {handler_pc} covers empty range {start_pc}..{end_pc}"
+      )));
+    }
+
+    if self.exception_table.len() == u16::MAX as usize {
+      return Err(KapiError::ArgError(String::from(
+        "Code cannot have more than 65535 exception handlers",
+      )));
+    }
+
+    let catch_type = exception_type
+      .map(|exception_type| self.constant_pool.borrow_mut().put_class(exception_type))
+      .transpose()?
+      .unwrap_or(0);
+
+    self.exception_table.push(ExceptionHandler {
+      start_pc,
+      end_pc,
+      handler_pc,
+      catch_type,
+    });
+
+    Ok(())
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if !target_info.is_method_target() {
+      return Err(KapiError::ArgError(format!(
+        "Type annotation target {target_info:?} is not applicable to method"
+      )));
+    }
+
+    let type_annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.visible_type_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.invisible_type_annotations
+    };
+
+    type_annotations.push(TypeAnnotationWriter::new(
+      self.constant_pool.clone(),
+      target_info,
+      type_path,
+      descriptor,
+    )?);
+
+    Ok(
+      type_annotations
+        .last_mut()
+        .map(|taw| taw as &mut dyn AnnotationVisitor),
+    )
+  }
+
+  fn visit_local_variable_annotation(
+    &mut self,
+    target: LocalVariableTarget,
+    ranges: &[(&Label, &Label, u16)],
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    let ranges = ranges
+      .iter()
+      .map(|(start, end, index)| {
+        let start_pc = Self::resolved_offset(start)?;
+        let end_pc = Self::resolved_offset(end)?;
+
+        if end_pc < start_pc {
+          return Err(KapiError::ArgError(format!(
+            "Local variable live range ends at bytecode offset {end_pc} before its start {start_pc}"
+          )));
+        }
+
+        Ok((start_pc, end_pc - start_pc, *index))
+      })
+      .collect::<KapiResult<Vec<_>>>()?;
+
+    let type_annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.visible_code_type_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.invisible_code_type_annotations
+    };
+
+    type_annotations.push(TypeAnnotationWriter::new_local_variable(
+      self.constant_pool.clone(),
+      target,
+      ranges,
+      type_path,
+      descriptor,
+    )?);
+
+    Ok(
+      type_annotations
+        .last_mut()
+        .map(|taw| taw as &mut dyn AnnotationVisitor),
+    )
+  }
+
+  fn visit_line_number(&mut self, line: u16, start: &Label) -> KapiResult<()> {
+    let start_pc = Self::resolved_offset(start)?;
+
+    self
+      .constant_pool
+      .borrow_mut()
+      .put_utf8(attrs::LINE_NUMBER_TABLE)?;
+    self.line_numbers.push((start_pc, line));
+
+    Ok(())
+  }
+
+  fn visit_local_variable(
+    &mut self,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+    start: &Label,
+    end: &Label,
+    index: u16,
+  ) -> KapiResult<()> {
+    let start_pc = Self::resolved_offset(start)?;
+    let end_pc = Self::resolved_offset(end)?;
+
+    if end_pc < start_pc {
+      return Err(KapiError::ArgError(format!(
+        "Local variable {name} ends at bytecode offset {end_pc} before its start {start_pc}"
+      )));
+    }
+
+    let mut cp = self.constant_pool.borrow_mut();
+
+    cp.put_utf8(attrs::LOCAL_VARIABLE_TABLE)?;
+
+    if signature.is_some() {
+      cp.put_utf8(attrs::LOCAL_VARIABLE_TYPE_TABLE)?;
+    }
+
+    let local_variable = LocalVariable {
+      start_pc,
+      length: end_pc - start_pc,
+      name_index: cp.put_utf8(name)?,
+      descriptor_index: cp.put_utf8(descriptor)?,
+      signature_index: signature
+        .map(|signature| cp.put_utf8(signature))
+        .transpose()?,
+      index,
+    };
+
+    drop(cp);
+
+    self.local_variables.push(local_variable);
+
+    Ok(())
+  }
+}
+
+impl ToBytes for MethodWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    let cp = self.constant_pool.borrow();
+    let attributes_count = self.attributes_count();
+
+    self
+      .check_parameters(&cp)
+      .map_err(|error| self.in_method(&cp, error))?;
+
+    vec.push_u16(self.access.bits());
+    vec.push_u16(self.name_index);
+    vec.push_u16(self.descriptor_index);
+    vec.push_u16(attributes_count as u16);
+
+    if !self.code.is_empty() {
+      self
+        .put_code(&cp, vec)
+        .map_err(|error| self.in_method(&cp, error))?;
+    }
+
+    if let Some(signature_index) = self.signature_index {
+      vec
+        .push_u16(cp.get_utf8(attrs::SIGNATURE).unwrap())
+        .push_u32(2)
+        .push_u16(signature_index);
+    }
+
+    if self.has_exceptions() {
+      vec
+        .push_u16(cp.get_utf8(attrs::EXCEPTIONS).unwrap())
+        .push_u32(2 + 2 * self.exception_indicies.len() as u32)
+        .push_u16(self.exception_indicies.len() as u16);
+
+      for exception_index in &self.exception_indicies {
+        vec.push_u16(*exception_index);
+      }
+    }
+
+    if !self.parameters.is_empty() {
+      vec
+        .push_u16(cp.get_utf8(attrs::METHOD_PARAMETERS).unwrap())
+        .push_u32(1 + 4 * self.parameters.len() as u32)
+        .push_u8(self.parameters.len() as u8);
+
+      for (name_index, access) in &self.parameters {
+        vec.push_u16(*name_index).push_u16(access.bits());
+      }
+    }
+
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS,
+      &self.visible_type_annotations,
+      &Ok,
+    )?;
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS,
+      &self.invisible_type_annotations,
+      &Ok,
+    )?;
+
+    Ok(())
+  }
+}
+
+impl SizeComputable for MethodWriter {
+  fn compute_size(&self) -> usize {
+    let mut size = 8;
+
+    if self.signature_index.is_some() {
+      size += 8;
+    }
+
+    if self.has_exceptions() {
+      size += 8 + 2 * self.exception_indicies.len();
+    }
+
+    if !self.parameters.is_empty() {
+      size += 7 + 4 * self.parameters.len();
+    }
+
+    if !self.code.is_empty() {
+      // Far jumps are expanded when writing, which lengthens the code
+      let code_len = if self.far_jumps.is_empty() {
+        self.code.len()
+      } else {
+        expand_jumps(&self.code, &self.far_jumps)
+          .map_or(self.code.len(), |expanded_code| expanded_code.code.len())
+      };
+
+      size += 18
+        + code_len
+        + 8 * self.exception_table.len()
+        + self.compute_code_attributes_size() as usize;
+    }
+
+    size += compute_type_annotations_size(&self.visible_type_annotations);
+    size += compute_type_annotations_size(&self.invisible_type_annotations);
+
+    size
+  }
+
+  fn attributes_count(&self) -> usize {
+    let mut size = 0;
+
+    if self.signature_index.is_some() {
+      size += 1;
+    }
+
+    if self.has_exceptions() {
+      size += 1;
+    }
+
+    if !self.parameters.is_empty() {
+      size += 1;
+    }
+
+    if !self.code.is_empty() {
+      size += 1;
+    }
+
+    if !self.visible_type_annotations.is_empty() {
+      size += 1;
+    }
+
+    if !self.invisible_type_annotations.is_empty() {
+      size += 1;
+    }
+
+    size
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    cell::RefCell,
+    rc::Rc,
+  };
+
+  use crate::{
+    access_flag::{
+      MethodAccessFlag,
+      ParameterAccessFlag,
+    },
+    annotation::LocalVariableTarget,
+    byte_reader::ByteReader,
+    byte_vec::{
+      SizeComputable,
+      ToBytes,
+    },
+    class::ComputeOption,
+    constant::{
+      Constant,
+      ConstantPool,
+    },
+    error::{
+      GenerationWarning,
+      InstructionKind,
+      KapiError,
+    },
+    handle::{
+      Handle,
+      RefKind,
+    },
+    instruction::Instructions,
+    label::Label,
+    method::{
+      LdcConstant,
+      MethodVisitor,
+      MethodWriter,
+    },
+    opcodes,
+    types::Type,
+  };
+
+  fn in_method(descriptor: &str, error: KapiError) -> KapiError {
+    KapiError::InMethod {
+      name: String::from("test"),
+      descriptor: descriptor.to_owned(),
+      source: Box::new(error),
+    }
+  }
+
+  fn method_writer() -> MethodWriter {
+    method_writer_with(ComputeOption::Nothing, "(I)V")
+  }
+
+  fn method_writer_with(compute: ComputeOption, descriptor: &str) -> MethodWriter {
+    MethodWriter::new(
+      Rc::new(RefCell::new(ConstantPool::default())),
+      compute,
+      None,
+      MethodAccessFlag::Public | MethodAccessFlag::Static,
+      "test",
+      descriptor,
+      None,
+      &[],
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_table_switch() {
+    let mut mw = method_writer();
+    let mut default = Label::new();
+    let mut case_0 = Label::new();
+    let mut case_1 = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_table_switch_inst(0, 1, &mut default, &mut [&mut case_0, &mut case_1])
+      .unwrap();
+    mw.visit_label(&mut case_0).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut case_1).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut default).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::ILOAD_0,
+        opcodes::TABLESWITCH, 0, 0,
+        0, 0, 0, 25, // default
+        0, 0, 0, 0,  // low
+        0, 0, 0, 1,  // high
+        0, 0, 0, 23, // 0
+        0, 0, 0, 24, // 1
+        opcodes::RETURN,
+        opcodes::RETURN,
+        opcodes::RETURN,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_table_switch_backward_targets() {
+    let mut mw = method_writer();
+    let mut target = Label::new();
+    let mut default = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut target).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_label(&mut default).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_table_switch_inst(-1, -1, &mut default, &mut [&mut target])
+      .unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::ILOAD_0,
+        opcodes::ILOAD_0,
+        opcodes::ILOAD_0,
+        opcodes::TABLESWITCH,
+        0xFF, 0xFF, 0xFF, 0xFF, // default
+        0xFF, 0xFF, 0xFF, 0xFF, // low
+        0xFF, 0xFF, 0xFF, 0xFF, // high
+        0xFF, 0xFF, 0xFF, 0xFD, // -1
+      ]
+    );
+  }
+
+  #[test]
+  fn test_table_switch_mismatched_labels() {
+    let mut mw = method_writer();
+    let mut default = Label::new();
+    let mut case_0 = Label::new();
+
+    mw.visit_code();
+
+    assert!(matches!(
+      mw.visit_table_switch_inst(0, 2, &mut default, &mut [&mut case_0]),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_table_switch_inst(1, 0, &mut default, &mut []),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_lookup_switch() {
+    let mut mw = method_writer();
+    let mut default = Label::new();
+    let mut case_10 = Label::new();
+    let mut case_m1 = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::NOP).unwrap();
+    mw.visit_lookup_switch_inst(&mut default, &mut [(10, &mut case_10), (-1, &mut case_m1)])
+      .unwrap();
+    mw.visit_label(&mut case_m1).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut case_10).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut default).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::ILOAD_0,
+        opcodes::NOP,
+        opcodes::LOOKUPSWITCH, 0,
+        0, 0, 0, 28,            // default
+        0, 0, 0, 2,             // npairs
+        0xFF, 0xFF, 0xFF, 0xFF, // -1
+        0, 0, 0, 26,
+        0, 0, 0, 10,            // 10
+        0, 0, 0, 27,
+        opcodes::RETURN,
+        opcodes::RETURN,
+        opcodes::RETURN,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_lookup_switch_duplicate_keys() {
+    let mut mw = method_writer();
+    let mut default = Label::new();
+    let mut case_0 = Label::new();
+    let mut case_1 = Label::new();
+
+    mw.visit_code();
+
+    assert!(matches!(
+      mw.visit_lookup_switch_inst(&mut default, &mut [(1, &mut case_0), (1, &mut case_1)]),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_box_unbox() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(J)J");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::LLOAD_0).unwrap();
+    mw.visit_box(&Type::Long).unwrap();
+    mw.visit_unbox(&Type::Long).unwrap();
+    mw.visit_simple_inst(opcodes::LRETURN).unwrap();
+
+    let mut cp = mw.constant_pool.borrow_mut();
+    let [value_of_0, value_of_1] = cp
+      .put_method_ref("java/lang/Long", "valueOf", "(J)Ljava/lang/Long;")
+      .unwrap()
+      .to_be_bytes();
+    let [long_value_0, long_value_1] = cp
+      .put_method_ref("java/lang/Long", "longValue", "()J")
+      .unwrap()
+      .to_be_bytes();
+
+    drop(cp);
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::LLOAD_0,
+        opcodes::INVOKESTATIC, value_of_0, value_of_1,
+        opcodes::INVOKEVIRTUAL, long_value_0, long_value_1,
+        opcodes::LRETURN,
+      ]
+    );
+    assert_eq!(mw.compute_maxs(&mw.code, &[]), Ok((2, 2)));
+    assert!(matches!(
+      mw.visit_box(&Type::Object(String::from("java/lang/String"))),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_unbox(&Type::Void),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_primitive_casts() {
+    let mut mw = method_writer();
+
+    mw.visit_code();
+    mw.visit_widening_cast(&Type::Byte, &Type::Int).unwrap();
+    mw.visit_widening_cast(&Type::Char, &Type::Double).unwrap();
+    mw.visit_narrowing_cast(&Type::Double, &Type::Short)
+      .unwrap();
+    mw.visit_narrowing_cast(&Type::Float, &Type::Long).unwrap();
+
+    assert_eq!(
+      mw.code,
+      vec![opcodes::I2D, opcodes::D2I, opcodes::I2S, opcodes::F2L]
+    );
+    assert!(matches!(
+      mw.visit_widening_cast(&Type::Long, &Type::Int),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_narrowing_cast(&Type::Byte, &Type::Char),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_widening_cast(&Type::Boolean, &Type::Int),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_return_instructions() {
+    // Both paths return a long
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(I)J");
+    let mut negative = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFLT, &mut negative).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_1).unwrap();
+    mw.visit_simple_inst(opcodes::LRETURN).unwrap();
+    mw.visit_label(&mut negative).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::LRETURN).unwrap();
+
+    assert!(mw.put_bytes(&mut Vec::new()).is_ok());
+
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(I)J");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::IRETURN).unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)J",
+        KapiError::TypeMismatch {
+          expected: Type::Long,
+          found: Type::Int,
+          at: 1,
+        },
+      ))
+    );
+
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(I)V");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::POP).unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::StateError(String::from(
+          "Execution falls off the end of code after instruction 87 at bytecode offset 1"
+        )),
+      ))
+    );
+  }
+
+  #[test]
+  fn test_code_boundaries() {
+    let mut mw = method_writer();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_simple_inst(opcodes::NOP).unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::StateError(String::from(
+          "Execution falls off the end of code after instruction 0 at bytecode offset 1"
+        )),
+      ))
+    );
+
+    // Jumps into its own branch offset
+    let mut mw = method_writer();
+
+    mw.visit_code();
+    mw.code.extend([opcodes::GOTO, 0, 1]);
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::StateError(String::from("Branch target 1 of instruction 167 at bytecode offset 0 is not the start of an instruction")),
+      ))
+    );
+
+    let mut mw = method_writer();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_jump_inst(opcodes::GOTO, &mut end).unwrap();
+    mw.visit_label(&mut end).unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::StateError(String::from(
+          "Branch target 3 of instruction 167 at bytecode offset 0 is out of code of length 3"
+        )),
+      ))
+    );
+  }
+
+  #[test]
+  fn test_int_inst() {
+    let mut mw = method_writer();
+
+    mw.visit_code();
+    mw.visit_int_inst(opcodes::BIPUSH, -1).unwrap();
+    mw.visit_int_inst(opcodes::SIPUSH, 1000).unwrap();
+    mw.visit_int_inst(opcodes::NEWARRAY, opcodes::T_INT as i32)
+      .unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::BIPUSH, 0xFF,
+        opcodes::SIPUSH, 0x03, 0xE8,
+        opcodes::NEWARRAY, opcodes::T_INT,
+      ]
+    );
+
+    for (opcode, operand, message) in [
+      (
+        opcodes::BIPUSH,
+        128,
+        "Operand 128 of instruction bipush is out of range",
+      ),
+      (
+        opcodes::SIPUSH,
+        -32769,
+        "Operand -32769 of instruction sipush is out of range",
+      ),
+      (
+        opcodes::NEWARRAY,
+        12,
+        "Operand 12 of instruction newarray is out of range",
+      ),
+    ] {
+      assert_eq!(
+        mw.visit_int_inst(opcode, operand),
+        Err(KapiError::ArgError(String::from(message)))
+      );
+    }
+
+    assert_eq!(
+      mw.visit_int_inst(opcodes::LDC, 1),
+      Err(KapiError::UnexpectedOpcode {
+        opcode: opcodes::LDC,
+        kind: InstructionKind::Int,
+      })
+    );
+
+    assert!(matches!(
+      mw.visit_method_inst(opcodes::INVOKEINTERFACE, "A", "x", "(", true),
+      Err(KapiError::InvalidDescriptor { .. })
+    ));
+    assert_eq!(mw.code.len(), 7);
+  }
+
+  #[test]
+  fn test_maxs_malformed_method_descriptor() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()V");
+    let [method_0, method_1] = mw
+      .constant_pool
+      .borrow_mut()
+      .put_method_ref("Main", "run", "(I")
+      .unwrap()
+      .to_be_bytes();
+
+    mw.visit_code();
+    mw.code
+      .extend([opcodes::INVOKESTATIC, method_0, method_1, opcodes::RETURN]);
+
+    assert!(matches!(
+      mw.maxs(&mw.code, &[]),
+      Err(KapiError::InvalidDescriptor { descriptor, .. }) if descriptor == "(I"
+    ));
+  }
+
+  #[test]
+  fn test_field_inst() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()V");
+
+    mw.visit_code();
+    mw.visit_field_inst(opcodes::GETSTATIC, "Main", "value", "J")
+      .unwrap();
+    mw.visit_field_inst(opcodes::PUTSTATIC, "Main", "value", "J")
+      .unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    let [field_0, field_1] = mw
+      .constant_pool
+      .borrow_mut()
+      .put_field_ref("Main", "value", "J")
+      .unwrap()
+      .to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::GETSTATIC, field_0, field_1,
+        opcodes::PUTSTATIC, field_0, field_1,
+        opcodes::RETURN,
+      ]
+    );
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((2, 0)));
+    assert_eq!(
+      mw.visit_field_inst(opcodes::INVOKESTATIC, "Main", "value", "J"),
+      Err(KapiError::UnexpectedOpcode {
+        opcode: opcodes::INVOKESTATIC,
+        kind: InstructionKind::Field,
+      })
+    );
+    assert!(matches!(
+      mw.visit_field_inst(opcodes::GETFIELD, "Main", "value", "V"),
+      Err(KapiError::InvalidDescriptor { .. })
+    ));
+  }
+
+  #[test]
+  fn test_method_inst() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(Ljava/util/List;)I");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ALOAD_0).unwrap();
+    mw.visit_method_inst(
+      opcodes::INVOKEINTERFACE,
+      "java/util/List",
+      "size",
+      "()I",
+      true,
+    )
+    .unwrap();
+    mw.visit_simple_inst(opcodes::IRETURN).unwrap();
+
+    let [method_0, method_1] = mw
+      .constant_pool
+      .borrow_mut()
+      .put_interface_method_ref("java/util/List", "size", "()I")
+      .unwrap()
+      .to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      vec![
+        opcodes::ALOAD_0,
+        opcodes::INVOKEINTERFACE, method_0, method_1, 1, 0,
+        opcodes::IRETURN,
+      ]
+    );
+    assert_eq!(
+      mw.visit_method_inst(opcodes::NOP, "Main", "run", "()V", false),
+      Err(KapiError::UnexpectedOpcode {
+        opcode: opcodes::NOP,
+        kind: InstructionKind::Method,
+      })
+    );
+    assert_eq!(
+      mw.visit_method_inst(opcodes::INVOKEDYNAMIC, "Main", "run", "()V", false),
+      Err(KapiError::UnexpectedOpcode {
+        opcode: opcodes::INVOKEDYNAMIC,
+        kind: InstructionKind::Method,
+      })
+    );
+    assert_eq!(
+      mw.visit_method_inst(opcodes::INVOKEINTERFACE, "Main", "run", "()V", false),
+      Err(KapiError::ArgError(String::from(
+        "Instruction invokeinterface cannot invoke method run()V of class Main"
+      )))
+    );
+    assert_eq!(
+      mw.visit_method_inst(opcodes::INVOKEVIRTUAL, "Main", "run", "()V", true),
+      Err(KapiError::ArgError(String::from(
+        "Instruction invokevirtual cannot invoke method run()V of interface Main"
+      )))
+    );
+    assert_eq!(mw.code.len(), 7);
+  }
+
+  #[test]
+  fn test_rejected_insts() {
+    let mut mw = method_writer();
+    let mut label = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ICONST_0).unwrap();
+
+    assert_eq!(
+      mw.visit_simple_inst(opcodes::BIPUSH),
+      Err(KapiError::UnexpectedOpcode {
+        opcode: opcodes::BIPUSH,
+        kind: InstructionKind::NoOperand,
+      })
+    );
+    assert_eq!(
+      mw.visit_jump_inst(opcodes::RETURN, &mut label),
+      Err(KapiError::UnexpectedOpcode {
+        opcode: opcodes::RETURN,
+        kind: InstructionKind::Jump,
+      })
+    );
+    #[allow(deprecated)]
+    let deprecated = mw.visit_inst(opcodes::SIPUSH);
+    assert!(deprecated.is_err());
+
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(mw.code, vec![opcodes::ICONST_0, opcodes::RETURN]);
+    assert!(mw.put_bytes(&mut Vec::new()).is_ok());
+  }
+
+  #[test]
+  fn test_implicit_local_loads() {
+    let mut mw = MethodWriter::new(
+      Rc::new(RefCell::new(ConstantPool::default())),
+      ComputeOption::Maxs,
+      None,
+      MethodAccessFlag::Public,
+      "test",
+      "(Ljava/lang/Object;F[I)V",
+      None,
+      &[],
+    )
+    .unwrap();
+
+    mw.visit_code();
+
+    for opcode in [opcodes::ALOAD_0, opcodes::ALOAD_1, opcodes::ALOAD_3] {
+      mw.visit_simple_inst(opcode).unwrap();
+      mw.visit_simple_inst(opcodes::POP).unwrap();
+    }
+
+    mw.visit_simple_inst(opcodes::FLOAD_2).unwrap();
+    mw.visit_simple_inst(opcodes::POP).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    let instructions = Instructions::new(&mw.code)
+      .map(|instruction| {
+        let instruction = instruction.unwrap();
+
+        (
+          instruction.opcode,
+          instruction.local_variable(&mw.code).unwrap(),
+        )
+      })
+      .collect::<Vec<_>>();
+
+    // Every load is a single byte accessing the local named by its opcode
+    assert_eq!(
+      instructions,
+      [
+        (opcodes::ALOAD_0, Some((0, 1))),
+        (opcodes::POP, None),
+        (opcodes::ALOAD_1, Some((1, 1))),
+        (opcodes::POP, None),
+        (opcodes::ALOAD_3, Some((3, 1))),
+        (opcodes::POP, None),
+        (opcodes::FLOAD_2, Some((2, 1))),
+        (opcodes::POP, None),
+        (opcodes::RETURN, None),
+      ]
+    );
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((1, 4)));
+  }
+
+  #[test]
+  fn test_array_owners() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(Ljava/lang/Object;)[I");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ALOAD_0).unwrap();
+    mw.visit_type_inst(opcodes::CHECKCAST, "[I").unwrap();
+    mw.visit_method_inst(
+      opcodes::INVOKEVIRTUAL,
+      "[I",
+      "clone",
+      "()Ljava/lang/Object;",
+      false,
+    )
+    .unwrap();
+    mw.visit_type_inst(opcodes::CHECKCAST, "[I").unwrap();
+    mw.visit_simple_inst(opcodes::ARETURN).unwrap();
+
+    let mut cp = mw.constant_pool.borrow_mut();
+    let [array_0, array_1] = cp.put_class("[I").unwrap().to_be_bytes();
+    let [clone_0, clone_1] = cp
+      .put_method_ref("[I", "clone", "()Ljava/lang/Object;")
+      .unwrap()
+      .to_be_bytes();
+
+    drop(cp);
+
+    #[rustfmt::skip]
+    assert_eq!(
+      *mw.code,
+      [
+        opcodes::ALOAD_0,
+        opcodes::CHECKCAST, array_0, array_1,
+        opcodes::INVOKEVIRTUAL, clone_0, clone_1,
+        opcodes::CHECKCAST, array_0, array_1,
+        opcodes::ARETURN,
+      ]
+    );
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((1, 1)));
+    assert!(matches!(
+      mw.visit_type_inst(opcodes::NEW, "[I"),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_type_inst(opcodes::GETFIELD, "Main"),
+      Err(KapiError::UnexpectedOpcode {
+        kind: InstructionKind::Type,
+        ..
+      })
+    ));
+  }
+
+  #[test]
+  fn test_ldc_inst() {
+    let mut mw = method_writer();
+    let handle = Handle::new(RefKind::InvokeStatic, "Main", "run", "()V", false).unwrap();
+
+    mw.visit_code();
+
+    let code_start = mw.code.len();
+
+    mw.visit_ldc_inst(LdcConstant::Int(1)).unwrap();
+    mw.visit_ldc_inst(LdcConstant::Double(1.0)).unwrap();
+    mw.visit_ldc_inst(LdcConstant::MethodType(String::from("()V")))
+      .unwrap();
+    mw.visit_ldc_inst(LdcConstant::MethodHandle(handle.clone()))
+      .unwrap();
+
+    // Pushes further constants beyond index 255
+    for i in 0..u8::MAX {
+      mw.constant_pool
+        .borrow_mut()
+        .put_integer(i as i32 + 2)
+        .unwrap();
+    }
+
+    mw.visit_ldc_inst(LdcConstant::Class(String::from("[I")))
+      .unwrap();
+    mw.visit_ldc_inst(LdcConstant::Long(1)).unwrap();
+
+    let mut cp = mw.constant_pool.borrow_mut();
+    let int = cp.put_integer(1).unwrap() as u8;
+    let [double_0, double_1] = cp.put_double(1.0).unwrap().to_be_bytes();
+    let method_type = cp.put_method_type("()V").unwrap() as u8;
+    let method_handle = cp.put_method_handle(&handle).unwrap() as u8;
+    let [class_0, class_1] = cp.put_class("[I").unwrap().to_be_bytes();
+    let [long_0, long_1] = cp.put_long(1).unwrap().to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code[code_start..],
+      [
+        opcodes::LDC, int,
+        opcodes::LDC2_W, double_0, double_1,
+        opcodes::LDC, method_type,
+        opcodes::LDC, method_handle,
+        opcodes::LDC_W, class_0, class_1,
+        opcodes::LDC2_W, long_0, long_1,
+      ]
+    );
+
+    drop(cp);
+
+    assert!(matches!(
+      mw.visit_ldc_inst(LdcConstant::MethodType(String::from("I"))),
+      Err(KapiError::InvalidDescriptor { .. })
+    ));
+  }
+
+  #[test]
+  fn test_shared_constant_pool() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+    let method_writer = |name: &str| {
+      MethodWriter::new(
+        cp.clone(),
+        ComputeOption::Nothing,
+        None,
+        MethodAccessFlag::Static,
+        name,
+        "()V",
+        None,
+        &[],
+      )
+      .unwrap()
+    };
+    let ldc_strings = |mw: &mut MethodWriter, strings: &[&str]| {
+      mw.visit_code();
+
+      for string in strings {
+        mw.visit_ldc_inst(LdcConstant::String(string.to_string()))
+          .unwrap();
+      }
+    };
+    // Resolves the string loaded by each ldc instruction
+    let loaded_strings = |mw: &MethodWriter| {
+      let cp = cp.borrow();
+
+      Instructions::new(&mw.code)
+        .map(|instruction| {
+          let offset = instruction.unwrap().offset as usize;
+          let index = match mw.code[offset] {
+            opcodes::LDC => mw.code[offset + 1] as u16,
+            _ => u16::from_be_bytes([mw.code[offset + 1], mw.code[offset + 2]]),
+          };
+
+          match cp.get(index) {
+            Some(Constant::String(utf8)) => cp.resolve_utf8(*utf8).unwrap().to_owned(),
+            constant => panic!("Unexpected constant {constant:?}"),
+          }
+        })
+        .collect::<Vec<_>>()
+    };
+
+    // Overlapping constants interned in different orders
+    let mut first = method_writer("first");
+    let mut second = method_writer("second");
+
+    ldc_strings(&mut first, &["a", "b", "c"]);
+    ldc_strings(&mut second, &["c", "d", "a"]);
+
+    assert_eq!(loaded_strings(&first), ["a", "b", "c"]);
+    assert_eq!(loaded_strings(&second), ["c", "d", "a"]);
+
+    let mut methods = (0..1000)
+      .map(|i| method_writer(&format!("method{i}")))
+      .collect::<Vec<_>>();
+
+    for (i, mw) in methods.iter_mut().enumerate() {
+      ldc_strings(mw, &[&format!("string{i}"), "a"]);
+    }
+
+    // Earlier methods are unaffected by later ones
+    assert_eq!(loaded_strings(&first), ["a", "b", "c"]);
+
+    for (i, mw) in methods.iter().enumerate() {
+      assert_eq!(
+        loaded_strings(mw),
+        [format!("string{i}"), String::from("a")]
+      );
+    }
+  }
+
+  #[test]
+  fn test_var_inst() {
+    let mut mw = method_writer();
+
+    mw.visit_code();
+
+    let code_start = mw.code.len();
+
+    mw.visit_var_inst(opcodes::ILOAD, 0).unwrap();
+    mw.visit_var_inst(opcodes::DSTORE, 3).unwrap();
+    mw.visit_var_inst(opcodes::ALOAD, 4).unwrap();
+    mw.visit_var_inst(opcodes::LSTORE, 256).unwrap();
+    mw.visit_var_inst(opcodes::RET, 0).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code[code_start..],
+      [
+        opcodes::ILOAD_0,
+        opcodes::DSTORE_3,
+        opcodes::ALOAD, 4,
+        opcodes::WIDE, opcodes::LSTORE, 1, 0,
+        opcodes::RET, 0,
+      ]
+    );
+    assert!(matches!(
+      mw.visit_var_inst(opcodes::IINC, 0),
+      Err(KapiError::UnexpectedOpcode {
+        kind: InstructionKind::LocalVariable,
+        ..
+      })
+    ));
+  }
+
+  #[test]
+  fn test_iinc() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()V");
+
+    mw.visit_code();
+    mw.visit_iinc_inst(1, -1);
+    mw.visit_iinc_inst(2, 127);
+    mw.visit_iinc_inst(3, -32768);
+    mw.visit_iinc_inst(300, 1);
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      *mw.code,
+      [
+        opcodes::IINC, 1, 0xFF,
+        opcodes::IINC, 2, 0x7F,
+        opcodes::WIDE, opcodes::IINC, 0, 3, 0x80, 0x00,
+        opcodes::WIDE, opcodes::IINC, 0x01, 0x2C, 0, 1,
+        opcodes::RETURN,
+      ]
+    );
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((0, 301)));
+  }
+
+  #[test]
+  fn test_instruction_lengths_round_trip() {
+    let mut mw = method_writer();
+    let mut labels = [Label::new(), Label::new(), Label::new(), Label::new()];
+
+    mw.visit_code();
+    mw.visit_int_inst(opcodes::BIPUSH, 1).unwrap();
+    mw.visit_int_inst(opcodes::SIPUSH, 300).unwrap();
+    mw.visit_int_inst(opcodes::NEWARRAY, opcodes::T_INT as i32)
+      .unwrap();
+    mw.visit_ldc_inst(LdcConstant::Int(100000)).unwrap();
+    mw.visit_ldc_inst(LdcConstant::Long(1)).unwrap();
+    mw.visit_var_inst(opcodes::ILOAD, 1).unwrap();
+    mw.visit_var_inst(opcodes::ILOAD, 4).unwrap();
+    mw.visit_var_inst(opcodes::ISTORE, 300).unwrap();
+    mw.visit_var_inst(opcodes::RET, 2).unwrap();
+    mw.visit_iinc_inst(1, 1);
+    mw.visit_iinc_inst(300, 1);
+    mw.visit_field_inst(opcodes::GETSTATIC, "Main", "field", "I")
+      .unwrap();
+    mw.visit_method_inst(opcodes::INVOKESTATIC, "Main", "method", "()V", false)
+      .unwrap();
+    mw.visit_method_inst(
+      opcodes::INVOKEINTERFACE,
+      "java/util/List",
+      "size",
+      "()I",
+      true,
+    )
+    .unwrap();
+    mw.visit_type_inst(opcodes::CHECKCAST, "java/lang/String")
+      .unwrap();
+
+    for label in &mut labels {
+      mw.visit_label(label).unwrap();
+    }
+
+    let [label_0, label_1, label_2, label_3] = &mut labels;
+
+    mw.visit_jump_inst(opcodes::GOTO, label_0).unwrap();
+    mw.visit_table_switch_inst(0, 0, label_1, &mut [label_2])
+      .unwrap();
+    mw.visit_lookup_switch_inst(label_3, &mut [(0, label_0)])
+      .unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    let decoded = Instructions::new(&mw.code)
+      .map(|instruction| {
+        instruction.map(|instruction| (instruction.offset, instruction.opcode, instruction.len))
+      })
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+
+    // Offsets and lengths follow JVMS 6.5, tableswitch starts at an aligned
+    // offset hence has no padding while lookupswitch is padded by 3 bytes
+    assert_eq!(
+      decoded,
+      [
+        (0, opcodes::BIPUSH, 2),
+        (2, opcodes::SIPUSH, 3),
+        (5, opcodes::NEWARRAY, 2),
+        (7, opcodes::LDC, 2),
+        (9, opcodes::LDC2_W, 3),
+        (12, opcodes::ILOAD_1, 1),
+        (13, opcodes::ILOAD, 2),
+        (15, opcodes::WIDE, 4),
+        (19, opcodes::RET, 2),
+        (21, opcodes::IINC, 3),
+        (24, opcodes::WIDE, 6),
+        (30, opcodes::GETSTATIC, 3),
+        (33, opcodes::INVOKESTATIC, 3),
+        (36, opcodes::INVOKEINTERFACE, 5),
+        (41, opcodes::CHECKCAST, 3),
+        (44, opcodes::GOTO, 3),
+        (47, opcodes::TABLESWITCH, 17),
+        (64, opcodes::LOOKUPSWITCH, 20),
+        (84, opcodes::RETURN, 1),
+      ]
+    );
+    assert_eq!(mw.code.len(), 85);
+  }
+
+  #[test]
+  fn test_visit_maxs() {
+    let mut mw = method_writer();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_maxs(4, 2);
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((4, 2)));
+  }
+
+  #[test]
+  fn test_compute_maxs_ignores_visit_maxs() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(I)V");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_maxs(4, 2);
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((0, 1)));
+  }
+
+  #[test]
+  fn test_compute_maxs_nested_branches() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(J)V");
+    let mut else_label = Label::new();
+    let mut inner_else_label = Label::new();
+    let mut end_label = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::DCONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::DSTORE_3).unwrap();
+    mw.visit_simple_inst(opcodes::ICONST_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFEQ, &mut else_label).unwrap();
+    // if branch: 4 words
+    mw.visit_simple_inst(opcodes::DCONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::DCONST_1).unwrap();
+    mw.visit_simple_inst(opcodes::DADD).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_jump_inst(opcodes::GOTO, &mut end_label).unwrap();
+    mw.visit_label(&mut else_label).unwrap();
+    mw.visit_simple_inst(opcodes::ICONST_1).unwrap();
+    mw.visit_jump_inst(opcodes::IFNE, &mut inner_else_label)
+      .unwrap();
+    mw.visit_jump_inst(opcodes::GOTO, &mut end_label).unwrap();
+    mw.visit_label(&mut inner_else_label).unwrap();
+    // nested else branch: 6 words, only reachable through two branches
+    mw.visit_simple_inst(opcodes::LLOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_1).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_1).unwrap();
+    mw.visit_simple_inst(opcodes::LADD).unwrap();
+    mw.visit_simple_inst(opcodes::LADD).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_label(&mut end_label).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((6, 5)));
+  }
+
+  #[test]
+  fn test_compute_maxs_switch() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(I)V");
+    let mut default = Label::new();
+    let mut case_0 = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_table_switch_inst(0, 0, &mut default, &mut [&mut case_0])
+      .unwrap();
+    mw.visit_label(&mut case_0).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_label(&mut default).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((4, 1)));
+  }
+
+  #[test]
+  fn test_compute_maxs_constants() {
+    for (descriptor, constants, return_opcode, max_stack) in [
+      ("()F", &[opcodes::FCONST_0][..], opcodes::FRETURN, 1),
+      (
+        "()F",
+        &[opcodes::FCONST_1, opcodes::FCONST_2, opcodes::FADD],
+        opcodes::FRETURN,
+        2,
+      ),
+      ("()J", &[opcodes::LCONST_0][..], opcodes::LRETURN, 2),
+      (
+        "()J",
+        &[opcodes::LCONST_1, opcodes::LCONST_1, opcodes::LADD],
+        opcodes::LRETURN,
+        4,
+      ),
+      ("()D", &[opcodes::DCONST_1][..], opcodes::DRETURN, 2),
+      (
+        "()D",
+        &[opcodes::FCONST_1, opcodes::F2D],
+        opcodes::DRETURN,
+        2,
+      ),
+    ] {
+      let mut mw = method_writer_with(ComputeOption::Maxs, descriptor);
+
+      mw.visit_code();
+
+      for constant in constants {
+        mw.visit_simple_inst(*constant).unwrap();
+      }
+
+      mw.visit_simple_inst(return_opcode).unwrap();
+
+      assert_eq!(mw.maxs(&mw.code, &[]), Ok((max_stack, 0)), "{constants:?}");
+    }
+  }
+
+  #[test]
+  fn test_compute_maxs_ldc() {
+    // Sums an int, a float, a double and a long as a double
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()D");
+
+    mw.visit_code();
+    mw.visit_ldc_inst(LdcConstant::Int(100_000)).unwrap();
+    mw.visit_simple_inst(opcodes::I2D).unwrap();
+    mw.visit_ldc_inst(LdcConstant::Float(0.5)).unwrap();
+    mw.visit_simple_inst(opcodes::F2D).unwrap();
+    mw.visit_simple_inst(opcodes::DADD).unwrap();
+    mw.visit_ldc_inst(LdcConstant::Double(0.25)).unwrap();
+    mw.visit_simple_inst(opcodes::DADD).unwrap();
+    mw.visit_ldc_inst(LdcConstant::Long(1 << 40)).unwrap();
+    mw.visit_var_inst(opcodes::LSTORE, 0).unwrap();
+    mw.visit_var_inst(opcodes::LLOAD, 0).unwrap();
+    mw.visit_simple_inst(opcodes::L2D).unwrap();
+    mw.visit_simple_inst(opcodes::DADD).unwrap();
+    mw.visit_simple_inst(opcodes::DRETURN).unwrap();
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((4, 2)));
+  }
+
+  #[test]
+  fn test_compute_maxs_category_2_manipulation() {
+    // As javac emits `return this.value = value;` and `value++;` discarded
+    // for long values, pop2 and dup2 move a whole long
+    let mut mw = method_writer_with(ComputeOption::Maxs, "(J)J");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::LLOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::DUP2).unwrap();
+    mw.visit_simple_inst(opcodes::LCONST_1).unwrap();
+    mw.visit_simple_inst(opcodes::LADD).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_simple_inst(opcodes::DUP2).unwrap();
+    mw.visit_simple_inst(opcodes::LSTORE_2).unwrap();
+    mw.visit_simple_inst(opcodes::LRETURN).unwrap();
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((6, 4)));
+    assert!(mw.put_bytes(&mut Vec::new()).is_ok());
+
+    // pop2 also discards two category 1 values
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()V");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ICONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::FCONST_0).unwrap();
+    mw.visit_simple_inst(opcodes::SWAP).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(mw.maxs(&mw.code, &[]), Ok((2, 0)));
+  }
+
+  #[test]
+  fn test_compute_maxs_stack_underflow() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()V");
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::POP).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "()V",
+        KapiError::StackUnderflow {
+          offset: 0,
+          opcode: opcodes::POP,
+          stack_size: 0,
+          delta: -1,
+        },
+      ))
+    );
+  }
+
+  #[test]
+  fn test_debug_tables() {
+    let mut mw = method_writer();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_line_number(7, &start).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::POP).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_local_variable("i", "I", None, &start, &end, 0)
+      .unwrap();
+    mw.visit_local_variable(
+      "l",
+      "Ljava/util/List;",
+      Some("Ljava/util/List<*>;"),
+      &start,
+      &end,
+      1,
+    )
+    .unwrap();
+
+    let cp = mw.constant_pool.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let mut vec = Vec::new();
+
+    mw.put_code_attributes(&cp, &mut vec, None).unwrap();
+
+    let [lnt_0, lnt_1] = utf8("LineNumberTable");
+    let [lvt_0, lvt_1] = utf8("LocalVariableTable");
+    let [lvtt_0, lvtt_1] = utf8("LocalVariableTypeTable");
+    let [i_0, i_1] = utf8("i");
+    let [int_0, int_1] = utf8("I");
+    let [l_0, l_1] = utf8("l");
+    let [list_0, list_1] = utf8("Ljava/util/List;");
+    let [sig_0, sig_1] = utf8("Ljava/util/List<*>;");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      vec,
+      vec![
+        0, 3,
+        lnt_0, lnt_1, 0, 0, 0, 6, 0, 1,
+        0, 0, 0, 7,
+        lvt_0, lvt_1, 0, 0, 0, 22, 0, 2,
+        0, 0, 0, 3, i_0, i_1, int_0, int_1, 0, 0,
+        0, 0, 0, 3, l_0, l_1, list_0, list_1, 0, 1,
+        lvtt_0, lvtt_1, 0, 0, 0, 12, 0, 1,
+        0, 0, 0, 3, l_0, l_1, sig_0, sig_1, 0, 1,
+      ]
+    );
+    assert_eq!(mw.compute_code_attributes_size() as usize, vec.len() - 2);
+  }
+
+  #[test]
+  fn test_method_parameters() {
+    let mut mw = method_writer_with(ComputeOption::Nothing, "(ILjava/lang/String;J)V");
+
+    mw.visit_parameter(
+      Some("this$0"),
+      ParameterAccessFlag::Final | ParameterAccessFlag::Mandated,
+    )
+    .unwrap();
+    mw.visit_parameter(None, ParameterAccessFlag::Synthetic)
+      .unwrap();
+    mw.visit_parameter(Some("count"), ParameterAccessFlag::empty())
+      .unwrap();
+
+    let mut bytes = Vec::new();
+
+    mw.put_bytes(&mut bytes).unwrap();
+
+    let cp = mw.constant_pool.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [parameters_0, parameters_1] = utf8("MethodParameters");
+    let [outer_0, outer_1] = utf8("this$0");
+    let [count_0, count_1] = utf8("count");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      bytes[6..],
+      [
+        0, 1,
+        parameters_0, parameters_1, 0, 0, 0, 13, 3,
+        outer_0, outer_1, 0x80, 0x10,
+        0, 0, 0x10, 0x00,
+        count_0, count_1, 0x00, 0x00,
+      ]
+    );
+    assert_eq!(mw.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_method_parameters_count_mismatch() {
+    let mut mw = method_writer_with(ComputeOption::Nothing, "(IJ)V");
+
+    mw.visit_parameter(Some("count"), ParameterAccessFlag::empty())
+      .unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(IJ)V",
+        KapiError::StateError(String::from(
+          "MethodParameters has 1 parameters but descriptor (IJ)V declares 2"
+        )),
+      ))
+    );
+  }
+
+  #[test]
+  fn test_method_parameters_overflow() {
+    let mut mw = method_writer();
+
+    for _ in 0..u8::MAX {
+      mw.visit_parameter(None, ParameterAccessFlag::empty())
+        .unwrap();
+    }
+
+    assert!(matches!(
+      mw.visit_parameter(None, ParameterAccessFlag::empty()),
+      Err(KapiError::StateError(_))
+    ));
+  }
+
+  #[test]
+  fn test_debug_tables_unvisited_label() {
+    let mut mw = method_writer();
+    let start = Label::new();
+
+    mw.visit_code();
+
+    assert!(matches!(
+      mw.visit_line_number(1, &start),
+      Err(KapiError::StateError(_))
+    ));
+  }
+
+  #[test]
+  fn test_jump_labels() {
+    // Counts down to zero, both forward jumps share the same label
+    let mut mw = method_writer();
+    let mut begin = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFLT, &mut end).unwrap();
+    mw.visit_label(&mut begin).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFEQ, &mut end).unwrap();
+    mw.visit_iinc_inst(0, -1);
+    mw.visit_jump_inst(opcodes::GOTO, &mut begin).unwrap();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      mw.code,
+      [
+        opcodes::ILOAD_0,
+        opcodes::IFLT, 0, 13,
+        opcodes::ILOAD_0,
+        opcodes::IFEQ, 0, 9,
+        opcodes::IINC, 0, 0xFF,
+        opcodes::GOTO, 0xFF, 0xF9,
+        opcodes::RETURN,
+      ]
+    );
+    assert!(mw.put_bytes(&mut Vec::new()).is_ok());
+    assert!(matches!(
+      mw.visit_label(&mut end),
+      Err(KapiError::StateError(_))
+    ));
+  }
+
+  #[test]
+  fn test_unresolved_labels() {
+    let mut mw = method_writer();
+    let mut resolved = Label::new();
+    let mut unresolved = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFEQ, &mut unresolved).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_lookup_switch_inst(&mut unresolved, &mut [(1, &mut resolved)])
+      .unwrap();
+    mw.visit_label(&mut resolved).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::StateError(String::from(
+          "Instructions at bytecode offsets [1, 5] reference labels which are never visited"
+        )),
+      ))
+    );
+  }
+
+  #[test]
+  fn test_label_patching_out_of_bounds() {
+    let mut mw = method_writer();
+    let mut label = Label::new();
+
+    mw.visit_code();
+    mw.visit_jump_inst(opcodes::GOTO, &mut label).unwrap();
+    // Drops the pending jump operand to corrupt label bookkeeping
+    mw.code.truncate(1);
+
+    assert!(matches!(
+      mw.visit_label(&mut label),
+      Err(KapiError::StateError(_))
+    ));
+  }
+
+  #[test]
+  fn test_local_variable_ends_before_start() {
+    let mut mw = method_writer();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut start).unwrap();
+
+    assert!(matches!(
+      mw.visit_local_variable("i", "I", None, &start, &end, 0),
+      Err(KapiError::ArgError(_))
+    ));
+  }
+
+  #[test]
+  fn test_far_conditional_branch_inverted() {
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let mut mw = MethodWriter::new(
+      Rc::new(RefCell::new(ConstantPool::default())),
+      ComputeOption::Maxs,
+      Some(warnings.clone()),
+      MethodAccessFlag::Public | MethodAccessFlag::Static,
+      "test",
+      "(II)V",
+      None,
+      &[],
+    )
+    .unwrap();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_line_number(1, &start).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_1).unwrap();
+    mw.visit_jump_inst(opcodes::IF_ICMPGE, &mut end).unwrap();
+
+    for _ in 0..40000 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_line_number(2, &end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    let expanded_code = mw.expand_far_jumps().unwrap().unwrap();
+    let code = &expanded_code.code;
+
+    assert_eq!(code.len(), 40000 + 11);
+    assert_eq!(
+      &code[2..10],
+      &[opcodes::IF_ICMPLT, 0, 8, opcodes::GOTO_W, 0, 0, 0x9C, 0x45]
+    );
+    assert_eq!(code[40010], opcodes::RETURN);
+    assert_eq!(expanded_code.offset(40005), 40010);
+    assert_eq!(mw.maxs(code, &[]), Ok((2, 2)));
+    assert_eq!(
+      *warnings.borrow(),
+      vec![GenerationWarning::BranchInverted { pc: 2 }]
+    );
+
+    let mut bytes = Vec::new();
+
+    mw.put_bytes(&mut bytes).unwrap();
+
+    assert_eq!(mw.compute_size(), bytes.len());
+  }
+
+  #[test]
+  fn test_code_length_limit() {
+    let mut mw = method_writer();
+    let mut label = Label::new();
+
+    mw.visit_code();
+
+    for _ in 0..70000 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut label).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(
+      mw.visit_line_number(1, &label),
+      Err(KapiError::ArgError(String::from(
+        "Label at bytecode offset 70000 exceeds the limit of 65535"
+      )))
+    );
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::ArgError(String::from(
+          "Code of 70001 bytes exceeds the limit of 65535 bytes"
+        )),
+      ))
+    );
+
+    // Fits until the branch is expanded over a goto_w
+    let mut mw = method_writer();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFEQ, &mut end).unwrap();
+
+    for _ in 0..65528 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(mw.code.len(), 65533);
+    assert_eq!(
+      mw.put_bytes(&mut Vec::new()),
+      Err(in_method(
+        "(I)V",
+        KapiError::ArgError(String::from(
+          "Code of 65538 bytes exceeds the limit of 65535 bytes"
+        )),
+      ))
+    );
+  }
+
+  #[test]
+  fn test_try_catch_block() {
+    let mut mw = method_writer_with(ComputeOption::Maxs, "()V");
+    let mut start = Label::new();
+    let mut end = Label::new();
+    let mut handler = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_simple_inst(opcodes::NOP).unwrap();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut handler).unwrap();
+    // Handler is only reachable by exceptions, and starts with the thrown
+    // exception on operand stack
+    mw.visit_simple_inst(opcodes::DUP).unwrap();
+    mw.visit_simple_inst(opcodes::POP2).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_try_catch_block(&start, &end, &handler, Some("java/lang/Exception"))
+      .unwrap();
+    mw.visit_try_catch_block(&start, &end, &handler, None)
+      .unwrap();
+
+    assert!(matches!(
+      mw.visit_try_catch_block(&start, &start, &handler, None),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_try_catch_block(&end, &start, &handler, None),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      mw.visit_try_catch_block(&start, &end, &Label::new(), None),
+      Err(KapiError::StateError(_))
+    ));
+
+    let cp = mw.constant_pool.borrow();
+    let mut vec = Vec::new();
+
+    mw.put_code(&cp, &mut vec).unwrap();
+
+    let mut reader = ByteReader::new(&vec);
+
+    assert_eq!(reader.read_u16(), Ok(cp.get_utf8("Code").unwrap()));
+    assert_eq!(reader.read_u32(), Ok(reader.remaining() as u32));
+    assert_eq!(reader.read_u16(), Ok(2));
+    assert_eq!(reader.read_u16(), Ok(0));
+    assert_eq!(reader.read_u32(), Ok(5));
+
+    reader.skip(5).unwrap();
+
+    assert_eq!(reader.read_u16(), Ok(2));
+
+    for catch_type in [Some("java/lang/Exception"), None] {
+      assert_eq!(reader.read_u16(), Ok(0));
+      assert_eq!(reader.read_u16(), Ok(1));
+      assert_eq!(reader.read_u16(), Ok(2));
+      assert_eq!(
+        reader
+          .read_u16()
+          .map(|catch_type| cp.resolve_class_name(catch_type)),
+        Ok(catch_type)
+      );
+    }
+
+    assert_eq!(reader.read_u16(), Ok(0));
+    assert_eq!(reader.remaining(), 0);
+
+    drop(cp);
+
+    let mut vec = Vec::new();
+
+    mw.put_bytes(&mut vec).unwrap();
+
+    assert_eq!(vec.len(), mw.compute_size());
+  }
+
+  #[test]
+  fn test_try_catch_block_far_jumps() {
+    let mut mw = method_writer();
+    let mut start = Label::new();
+    let mut end = Label::new();
+    let mut handler = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_jump_inst(opcodes::GOTO, &mut end).unwrap();
+
+    for _ in 0..40000 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut handler).unwrap();
+    mw.visit_simple_inst(opcodes::ATHROW).unwrap();
+    mw.visit_try_catch_block(&start, &end, &handler, None)
+      .unwrap();
+
+    let cp = mw.constant_pool.borrow();
+    let mut vec = Vec::new();
+
+    mw.put_code(&cp, &mut vec).unwrap();
+
+    // Expanded GOTO_W moves the end of range and the handler by 2 bytes
+    let mut reader = ByteReader::new(&vec);
+
+    reader.skip(10).unwrap();
+
+    assert_eq!(reader.read_u32(), Ok(40007));
+
+    reader.skip(40007).unwrap();
+
+    assert_eq!(reader.read_u16(), Ok(1));
+    assert_eq!(reader.read_u16(), Ok(0));
+    assert_eq!(reader.read_u16(), Ok(40005));
+    assert_eq!(reader.read_u16(), Ok(40006));
+    assert_eq!(reader.read_u16(), Ok(0));
+  }
+
+  #[test]
+  fn test_try_catch_block_handler_out_of_code() {
+    let mut mw = method_writer();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_try_catch_block(&start, &end, &end, None).unwrap();
+
+    let cp = mw.constant_pool.borrow();
+
+    assert_eq!(
+      mw.put_code(&cp, &mut Vec::new()),
+      Err(KapiError::StateError(String::from(
+        "Exception handler at bytecode offset 1 covering 0..1 is not aligned to instructions"
+      )))
+    );
+  }
+
+  #[test]
+  fn test_resource_variable_annotation() {
+    let mut mw = method_writer();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_simple_inst(opcodes::NOP).unwrap();
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_local_variable_annotation(
+      LocalVariableTarget::ResourceVariable,
+      &[(&start, &end, 0)],
+      &[],
+      "LNonNull;",
+      true,
+    )
+    .unwrap();
+
+    let cp = mw.constant_pool.borrow();
+    let mut vec = Vec::new();
+
+    mw.put_code(&cp, &mut vec).unwrap();
+
+    let mut reader = ByteReader::new(&vec);
+
+    // Skips Code header and code
+    reader.skip(14 + 2).unwrap();
+
+    assert_eq!(reader.read_u16(), Ok(0));
+    assert_eq!(reader.read_u16(), Ok(1));
+    assert_eq!(
+      reader.read_u16(),
+      Ok(cp.get_utf8("RuntimeVisibleTypeAnnotations").unwrap())
+    );
+
+    reader.skip(4 + 2).unwrap();
+
+    assert_eq!(reader.read_u8(), Ok(0x41));
+    assert_eq!(reader.read_u16(), Ok(1));
+    assert_eq!(reader.read_u16(), Ok(0));
+    assert_eq!(reader.read_u16(), Ok(1));
+    assert_eq!(reader.read_u16(), Ok(0));
+  }
+
+  #[test]
+  fn test_code_attribute_length() {
+    let mut mw = method_writer();
+    let mut start = Label::new();
+    let mut end = Label::new();
+
+    mw.visit_code();
+    mw.visit_label(&mut start).unwrap();
+    mw.visit_line_number(1, &start).unwrap();
+    mw.visit_jump_inst(opcodes::GOTO, &mut end).unwrap();
+
+    for _ in 0..40000 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut end).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+    mw.visit_local_variable("i", "I", None, &start, &end, 0)
+      .unwrap();
+
+    let cp = mw.constant_pool.borrow();
+    let mut vec = Vec::new();
+
+    mw.put_code(&cp, &mut vec).unwrap();
+
+    // Expanded GOTO_W is 2 bytes longer than the visited GOTO
+    let mut reader = ByteReader::new(&vec);
+
+    assert_eq!(reader.read_u16(), Ok(cp.get_utf8("Code").unwrap()));
+    assert_eq!(reader.read_u32(), Ok(reader.remaining() as u32));
+
+    reader.skip(4).unwrap();
+
+    assert_eq!(reader.read_u32(), Ok(40006));
+
+    reader.skip(40006).unwrap();
+
+    assert_eq!(reader.read_u16(), Ok(0));
+    assert_eq!(reader.read_u16(), Ok(2));
+
+    for _ in 0..2 {
+      reader.skip(2).unwrap();
+
+      let len = reader.read_u32().unwrap() as usize;
+
+      reader.skip(len).unwrap();
+    }
+
+    assert_eq!(reader.remaining(), 0);
+  }
+
+  #[test]
+  fn test_far_jumps_cascade() {
+    let mut mw = method_writer();
+    let mut far = Label::new();
+    let mut near = Label::new();
+
+    mw.visit_code();
+    // Fits in 2 bytes until the inner branch gets expanded
+    mw.visit_jump_inst(opcodes::GOTO, &mut near).unwrap();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_jump_inst(opcodes::IFEQ, &mut far).unwrap();
+
+    for _ in 0..32760 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut near).unwrap();
+    mw.visit_simple_inst(opcodes::NOP).unwrap();
+
+    for _ in 0..10 {
+      mw.visit_simple_inst(opcodes::NOP).unwrap();
+    }
+
+    mw.visit_label(&mut far).unwrap();
+    mw.visit_simple_inst(opcodes::RETURN).unwrap();
+
+    assert_eq!(mw.far_jumps.len(), 1);
+
+    let expanded_code = mw.expand_far_jumps().unwrap().unwrap();
+    let code = &expanded_code.code;
+
+    assert_eq!(code[0], opcodes::GOTO_W);
+    assert_eq!(
+      i32::from_be_bytes([code[1], code[2], code[3], code[4]]),
+      expanded_code.offset(near.offset()) as i32
+    );
+    assert_eq!(&code[6..9], &[opcodes::IFNE, 0, 8]);
+    assert_eq!(code[9], opcodes::GOTO_W);
+    assert_eq!(
+      i32::from_be_bytes([code[10], code[11], code[12], code[13]]),
+      (expanded_code.offset(far.offset()) - 9) as i32
+    );
+    assert_eq!(expanded_code.inverted_branches, vec![6]);
+  }
 }
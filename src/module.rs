@@ -0,0 +1,409 @@
+use std::{
+  cell::RefCell,
+  rc::Rc,
+};
+
+use crate::{
+  access_flag::{
+    ExportsAccessFlag,
+    ModuleAccessFlag,
+    OpensAccessFlag,
+    RequiresAccessFlag,
+  },
+  attrs,
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    SizeComputable,
+    ToBytes,
+  },
+  constant::ConstantPool,
+  error::KapiResult,
+};
+
+pub trait ModuleVisitor {
+  fn inner(&mut self) -> Option<&mut dyn ModuleVisitor> {
+    None
+  }
+
+  fn visit_main_class(&mut self, main_class: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_main_class(main_class);
+    }
+  }
+
+  fn visit_package(&mut self, package: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_package(package);
+    }
+  }
+
+  fn visit_require(&mut self, module: &str, access: RequiresAccessFlag, version: Option<&str>) {
+    if let Some(inner) = self.inner() {
+      inner.visit_require(module, access, version);
+    }
+  }
+
+  fn visit_export(&mut self, package: &str, access: ExportsAccessFlag, modules: &[&str]) {
+    if let Some(inner) = self.inner() {
+      inner.visit_export(package, access, modules);
+    }
+  }
+
+  fn visit_open(&mut self, package: &str, access: OpensAccessFlag, modules: &[&str]) {
+    if let Some(inner) = self.inner() {
+      inner.visit_open(package, access, modules);
+    }
+  }
+
+  fn visit_use(&mut self, service: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_use(service);
+    }
+  }
+
+  fn visit_provide(&mut self, service: &str, providers: &[&str]) {
+    if let Some(inner) = self.inner() {
+      inner.visit_provide(service, providers);
+    }
+  }
+
+  fn visit_end(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_end();
+    }
+  }
+}
+
+/// Writes Module attribute, along with ModulePackages and ModuleMainClass
+/// attributes if any package or main class is visited.
+#[derive(Debug)]
+pub struct ModuleWriter {
+  constant_pool: Rc<RefCell<ConstantPool>>,
+  name_index: u16,
+  access: ModuleAccessFlag,
+  version_index: u16,
+  requires_count: u16,
+  requires: ByteVec,
+  exports_count: u16,
+  exports: ByteVec,
+  opens_count: u16,
+  opens: ByteVec,
+  uses: Vec<u16>,
+  provides_count: u16,
+  provides: ByteVec,
+  // Attribute ModulePackages
+  packages: Vec<u16>,
+  // Attribute ModuleMainClass
+  main_class: Option<u16>,
+}
+
+impl ModuleWriter {
+  pub(crate) fn new(
+    constant_pool: Rc<RefCell<ConstantPool>>,
+    name: &str,
+    access: ModuleAccessFlag,
+    version: Option<&str>,
+  ) -> KapiResult<Self> {
+    let mut cp = constant_pool.borrow_mut();
+
+    cp.put_utf8(attrs::MODULE)?;
+
+    let name_index = cp.put_module(name)?;
+    let version_index = version.map_or(Ok(0), |version| cp.put_utf8(version))?;
+
+    drop(cp);
+
+    Ok(Self {
+      constant_pool,
+      name_index,
+      access,
+      version_index,
+      requires_count: 0,
+      requires: ByteVec::new(),
+      exports_count: 0,
+      exports: ByteVec::new(),
+      opens_count: 0,
+      opens: ByteVec::new(),
+      uses: Vec::new(),
+      provides_count: 0,
+      provides: ByteVec::new(),
+      packages: Vec::new(),
+      main_class: None,
+    })
+  }
+}
+
+impl ModuleVisitor for ModuleWriter {
+  fn visit_main_class(&mut self, main_class: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::MODULE_MAIN_CLASS);
+    self.main_class = cp.put_class(main_class).ok();
+  }
+
+  fn visit_package(&mut self, package: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::MODULE_PACKAGES);
+    self.packages.extend(cp.put_package(package));
+  }
+
+  fn visit_require(&mut self, module: &str, access: RequiresAccessFlag, version: Option<&str>) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let (Ok(module), Ok(version)) = (
+      cp.put_module(module),
+      version.map_or(Ok(0), |version| cp.put_utf8(version)),
+    ) else {
+      return;
+    };
+
+    self
+      .requires
+      .push_u16(module)
+      .push_u16(access.bits())
+      .push_u16(version);
+    self.requires_count += 1;
+  }
+
+  fn visit_export(&mut self, package: &str, access: ExportsAccessFlag, modules: &[&str]) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let (Ok(package), Ok(modules)) = (
+      cp.put_package(package),
+      modules
+        .iter()
+        .map(|module| cp.put_module(module))
+        .collect::<KapiResult<Vec<_>>>(),
+    ) else {
+      return;
+    };
+
+    self
+      .exports
+      .push_u16(package)
+      .push_u16(access.bits())
+      .push_u16(modules.len() as u16);
+
+    for module in modules {
+      self.exports.push_u16(module);
+    }
+
+    self.exports_count += 1;
+  }
+
+  fn visit_open(&mut self, package: &str, access: OpensAccessFlag, modules: &[&str]) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let (Ok(package), Ok(modules)) = (
+      cp.put_package(package),
+      modules
+        .iter()
+        .map(|module| cp.put_module(module))
+        .collect::<KapiResult<Vec<_>>>(),
+    ) else {
+      return;
+    };
+
+    self
+      .opens
+      .push_u16(package)
+      .push_u16(access.bits())
+      .push_u16(modules.len() as u16);
+
+    for module in modules {
+      self.opens.push_u16(module);
+    }
+
+    self.opens_count += 1;
+  }
+
+  fn visit_use(&mut self, service: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    self.uses.extend(cp.put_class(service));
+  }
+
+  fn visit_provide(&mut self, service: &str, providers: &[&str]) {
+    let mut cp = self.constant_pool.borrow_mut();
+    let (Ok(service), Ok(providers)) = (
+      cp.put_class(service),
+      providers
+        .iter()
+        .map(|provider| cp.put_class(provider))
+        .collect::<KapiResult<Vec<_>>>(),
+    ) else {
+      return;
+    };
+
+    self
+      .provides
+      .push_u16(service)
+      .push_u16(providers.len() as u16);
+
+    for provider in providers {
+      self.provides.push_u16(provider);
+    }
+
+    self.provides_count += 1;
+  }
+}
+
+impl ToBytes for ModuleWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    let cp = self.constant_pool.borrow();
+
+    vec.push_u16(cp.get_utf8(attrs::MODULE).unwrap());
+
+    let module_attr_len = vec.reserve_slot(4);
+
+    vec
+      .push_u16(self.name_index)
+      .push_u16(self.access.bits())
+      .push_u16(self.version_index)
+      .push_u16(self.requires_count)
+      .push_u8s(&self.requires)
+      .push_u16(self.exports_count)
+      .push_u8s(&self.exports)
+      .push_u16(self.opens_count)
+      .push_u8s(&self.opens)
+      .push_u16(self.uses.len() as u16);
+
+    for use_index in &self.uses {
+      vec.push_u16(*use_index);
+    }
+
+    vec.push_u16(self.provides_count).push_u8s(&self.provides);
+    vec.patch_len(module_attr_len)?;
+
+    if !self.packages.is_empty() {
+      vec
+        .push_u16(cp.get_utf8(attrs::MODULE_PACKAGES).unwrap())
+        .push_u32(2 + 2 * self.packages.len() as u32)
+        .push_u16(self.packages.len() as u16);
+
+      for package in &self.packages {
+        vec.push_u16(*package);
+      }
+    }
+
+    if let Some(main_class) = self.main_class {
+      vec
+        .push_u16(cp.get_utf8(attrs::MODULE_MAIN_CLASS).unwrap())
+        .push_u32(2)
+        .push_u16(main_class);
+    }
+
+    Ok(())
+  }
+}
+
+impl SizeComputable for ModuleWriter {
+  fn compute_size(&self) -> usize {
+    let mut size = 22
+      + self.requires.len()
+      + self.exports.len()
+      + self.opens.len()
+      + 2 * self.uses.len()
+      + self.provides.len();
+
+    if !self.packages.is_empty() {
+      size += 8 + 2 * self.packages.len();
+    }
+
+    if self.main_class.is_some() {
+      size += 8;
+    }
+
+    size
+  }
+
+  fn attributes_count(&self) -> usize {
+    let mut count = 1;
+
+    if !self.packages.is_empty() {
+      count += 1;
+    }
+
+    if self.main_class.is_some() {
+      count += 1;
+    }
+
+    count
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    cell::RefCell,
+    rc::Rc,
+  };
+
+  use crate::{
+    access_flag::{
+      ExportsAccessFlag,
+      ModuleAccessFlag,
+      OpensAccessFlag,
+      RequiresAccessFlag,
+    },
+    byte_vec::{
+      SizeComputable,
+      ToBytes,
+    },
+    constant::ConstantPool,
+    module::{
+      ModuleVisitor,
+      ModuleWriter,
+    },
+  };
+
+  #[test]
+  fn test_module_attributes() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+    let mut mw = ModuleWriter::new(cp.clone(), "app", ModuleAccessFlag::Open, Some("1.0")).unwrap();
+
+    mw.visit_require("java.base", RequiresAccessFlag::Mandated, None);
+    mw.visit_export("app/api", ExportsAccessFlag::empty(), &["lib"]);
+    mw.visit_open("app/impl", OpensAccessFlag::empty(), &[]);
+    mw.visit_use("app/api/Service");
+    mw.visit_provide("app/api/Service", &["app/impl/ServiceImpl"]);
+    mw.visit_package("app/api");
+    mw.visit_main_class("app/Main");
+    mw.visit_end();
+
+    let mut bytes = Vec::new();
+
+    mw.put_bytes(&mut bytes).unwrap();
+
+    let mut cp = cp.borrow_mut();
+    let [app_0, app_1] = cp.put_module("app").unwrap().to_be_bytes();
+    let [version_0, version_1] = cp.put_utf8("1.0").unwrap().to_be_bytes();
+    let [base_0, base_1] = cp.put_module("java.base").unwrap().to_be_bytes();
+    let [api_0, api_1] = cp.put_package("app/api").unwrap().to_be_bytes();
+    let [lib_0, lib_1] = cp.put_module("lib").unwrap().to_be_bytes();
+    let [impl_0, impl_1] = cp.put_package("app/impl").unwrap().to_be_bytes();
+    let [service_0, service_1] = cp.put_class("app/api/Service").unwrap().to_be_bytes();
+    let [provider_0, provider_1] = cp.put_class("app/impl/ServiceImpl").unwrap().to_be_bytes();
+    let [main_0, main_1] = cp.put_class("app/Main").unwrap().to_be_bytes();
+    let [module_0, module_1] = cp.get_utf8("Module").unwrap().to_be_bytes();
+    let [packages_0, packages_1] = cp.get_utf8("ModulePackages").unwrap().to_be_bytes();
+    let [main_class_0, main_class_1] = cp.get_utf8("ModuleMainClass").unwrap().to_be_bytes();
+
+    #[rustfmt::skip]
+    assert_eq!(
+      bytes,
+      vec![
+        module_0, module_1, 0, 0, 0, 44,
+        app_0, app_1, 0x00, 0x20, version_0, version_1,
+        0, 1, base_0, base_1, 0x80, 0x00, 0, 0,
+        0, 1, api_0, api_1, 0, 0, 0, 1, lib_0, lib_1,
+        0, 1, impl_0, impl_1, 0, 0, 0, 0,
+        0, 1, service_0, service_1,
+        0, 1, service_0, service_1, 0, 1, provider_0, provider_1,
+        packages_0, packages_1, 0, 0, 0, 4, 0, 1, api_0, api_1,
+        main_class_0, main_class_1, 0, 0, 0, 2, main_0, main_1,
+      ]
+    );
+    assert_eq!(mw.compute_size(), bytes.len());
+    assert_eq!(mw.attributes_count(), 3);
+  }
+}
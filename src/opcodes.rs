@@ -200,3 +200,13 @@ pub const IFNULL: u8 = 198;
 pub const IFNONNULL: u8 = 199;
 pub const GOTO_W: u8 = 200;
 pub const JSR_W: u8 = 201;
+
+// Array type codes of newarray
+pub const T_BOOLEAN: u8 = 4;
+pub const T_CHAR: u8 = 5;
+pub const T_FLOAT: u8 = 6;
+pub const T_DOUBLE: u8 = 7;
+pub const T_BYTE: u8 = 8;
+pub const T_SHORT: u8 = 9;
+pub const T_INT: u8 = 10;
+pub const T_LONG: u8 = 11;
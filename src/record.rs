@@ -0,0 +1,358 @@
+use std::{
+  cell::RefCell,
+  rc::Rc,
+};
+
+use crate::{
+  annotation::{
+    compute_annotations_size,
+    compute_type_annotations_size,
+    put_annotations,
+    put_type_annotations,
+    AnnotationVisitor,
+    AnnotationWriter,
+    TargetInfo,
+    TypeAnnotationWriter,
+    TypePathStep,
+  },
+  attrs,
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    SizeComputable,
+    ToBytes,
+  },
+  constant::ConstantPool,
+  error::{
+    KapiError,
+    KapiResult,
+  },
+};
+
+pub trait RecordComponentVisitor {
+  fn inner(&mut self) -> Option<&mut dyn RecordComponentVisitor> {
+    None
+  }
+
+  fn visit_signature(&mut self, signature: &str) {
+    if let Some(inner) = self.inner() {
+      inner.visit_signature(signature);
+    }
+  }
+
+  fn visit_annotation(
+    &mut self,
+    descriptor: &str,
+    visible: bool,
+  ) -> Option<&mut dyn AnnotationVisitor> {
+    if let Some(inner) = self.inner() {
+      inner.visit_annotation(descriptor, visible)
+    } else {
+      None
+    }
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    if let Some(inner) = self.inner() {
+      inner.visit_type_annotation(target_info, type_path, descriptor, visible)
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn visit_end(&mut self) {
+    if let Some(inner) = self.inner() {
+      inner.visit_end();
+    }
+  }
+}
+
+/// Writes a record_component entry of Record attribute, see JVMS 4.7.30.
+#[derive(Debug)]
+pub struct RecordComponentWriter {
+  constant_pool: Rc<RefCell<ConstantPool>>,
+  name_index: u16,
+  descriptor_index: u16,
+  // Attribute Signature
+  signature_index: Option<u16>,
+  // Attribute RuntimeVisibleAnnotations
+  visible_annotations: Vec<AnnotationWriter>,
+  // Attribute RuntimeInvisibleAnnotations
+  invisible_annotations: Vec<AnnotationWriter>,
+  // Attribute RuntimeVisibleTypeAnnotations
+  visible_type_annotations: Vec<TypeAnnotationWriter>,
+  // Attribute RuntimeInvisibleTypeAnnotations
+  invisible_type_annotations: Vec<TypeAnnotationWriter>,
+}
+
+impl RecordComponentWriter {
+  pub(crate) fn new(
+    constant_pool: Rc<RefCell<ConstantPool>>,
+    name: &str,
+    descriptor: &str,
+    signature: Option<&str>,
+  ) -> KapiResult<Self> {
+    let mut cp = constant_pool.borrow_mut();
+    let name_index = cp.put_utf8(name)?;
+    let descriptor_index = cp.put_utf8(descriptor)?;
+
+    drop(cp);
+
+    let mut rcw = Self {
+      constant_pool,
+      name_index,
+      descriptor_index,
+      signature_index: None,
+      visible_annotations: Vec::new(),
+      invisible_annotations: Vec::new(),
+      visible_type_annotations: Vec::new(),
+      invisible_type_annotations: Vec::new(),
+    };
+
+    if let Some(signature) = signature {
+      rcw.visit_signature(signature);
+    }
+
+    Ok(rcw)
+  }
+}
+
+impl RecordComponentVisitor for RecordComponentWriter {
+  fn visit_signature(&mut self, signature: &str) {
+    let mut cp = self.constant_pool.borrow_mut();
+
+    let _ = cp.put_utf8(attrs::SIGNATURE);
+    self.signature_index = cp.put_utf8(signature).ok();
+  }
+
+  fn visit_annotation(
+    &mut self,
+    descriptor: &str,
+    visible: bool,
+  ) -> Option<&mut dyn AnnotationVisitor> {
+    let annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_ANNOTATIONS)
+        .ok()?;
+      &mut self.visible_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_ANNOTATIONS)
+        .ok()?;
+      &mut self.invisible_annotations
+    };
+
+    annotations.push(AnnotationWriter::new(self.constant_pool.clone(), descriptor).ok()?);
+
+    annotations
+      .last_mut()
+      .map(|aw| aw as &mut dyn AnnotationVisitor)
+  }
+
+  fn visit_type_annotation(
+    &mut self,
+    target_info: TargetInfo,
+    type_path: &[TypePathStep],
+    descriptor: &str,
+    visible: bool,
+  ) -> KapiResult<Option<&mut dyn AnnotationVisitor>> {
+    // Record components share type annotation targets with fields
+    if !target_info.is_field_target() {
+      return Err(KapiError::ArgError(format!(
+        "Type annotation target {target_info:?} is not applicable to record component"
+      )));
+    }
+
+    let type_annotations = if visible {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.visible_type_annotations
+    } else {
+      self
+        .constant_pool
+        .borrow_mut()
+        .put_utf8(attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS)?;
+      &mut self.invisible_type_annotations
+    };
+
+    type_annotations.push(TypeAnnotationWriter::new(
+      self.constant_pool.clone(),
+      target_info,
+      type_path,
+      descriptor,
+    )?);
+
+    Ok(
+      type_annotations
+        .last_mut()
+        .map(|taw| taw as &mut dyn AnnotationVisitor),
+    )
+  }
+}
+
+impl ToBytes for RecordComponentWriter {
+  fn put_bytes(&self, vec: &mut ByteVec) -> KapiResult<()> {
+    let cp = self.constant_pool.borrow();
+
+    vec
+      .push_u16(self.name_index)
+      .push_u16(self.descriptor_index)
+      .push_u16(self.attributes_count() as u16);
+
+    if let Some(signature_index) = self.signature_index {
+      vec
+        .push_u16(cp.get_utf8(attrs::SIGNATURE).unwrap())
+        .push_u32(2)
+        .push_u16(signature_index);
+    }
+
+    put_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_ANNOTATIONS,
+      &self.visible_annotations,
+    )?;
+    put_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_ANNOTATIONS,
+      &self.invisible_annotations,
+    )?;
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_VISIBLE_TYPE_ANNOTATIONS,
+      &self.visible_type_annotations,
+      &Ok,
+    )?;
+    put_type_annotations(
+      &cp,
+      vec,
+      attrs::RUNTIME_INVISIBLE_TYPE_ANNOTATIONS,
+      &self.invisible_type_annotations,
+      &Ok,
+    )?;
+
+    Ok(())
+  }
+}
+
+impl SizeComputable for RecordComponentWriter {
+  fn compute_size(&self) -> usize {
+    let mut size = 6;
+
+    if self.signature_index.is_some() {
+      size += 8;
+    }
+
+    size += compute_annotations_size(&self.visible_annotations);
+    size += compute_annotations_size(&self.invisible_annotations);
+    size += compute_type_annotations_size(&self.visible_type_annotations);
+    size += compute_type_annotations_size(&self.invisible_type_annotations);
+
+    size
+  }
+
+  fn attributes_count(&self) -> usize {
+    let mut count = 0;
+
+    if self.signature_index.is_some() {
+      count += 1;
+    }
+
+    if !self.visible_annotations.is_empty() {
+      count += 1;
+    }
+
+    if !self.invisible_annotations.is_empty() {
+      count += 1;
+    }
+
+    if !self.visible_type_annotations.is_empty() {
+      count += 1;
+    }
+
+    if !self.invisible_type_annotations.is_empty() {
+      count += 1;
+    }
+
+    count
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    cell::RefCell,
+    rc::Rc,
+  };
+
+  use crate::{
+    byte_vec::{
+      SizeComputable,
+      ToBytes,
+    },
+    constant::ConstantPool,
+    record::{
+      RecordComponentVisitor,
+      RecordComponentWriter,
+    },
+  };
+
+  #[test]
+  fn test_record_component_attributes() {
+    let cp = Rc::new(RefCell::new(ConstantPool::default()));
+    let mut rcw = RecordComponentWriter::new(
+      cp.clone(),
+      "values",
+      "Ljava/util/List;",
+      Some("Ljava/util/List<Ljava/lang/String;>;"),
+    )
+    .unwrap();
+
+    rcw
+      .visit_annotation("LNonNull;", false)
+      .unwrap()
+      .visit_end();
+    rcw.visit_end();
+
+    let mut bytes = Vec::new();
+
+    rcw.put_bytes(&mut bytes).unwrap();
+
+    let cp = cp.borrow();
+    let utf8 = |str| cp.get_utf8(str).unwrap().to_be_bytes();
+    let [name_0, name_1] = utf8("values");
+    let [descriptor_0, descriptor_1] = utf8("Ljava/util/List;");
+    let [signature_0, signature_1] = utf8("Signature");
+    let [signature_value_0, signature_value_1] = utf8("Ljava/util/List<Ljava/lang/String;>;");
+    let [annotations_0, annotations_1] = utf8("RuntimeInvisibleAnnotations");
+    let [type_0, type_1] = utf8("LNonNull;");
+
+    #[rustfmt::skip]
+    assert_eq!(
+      bytes,
+      vec![
+        name_0, name_1,
+        descriptor_0, descriptor_1,
+        0, 2,
+        signature_0, signature_1, 0, 0, 0, 2, signature_value_0, signature_value_1,
+        annotations_0, annotations_1, 0, 0, 0, 6, 0, 1,
+        type_0, type_1, 0, 0,
+      ]
+    );
+    assert_eq!(rcw.compute_size(), bytes.len());
+  }
+}
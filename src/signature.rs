@@ -0,0 +1,818 @@
+use std::fmt::{
+  Display,
+  Formatter,
+};
+
+use crate::{
+  error::{
+    KapiError,
+    KapiResult,
+  },
+  types::Type,
+};
+
+/// A generic class signature, see JVMS 4.7.9.1.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClassSignature {
+  pub type_parameters: Vec<TypeParameter>,
+  pub super_class: ClassTypeSignature,
+  pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// A generic method signature, see JVMS 4.7.9.1.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodSignature {
+  pub type_parameters: Vec<TypeParameter>,
+  pub parameters: Vec<TypeSignature>,
+  pub return_type: TypeSignature,
+  /// Thrown class types or type variables.
+  pub throws: Vec<TypeSignature>,
+}
+
+/// A formal type parameter, e.g. `T:Ljava/lang/Object;`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeParameter {
+  pub name: String,
+  /// Absent when the type parameter is bounded by interfaces only.
+  pub class_bound: Option<TypeSignature>,
+  pub interface_bounds: Vec<TypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeSignature {
+  /// A primitive type, or [Type::Void] as method return type.
+  Base(Type),
+  Class(ClassTypeSignature),
+  /// A type variable of given name, e.g. `TT;`.
+  TypeVariable(String),
+  Array(Box<TypeSignature>),
+}
+
+/// A class type with type arguments, e.g. `Ljava/util/Map<TK;TV;>.Entry;`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClassTypeSignature {
+  /// Internal name of the outermost class, including its package.
+  pub name: String,
+  pub type_arguments: Vec<TypeArgument>,
+  /// Inner classes following the outermost class, separated by `.`.
+  pub inner_classes: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimpleClassTypeSignature {
+  pub name: String,
+  pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeArgument {
+  /// An unbounded wildcard `*`.
+  Wildcard,
+  Exact(TypeSignature),
+  /// A wildcard bounded by `+`, i.e. `? extends`.
+  Extends(TypeSignature),
+  /// A wildcard bounded by `-`, i.e. `? super`.
+  Super(TypeSignature),
+}
+
+/// Parses a class signature, e.g. `<T:Ljava/lang/Object;>Ljava/lang/Object;`.
+pub fn parse_class_signature(signature: &str) -> KapiResult<ClassSignature> {
+  let mut parser = SignatureParser::new(signature);
+  let type_parameters = parser.type_parameters()?;
+  let super_class = parser.class_type()?;
+  let mut interfaces = Vec::new();
+
+  while !parser.is_end() {
+    interfaces.push(parser.class_type()?);
+  }
+
+  Ok(ClassSignature {
+    type_parameters,
+    super_class,
+    interfaces,
+  })
+}
+
+/// Parses a method signature, e.g. `<T:Ljava/lang/Object;>(TT;)TT;`.
+pub fn parse_method_signature(signature: &str) -> KapiResult<MethodSignature> {
+  let mut parser = SignatureParser::new(signature);
+  let type_parameters = parser.type_parameters()?;
+  let mut parameters = Vec::new();
+
+  parser.expect('(')?;
+
+  while !parser.eat(')') {
+    parameters.push(parser.java_type()?);
+  }
+
+  let return_type = if parser.eat('V') {
+    TypeSignature::Base(Type::Void)
+  } else {
+    parser.java_type()?
+  };
+  let mut throws = Vec::new();
+
+  while parser.eat('^') {
+    throws.push(match parser.peek() {
+      Some('T') => parser.type_variable()?,
+      _ => TypeSignature::Class(parser.class_type()?),
+    });
+  }
+
+  parser.expect_end()?;
+
+  Ok(MethodSignature {
+    type_parameters,
+    parameters,
+    return_type,
+    throws,
+  })
+}
+
+/// Parses a field signature, which is a reference type signature, e.g.
+/// `Ljava/util/List<Ljava/lang/String;>;`.
+pub fn parse_field_signature(signature: &str) -> KapiResult<TypeSignature> {
+  let mut parser = SignatureParser::new(signature);
+  let field_type = parser.reference_type()?;
+
+  parser.expect_end()?;
+
+  Ok(field_type)
+}
+
+struct SignatureParser<'a> {
+  signature: &'a str,
+  offset: usize,
+}
+
+impl<'a> SignatureParser<'a> {
+  fn new(signature: &'a str) -> Self {
+    Self {
+      signature,
+      offset: 0,
+    }
+  }
+
+  fn error(&self, reason: &str) -> KapiError {
+    KapiError::ArgError(format!(
+      "Malformed signature `{}` at {}: {reason}",
+      self.signature, self.offset
+    ))
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.signature[self.offset..].chars().next()
+  }
+
+  fn is_end(&self) -> bool {
+    self.offset == self.signature.len()
+  }
+
+  fn eat(&mut self, expected: char) -> bool {
+    if self.peek() == Some(expected) {
+      self.offset += expected.len_utf8();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, expected: char) -> KapiResult<()> {
+    if self.eat(expected) {
+      Ok(())
+    } else {
+      Err(self.error(&format!("expected `{expected}`")))
+    }
+  }
+
+  fn expect_end(&self) -> KapiResult<()> {
+    if self.is_end() {
+      Ok(())
+    } else {
+      Err(self.error("unexpected trailing characters"))
+    }
+  }
+
+  /// Reads an identifier, which must not contain any of `.;[/<>:`.
+  fn identifier(&mut self) -> KapiResult<&'a str> {
+    let rest = &self.signature[self.offset..];
+    let len = rest
+      .find(['.', ';', '[', '/', '<', '>', ':'])
+      .unwrap_or(rest.len());
+
+    if len == 0 {
+      return Err(self.error("expected identifier"));
+    }
+
+    self.offset += len;
+
+    Ok(&rest[..len])
+  }
+
+  fn type_parameters(&mut self) -> KapiResult<Vec<TypeParameter>> {
+    let mut type_parameters = Vec::new();
+
+    if !self.eat('<') {
+      return Ok(type_parameters);
+    }
+
+    loop {
+      let name = self.identifier()?.to_owned();
+
+      self.expect(':')?;
+
+      let class_bound = match self.peek() {
+        Some(':') => None,
+        _ => Some(self.reference_type()?),
+      };
+      let mut interface_bounds = Vec::new();
+
+      while self.eat(':') {
+        interface_bounds.push(self.reference_type()?);
+      }
+
+      type_parameters.push(TypeParameter {
+        name,
+        class_bound,
+        interface_bounds,
+      });
+
+      if self.eat('>') {
+        return Ok(type_parameters);
+      }
+    }
+  }
+
+  fn java_type(&mut self) -> KapiResult<TypeSignature> {
+    let base_type = match self.peek() {
+      Some('Z') => Type::Boolean,
+      Some('C') => Type::Char,
+      Some('B') => Type::Byte,
+      Some('S') => Type::Short,
+      Some('I') => Type::Int,
+      Some('F') => Type::Float,
+      Some('J') => Type::Long,
+      Some('D') => Type::Double,
+      _ => return self.reference_type(),
+    };
+
+    self.offset += 1;
+
+    Ok(TypeSignature::Base(base_type))
+  }
+
+  fn reference_type(&mut self) -> KapiResult<TypeSignature> {
+    match self.peek() {
+      Some('L') => Ok(TypeSignature::Class(self.class_type()?)),
+      Some('T') => self.type_variable(),
+      Some('[') => {
+        self.offset += 1;
+
+        Ok(TypeSignature::Array(Box::new(self.java_type()?)))
+      }
+      _ => Err(self.error("expected reference type")),
+    }
+  }
+
+  fn type_variable(&mut self) -> KapiResult<TypeSignature> {
+    self.expect('T')?;
+
+    let name = self.identifier()?.to_owned();
+
+    self.expect(';')?;
+
+    Ok(TypeSignature::TypeVariable(name))
+  }
+
+  fn class_type(&mut self) -> KapiResult<ClassTypeSignature> {
+    self.expect('L')?;
+
+    let mut name = self.identifier()?.to_owned();
+
+    while self.eat('/') {
+      name.push('/');
+      name.push_str(self.identifier()?);
+    }
+
+    let type_arguments = self.type_arguments()?;
+    let mut inner_classes = Vec::new();
+
+    while self.eat('.') {
+      let name = self.identifier()?.to_owned();
+      let type_arguments = self.type_arguments()?;
+
+      inner_classes.push(SimpleClassTypeSignature {
+        name,
+        type_arguments,
+      });
+    }
+
+    self.expect(';')?;
+
+    Ok(ClassTypeSignature {
+      name,
+      type_arguments,
+      inner_classes,
+    })
+  }
+
+  fn type_arguments(&mut self) -> KapiResult<Vec<TypeArgument>> {
+    let mut type_arguments = Vec::new();
+
+    if !self.eat('<') {
+      return Ok(type_arguments);
+    }
+
+    loop {
+      type_arguments.push(if self.eat('*') {
+        TypeArgument::Wildcard
+      } else if self.eat('+') {
+        TypeArgument::Extends(self.reference_type()?)
+      } else if self.eat('-') {
+        TypeArgument::Super(self.reference_type()?)
+      } else {
+        TypeArgument::Exact(self.reference_type()?)
+      });
+
+      if self.eat('>') {
+        return Ok(type_arguments);
+      }
+    }
+  }
+}
+
+impl ClassSignature {
+  /// Checks the signature is well-formed per JVMS 4.7.9.1, since fields can
+  /// be freely constructed and [Display] renders them unconditionally.
+  pub fn validate(&self) -> KapiResult<()> {
+    validate_type_parameters(&self.type_parameters)?;
+    self.super_class.validate()?;
+
+    for interface in &self.interfaces {
+      interface.validate()?;
+    }
+
+    Ok(())
+  }
+}
+
+impl MethodSignature {
+  /// Checks the signature is well-formed per JVMS 4.7.9.1.
+  pub fn validate(&self) -> KapiResult<()> {
+    validate_type_parameters(&self.type_parameters)?;
+
+    for parameter in &self.parameters {
+      parameter.validate_java_type()?;
+    }
+
+    if self.return_type != TypeSignature::Base(Type::Void) {
+      self.return_type.validate_java_type()?;
+    }
+
+    for throw in &self.throws {
+      match throw {
+        TypeSignature::Class(class_type) => class_type.validate()?,
+        TypeSignature::TypeVariable(name) => validate_identifier(name)?,
+        _ => {
+          return Err(invalid_signature(&format!(
+            "thrown type {throw} is neither a class type nor a type variable"
+          )))
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl TypeSignature {
+  /// Checks the signature is a well-formed field signature, which must be a
+  /// reference type, per JVMS 4.7.9.1.
+  pub fn validate(&self) -> KapiResult<()> {
+    match self {
+      TypeSignature::Base(base_type) => Err(invalid_signature(&format!(
+        "{} is not a reference type",
+        base_type.descriptor()
+      ))),
+      TypeSignature::Class(class_type) => class_type.validate(),
+      TypeSignature::TypeVariable(name) => validate_identifier(name),
+      TypeSignature::Array(component_type) => component_type.validate_java_type(),
+    }
+  }
+
+  fn validate_java_type(&self) -> KapiResult<()> {
+    match self {
+      TypeSignature::Base(base_type) if base_type.is_primitive() => Ok(()),
+      _ => self.validate(),
+    }
+  }
+}
+
+impl ClassTypeSignature {
+  fn validate(&self) -> KapiResult<()> {
+    for segment in self.name.split('/') {
+      validate_identifier(segment)?;
+    }
+
+    validate_type_arguments(&self.type_arguments)?;
+
+    for inner_class in &self.inner_classes {
+      validate_identifier(&inner_class.name)?;
+      validate_type_arguments(&inner_class.type_arguments)?;
+    }
+
+    Ok(())
+  }
+}
+
+fn validate_type_parameters(type_parameters: &[TypeParameter]) -> KapiResult<()> {
+  for type_parameter in type_parameters {
+    validate_identifier(&type_parameter.name)?;
+
+    if let Some(class_bound) = &type_parameter.class_bound {
+      class_bound.validate()?;
+    } else if type_parameter.interface_bounds.is_empty() {
+      return Err(invalid_signature(&format!(
+        "type parameter {} has no bound",
+        type_parameter.name
+      )));
+    }
+
+    for interface_bound in &type_parameter.interface_bounds {
+      interface_bound.validate()?;
+    }
+  }
+
+  Ok(())
+}
+
+fn validate_type_arguments(type_arguments: &[TypeArgument]) -> KapiResult<()> {
+  for type_argument in type_arguments {
+    match type_argument {
+      TypeArgument::Wildcard => {}
+      TypeArgument::Exact(type_signature)
+      | TypeArgument::Extends(type_signature)
+      | TypeArgument::Super(type_signature) => type_signature.validate()?,
+    }
+  }
+
+  Ok(())
+}
+
+/// Checks an identifier is non-empty and contains none of `.;[/<>:`.
+fn validate_identifier(identifier: &str) -> KapiResult<()> {
+  if identifier.is_empty() {
+    return Err(invalid_signature("empty identifier"));
+  }
+
+  if let Some(char) = identifier
+    .chars()
+    .find(|char| matches!(char, '.' | ';' | '[' | '/' | '<' | '>' | ':'))
+  {
+    return Err(invalid_signature(&format!(
+      "identifier `{identifier}` contains illegal character `{char}`"
+    )));
+  }
+
+  Ok(())
+}
+
+fn invalid_signature(reason: &str) -> KapiError {
+  KapiError::ArgError(format!("Invalid signature: {reason}"))
+}
+
+fn fmt_type_parameters(
+  f: &mut Formatter<'_>,
+  type_parameters: &[TypeParameter],
+) -> std::fmt::Result {
+  if type_parameters.is_empty() {
+    return Ok(());
+  }
+
+  write!(f, "<")?;
+
+  for type_parameter in type_parameters {
+    write!(f, "{type_parameter}")?;
+  }
+
+  write!(f, ">")
+}
+
+fn fmt_type_arguments(f: &mut Formatter<'_>, type_arguments: &[TypeArgument]) -> std::fmt::Result {
+  if type_arguments.is_empty() {
+    return Ok(());
+  }
+
+  write!(f, "<")?;
+
+  for type_argument in type_arguments {
+    write!(f, "{type_argument}")?;
+  }
+
+  write!(f, ">")
+}
+
+impl Display for ClassSignature {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fmt_type_parameters(f, &self.type_parameters)?;
+    write!(f, "{}", self.super_class)?;
+
+    for interface in &self.interfaces {
+      write!(f, "{interface}")?;
+    }
+
+    Ok(())
+  }
+}
+
+impl Display for MethodSignature {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fmt_type_parameters(f, &self.type_parameters)?;
+    write!(f, "(")?;
+
+    for parameter in &self.parameters {
+      write!(f, "{parameter}")?;
+    }
+
+    write!(f, "){}", self.return_type)?;
+
+    for throw in &self.throws {
+      write!(f, "^{throw}")?;
+    }
+
+    Ok(())
+  }
+}
+
+impl Display for TypeParameter {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}:", self.name)?;
+
+    if let Some(class_bound) = &self.class_bound {
+      write!(f, "{class_bound}")?;
+    }
+
+    for interface_bound in &self.interface_bounds {
+      write!(f, ":{interface_bound}")?;
+    }
+
+    Ok(())
+  }
+}
+
+impl Display for TypeSignature {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TypeSignature::Base(base_type) => write!(f, "{}", base_type.descriptor()),
+      TypeSignature::Class(class_type) => write!(f, "{class_type}"),
+      TypeSignature::TypeVariable(name) => write!(f, "T{name};"),
+      TypeSignature::Array(component_type) => write!(f, "[{component_type}"),
+    }
+  }
+}
+
+impl Display for ClassTypeSignature {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "L{}", self.name)?;
+    fmt_type_arguments(f, &self.type_arguments)?;
+
+    for inner_class in &self.inner_classes {
+      write!(f, ".{}", inner_class.name)?;
+      fmt_type_arguments(f, &inner_class.type_arguments)?;
+    }
+
+    write!(f, ";")
+  }
+}
+
+impl Display for TypeArgument {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TypeArgument::Wildcard => write!(f, "*"),
+      TypeArgument::Exact(type_signature) => write!(f, "{type_signature}"),
+      TypeArgument::Extends(type_signature) => write!(f, "+{type_signature}"),
+      TypeArgument::Super(type_signature) => write!(f, "-{type_signature}"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    error::KapiError,
+    signature::{
+      parse_class_signature,
+      parse_field_signature,
+      parse_method_signature,
+      ClassSignature,
+      ClassTypeSignature,
+      MethodSignature,
+      TypeArgument,
+      TypeParameter,
+      TypeSignature,
+    },
+    types::Type,
+  };
+
+  fn class_type(name: &str, type_arguments: Vec<TypeArgument>) -> ClassTypeSignature {
+    ClassTypeSignature {
+      name: String::from(name),
+      type_arguments,
+      inner_classes: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn test_parse_class_signature() {
+    let signature = "<T:Ljava/lang/Object;>Ljava/util/List<+TT;>;";
+    let parsed = parse_class_signature(signature).unwrap();
+
+    assert_eq!(
+      parsed.type_parameters,
+      vec![TypeParameter {
+        name: String::from("T"),
+        class_bound: Some(TypeSignature::Class(class_type(
+          "java/lang/Object",
+          Vec::new()
+        ))),
+        interface_bounds: Vec::new(),
+      }]
+    );
+    assert_eq!(
+      parsed.super_class,
+      class_type(
+        "java/util/List",
+        vec![TypeArgument::Extends(TypeSignature::TypeVariable(
+          String::from("T")
+        ))]
+      )
+    );
+    assert!(parsed.interfaces.is_empty());
+    assert_eq!(parsed.to_string(), signature);
+  }
+
+  #[test]
+  fn test_signature_round_trip() {
+    for signature in [
+      "<K:Ljava/lang/Object;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;Ljava/util/Map<TK;TV;>;Ljava/io/Serializable;",
+      "<T::Ljava/lang/Comparable<-TT;>;:Ljava/io/Serializable;>Ljava/lang/Object;",
+      "Ljava/lang/Enum<LMain$Color;>;",
+    ] {
+      assert_eq!(parse_class_signature(signature).unwrap().to_string(), signature);
+    }
+
+    for signature in [
+      "<T:Ljava/lang/Object;>(TT;[[IJD)TT;",
+      "(Ljava/util/Map<*+Ljava/lang/Number;>.Entry<Ljava/lang/String;>;)V^Ljava/io/IOException;^TE;",
+    ] {
+      assert_eq!(parse_method_signature(signature).unwrap().to_string(), signature);
+    }
+
+    for signature in ["[TT;", "Ljava/util/List<[Ljava/lang/String;>;"] {
+      assert_eq!(
+        parse_field_signature(signature).unwrap().to_string(),
+        signature
+      );
+    }
+  }
+
+  #[test]
+  fn test_parse_method_signature() {
+    let parsed = parse_method_signature("(JD)V").unwrap();
+
+    assert_eq!(
+      parsed.parameters,
+      vec![
+        TypeSignature::Base(Type::Long),
+        TypeSignature::Base(Type::Double)
+      ]
+    );
+    assert_eq!(parsed.return_type, TypeSignature::Base(Type::Void));
+  }
+
+  #[test]
+  fn test_parse_malformed_signature() {
+    for signature in [
+      "",
+      "<T:>",
+      "<>Ljava/lang/Object;",
+      "Ljava/lang/Object",
+      "Ljava/util/List<>;",
+      "Ljava//Object;",
+      "I",
+    ] {
+      assert!(
+        matches!(
+          parse_class_signature(signature),
+          Err(KapiError::ArgError(_))
+        ),
+        "{signature}"
+      );
+    }
+
+    for signature in ["()", "(V)V", "()VV", "()V^I"] {
+      assert!(
+        matches!(
+          parse_method_signature(signature),
+          Err(KapiError::ArgError(_))
+        ),
+        "{signature}"
+      );
+    }
+
+    for signature in ["I", "TT", "[V"] {
+      assert!(
+        matches!(
+          parse_field_signature(signature),
+          Err(KapiError::ArgError(_))
+        ),
+        "{signature}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_validate_signature() {
+    let type_variable = |name: &str| TypeSignature::TypeVariable(String::from(name));
+    let type_parameter = |name: &str| TypeParameter {
+      name: String::from(name),
+      class_bound: Some(TypeSignature::Class(class_type(
+        "java/lang/Object",
+        Vec::new(),
+      ))),
+      interface_bounds: Vec::new(),
+    };
+    let arguments = || {
+      vec![
+        TypeArgument::Exact(type_variable("K")),
+        TypeArgument::Exact(type_variable("V")),
+      ]
+    };
+    // Signature of java.util.HashMap emitted by javac
+    let hash_map = ClassSignature {
+      type_parameters: vec![type_parameter("K"), type_parameter("V")],
+      super_class: class_type("java/util/AbstractMap", arguments()),
+      interfaces: vec![
+        class_type("java/util/Map", arguments()),
+        class_type("java/lang/Cloneable", Vec::new()),
+        class_type("java/io/Serializable", Vec::new()),
+      ],
+    };
+
+    assert_eq!(hash_map.validate(), Ok(()));
+    assert_eq!(
+      hash_map.to_string(),
+      "<K:Ljava/lang/Object;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;Ljava/util/Map<TK;TV;>;Ljava/lang/Cloneable;Ljava/io/Serializable;"
+    );
+
+    for name in ["T;", "a/b", ""] {
+      let signature = ClassSignature {
+        type_parameters: vec![type_parameter(name)],
+        ..hash_map.clone()
+      };
+
+      assert!(
+        matches!(signature.validate(), Err(KapiError::ArgError(_))),
+        "{name}"
+      );
+    }
+
+    assert!(matches!(
+      class_type("java/lang/Obj;ect", Vec::new()).validate(),
+      Err(KapiError::ArgError(_))
+    ));
+    assert!(matches!(
+      TypeSignature::Base(Type::Int).validate(),
+      Err(KapiError::ArgError(_))
+    ));
+
+    let method = MethodSignature {
+      type_parameters: Vec::new(),
+      parameters: vec![TypeSignature::Base(Type::Int)],
+      return_type: TypeSignature::Base(Type::Void),
+      throws: vec![type_variable("E")],
+    };
+
+    assert_eq!(method.validate(), Ok(()));
+
+    for invalid_method in [
+      MethodSignature {
+        parameters: vec![TypeSignature::Base(Type::Void)],
+        ..method.clone()
+      },
+      MethodSignature {
+        return_type: TypeSignature::Array(Box::new(TypeSignature::Base(Type::Void))),
+        ..method.clone()
+      },
+      MethodSignature {
+        throws: vec![TypeSignature::Base(Type::Int)],
+        ..method.clone()
+      },
+    ] {
+      assert!(matches!(
+        invalid_method.validate(),
+        Err(KapiError::ArgError(_))
+      ));
+    }
+  }
+}
@@ -0,0 +1,190 @@
+//! Helpers assembling minimal class files directly, without going through
+//! [ClassWriter](crate::class::ClassWriter), for tests which need small
+//! valid or deliberately malformed inputs.
+
+use crate::{
+  access_flag::{
+    ClassAccessFlag,
+    MethodAccessFlag,
+  },
+  attrs,
+  byte_vec::{
+    ByteVec,
+    ByteVector,
+    ToBytes,
+  },
+  class::JavaVersion,
+  constant::ConstantPool,
+};
+
+/// Assembles a public class extending `java/lang/Object` with no members.
+pub fn minimal_class(name: &str) -> Vec<u8> {
+  assemble(name, None)
+}
+
+/// Assembles a public class `Main` with a single public static method named
+/// `method_name` whose Code attribute contains `code` as is. Neither the code
+/// nor the maxs are checked.
+pub fn class_with_method(
+  method_name: &str,
+  descriptor: &str,
+  code: &[u8],
+  max_stack: u16,
+  max_locals: u16,
+) -> Vec<u8> {
+  assemble(
+    "Main",
+    Some((method_name, descriptor, code, max_stack, max_locals)),
+  )
+}
+
+/// Copies `bytes` with the byte at `offset` replaced by `new_byte`.
+///
+/// # Panics
+///
+/// Panics if `offset` is out of bounds of `bytes`.
+pub fn corrupt_at(bytes: &[u8], offset: usize, new_byte: u8) -> Vec<u8> {
+  assert!(
+    offset < bytes.len(),
+    "Offset {offset} to corrupt is out of bounds of {} bytes",
+    bytes.len()
+  );
+
+  let mut bytes = bytes.to_vec();
+
+  bytes[offset] = new_byte;
+  bytes
+}
+
+fn assemble(class_name: &str, method: Option<(&str, &str, &[u8], u16, u16)>) -> Vec<u8> {
+  let mut cp = ConstantPool::default();
+  let this_class = cp.put_class(class_name).unwrap();
+  let super_class = cp.put_class("java/lang/Object").unwrap();
+  let mut methods = ByteVec::new();
+
+  if let Some((name, descriptor, code, max_stack, max_locals)) = method {
+    let name_index = cp.put_utf8(name).unwrap();
+    let descriptor_index = cp.put_utf8(descriptor).unwrap();
+
+    methods
+      .push_u16((MethodAccessFlag::Public | MethodAccessFlag::Static).bits())
+      .push_u16(name_index)
+      .push_u16(descriptor_index)
+      .push_u16(1)
+      .push_u16(cp.put_utf8(attrs::CODE).unwrap())
+      .push_u32(12 + code.len() as u32)
+      .push_u16(max_stack)
+      .push_u16(max_locals)
+      .push_u32(code.len() as u32)
+      .push_u8s(code)
+      .push_u16(0)
+      .push_u16(0);
+  }
+
+  let mut vec = ByteVec::new();
+
+  vec
+    .push_u32(0xCAFEBABE)
+    .push_u32(JavaVersion::V17.version());
+  cp.put_bytes(&mut vec)
+    .expect("Constant pool of assembled class is well formed");
+  vec
+    .push_u16((ClassAccessFlag::Public | ClassAccessFlag::Super).bits())
+    .push_u16(this_class)
+    .push_u16(super_class)
+    .push_u16(0)
+    .push_u16(0)
+    .push_u16(method.is_some() as u16)
+    .push_u8s(&methods)
+    .push_u16(0);
+
+  vec
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    access_flag::{
+      ClassAccessFlag,
+      MethodAccessFlag,
+    },
+    class::{
+      ClassVisitor,
+      ClassWriter,
+      JavaVersion,
+    },
+    opcodes,
+    testkit::{
+      class_with_method,
+      corrupt_at,
+      minimal_class,
+    },
+  };
+
+  fn class_writer() -> ClassWriter {
+    let mut writer = ClassWriter::new();
+
+    writer
+      .visit(
+        JavaVersion::V17,
+        ClassAccessFlag::Public | ClassAccessFlag::Super,
+        "Main",
+        None,
+        "java/lang/Object",
+        &[],
+      )
+      .unwrap();
+
+    writer
+  }
+
+  #[test]
+  fn test_minimal_class() {
+    let mut writer = class_writer();
+
+    writer.visit_end();
+
+    assert_eq!(minimal_class("Main"), writer.to_bytes().unwrap());
+  }
+
+  #[test]
+  fn test_class_with_method() {
+    let mut writer = class_writer();
+    let mw = writer
+      .visit_method(
+        MethodAccessFlag::Public | MethodAccessFlag::Static,
+        "run",
+        "(I)I",
+        None,
+        &[],
+      )
+      .unwrap()
+      .unwrap();
+
+    mw.visit_code();
+    mw.visit_simple_inst(opcodes::ILOAD_0).unwrap();
+    mw.visit_simple_inst(opcodes::IRETURN).unwrap();
+    mw.visit_maxs(1, 1);
+    writer.visit_end();
+
+    assert_eq!(
+      class_with_method("run", "(I)I", &[opcodes::ILOAD_0, opcodes::IRETURN], 1, 1),
+      writer.to_bytes().unwrap()
+    );
+  }
+
+  #[test]
+  fn test_corrupt_at() {
+    let bytes = minimal_class("Main");
+    let corrupted = corrupt_at(&bytes, 0, 0);
+
+    assert_eq!(corrupted[0], 0);
+    assert_eq!(corrupted[1..], bytes[1..]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Offset 100 to corrupt is out of bounds of")]
+  fn test_corrupt_at_out_of_bounds() {
+    corrupt_at(&minimal_class("Main"), 100, 0);
+  }
+}
@@ -1,69 +1,456 @@
-pub fn compute_method_descriptor_sizes(descriptor: &str, is_static: bool) -> (u16, u16) {
-  let mut arg_size = if is_static { 1 } else { 0 };
-  let mut chars = descriptor.chars().peekable();
+use std::fmt::{
+  Display,
+  Formatter,
+};
 
-  chars.next(); // Skips '('
+use crate::error::{
+  KapiError,
+  KapiResult,
+};
 
-  loop {
-    let Some(mut char) = chars.next() else {
-      panic!("Incomplete method descriptor `{descriptor}` while computing sizes");
-    };
+/// Maximum dimensions of an array type, see JVMS 4.3.2.
+const MAX_ARRAY_DIMENSIONS: usize = 255;
 
-    if char == ')' {
-      break;
-    }
+/// A Java type, see JVMS 4.3.2.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+  Boolean,
+  Char,
+  Byte,
+  Short,
+  Int,
+  Float,
+  Long,
+  Double,
+  Void,
+  /// A class or interface type of given internal name.
+  Object(String),
+  Array(Box<Type>),
+}
 
-    match char {
-      'J' | 'D' => arg_size += 2,
-      _ => {
-        if char == '[' {
-          while let Some(_) = chars.next_if_eq(&'[') {}
-
-          let Some(starting_char) = chars.next() else {
-            panic!("Incomplete method descriptor `{descriptor}` while computing sizes");
-          };
-
-          char = starting_char;
-        }
-        
-        if char == 'L' {
-          while let Some(_) = chars.next_if(|&c| c != ';') {}
-      
-          chars.next(); // Skips ';'
-        }
-
-        arg_size += 1;
-      }
+impl Type {
+  /// Gets the descriptor of the type, e.g. `I` or `Ljava/lang/String;`.
+  pub fn descriptor(&self) -> String {
+    match self {
+      Type::Boolean => String::from("Z"),
+      Type::Char => String::from("C"),
+      Type::Byte => String::from("B"),
+      Type::Short => String::from("S"),
+      Type::Int => String::from("I"),
+      Type::Float => String::from("F"),
+      Type::Long => String::from("J"),
+      Type::Double => String::from("D"),
+      Type::Void => String::from("V"),
+      Type::Object(internal_name) => format!("L{internal_name};"),
+      Type::Array(component_type) => format!("[{}", component_type.descriptor()),
     }
   }
 
-  let return_size = match chars.next() {
-    Some(char) => match char {
-      'V' => 0,
-      'J' | 'D' => 2,
+  /// Gets the amount of local variable slots or operand stack words the type
+  /// takes.
+  pub const fn size(&self) -> u16 {
+    match self {
+      Type::Void => 0,
+      Type::Long | Type::Double => 2,
       _ => 1,
-    },
-    None => {
-      panic!("Incomplete method descriptor `{descriptor}` while computing sizes");
+    }
+  }
+
+  /// Gets the internal name used by Class constants, which is the descriptor
+  /// for array types, see JVMS 4.4.1. [None] for primitive types and void.
+  pub fn internal_name(&self) -> Option<String> {
+    match self {
+      Type::Object(internal_name) => Some(internal_name.clone()),
+      Type::Array(..) => Some(self.descriptor()),
+      _ => None,
+    }
+  }
+
+  /// Gets the number of array dimensions, 0 for non-array types.
+  pub fn dimensions(&self) -> usize {
+    match self {
+      Type::Array(component_type) => 1 + component_type.dimensions(),
+      _ => 0,
+    }
+  }
+
+  /// Gets the innermost element type of an array type, or the type itself
+  /// for non-array types.
+  pub fn element_type(&self) -> &Type {
+    match self {
+      Type::Array(component_type) => component_type.element_type(),
+      _ => self,
+    }
+  }
+
+  /// Whether the type is a primitive value type, [Type::Void] is not
+  /// considered as one.
+  pub const fn is_primitive(&self) -> bool {
+    matches!(
+      self,
+      Type::Boolean
+        | Type::Char
+        | Type::Byte
+        | Type::Short
+        | Type::Int
+        | Type::Float
+        | Type::Long
+        | Type::Double
+    )
+  }
+
+  /// Gets the internal name of the wrapper class of a primitive type and the
+  /// name of the wrapper's method unboxing it.
+  pub(crate) const fn wrapper(&self) -> Option<(&'static str, &'static str)> {
+    match self {
+      Type::Boolean => Some(("java/lang/Boolean", "booleanValue")),
+      Type::Char => Some(("java/lang/Character", "charValue")),
+      Type::Byte => Some(("java/lang/Byte", "byteValue")),
+      Type::Short => Some(("java/lang/Short", "shortValue")),
+      Type::Int => Some(("java/lang/Integer", "intValue")),
+      Type::Float => Some(("java/lang/Float", "floatValue")),
+      Type::Long => Some(("java/lang/Long", "longValue")),
+      Type::Double => Some(("java/lang/Double", "doubleValue")),
+      _ => None,
+    }
+  }
+}
+
+impl Display for Type {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Type::Boolean => write!(f, "boolean"),
+      Type::Char => write!(f, "char"),
+      Type::Byte => write!(f, "byte"),
+      Type::Short => write!(f, "short"),
+      Type::Int => write!(f, "int"),
+      Type::Float => write!(f, "float"),
+      Type::Long => write!(f, "long"),
+      Type::Double => write!(f, "double"),
+      Type::Void => write!(f, "void"),
+      Type::Object(internal_name) => write!(f, "{}", internal_name.replace('/', ".")),
+      Type::Array(component_type) => write!(f, "{component_type}[]"),
+    }
+  }
+}
+
+/// Parses a field descriptor, e.g. `[Ljava/lang/String;`, see JVMS 4.3.2.
+pub fn parse_field_descriptor(descriptor: &str) -> KapiResult<Type> {
+  let (typ, len) = parse_type(descriptor, 0)?;
+
+  if typ == Type::Void {
+    return Err(malformed_descriptor(descriptor, "void is not a field type"));
+  }
+
+  if len != descriptor.len() {
+    return Err(malformed_descriptor(
+      descriptor,
+      &format!("unexpected trailing characters at {len}"),
+    ));
+  }
+
+  Ok(typ)
+}
+
+/// Parses a method descriptor into its parameter types and return type, e.g.
+/// `(IJ)V`, see JVMS 4.3.3.
+pub fn parse_method_descriptor(descriptor: &str) -> KapiResult<(Vec<Type>, Type)> {
+  if !descriptor.starts_with('(') {
+    return Err(malformed_descriptor(descriptor, "missing `(`"));
+  }
+
+  let mut parameter_types = Vec::new();
+  let mut offset = 1;
+
+  while !descriptor[offset..].starts_with(')') {
+    let (typ, next_offset) = parse_type(descriptor, offset)?;
+
+    if typ == Type::Void {
+      return Err(malformed_descriptor(
+        descriptor,
+        &format!("void parameter at {offset}"),
+      ));
+    }
+
+    parameter_types.push(typ);
+    offset = next_offset;
+  }
+
+  let (return_type, len) = parse_type(descriptor, offset + 1)?;
+
+  if len != descriptor.len() {
+    return Err(malformed_descriptor(
+      descriptor,
+      &format!("unexpected trailing characters at {len}"),
+    ));
+  }
+
+  Ok((parameter_types, return_type))
+}
+
+/// Parses a type starting at given byte offset of descriptor, returns the
+/// type and the offset right after it. Array dimensions are counted instead
+/// of recursed into, so that deeply nested arrays never overflow the stack.
+fn parse_type(descriptor: &str, offset: usize) -> KapiResult<(Type, usize)> {
+  let dimensions = descriptor[offset..]
+    .bytes()
+    .take_while(|byte| *byte == b'[')
+    .count();
+
+  if dimensions > MAX_ARRAY_DIMENSIONS {
+    return Err(malformed_descriptor(
+      descriptor,
+      &format!(
+        "array of {dimensions} dimensions at {offset} exceeds the limit of {MAX_ARRAY_DIMENSIONS}"
+      ),
+    ));
+  }
+
+  let element_offset = offset + dimensions;
+  let (mut typ, next_offset) = parse_element_type(descriptor, element_offset)?;
+
+  if dimensions > 0 && typ == Type::Void {
+    return Err(malformed_descriptor(
+      descriptor,
+      &format!("void array component at {element_offset}"),
+    ));
+  }
+
+  for _ in 0..dimensions {
+    typ = Type::Array(Box::new(typ));
+  }
+
+  Ok((typ, next_offset))
+}
+
+/// Parses a non-array type starting at given byte offset of descriptor.
+fn parse_element_type(descriptor: &str, offset: usize) -> KapiResult<(Type, usize)> {
+  let Some(char) = descriptor[offset..].chars().next() else {
+    return Err(malformed_descriptor(
+      descriptor,
+      &format!("expected type at {offset}"),
+    ));
+  };
+
+  let typ = match char {
+    'Z' => Type::Boolean,
+    'C' => Type::Char,
+    'B' => Type::Byte,
+    'S' => Type::Short,
+    'I' => Type::Int,
+    'F' => Type::Float,
+    'J' => Type::Long,
+    'D' => Type::Double,
+    'V' => Type::Void,
+    'L' => {
+      let Some(len) = descriptor[offset..].find(';') else {
+        return Err(malformed_descriptor(
+          descriptor,
+          &format!("unterminated class type at {offset}"),
+        ));
+      };
+
+      if len == 1 {
+        return Err(malformed_descriptor(
+          descriptor,
+          &format!("empty class name at {offset}"),
+        ));
+      }
+
+      return Ok((
+        Type::Object(descriptor[offset + 1..offset + len].to_owned()),
+        offset + len + 1,
+      ));
+    }
+    char => {
+      return Err(malformed_descriptor(
+        descriptor,
+        &format!("unexpected `{char}` at {offset}"),
+      ))
     }
   };
 
-  (arg_size, return_size)
+  Ok((typ, offset + 1))
+}
+
+fn malformed_descriptor(descriptor: &str, reason: &str) -> KapiError {
+  KapiError::InvalidDescriptor {
+    descriptor: descriptor.to_owned(),
+    reason: reason.to_owned(),
+  }
+}
+
+/// Computes the size of arguments, including the implicit `this` of
+/// instance methods, and the size of return value of a method descriptor.
+pub fn compute_method_descriptor_sizes(
+  descriptor: &str,
+  is_static: bool,
+) -> KapiResult<(u16, u16)> {
+  let (parameter_types, return_type) = parse_method_descriptor(descriptor)?;
+  let this_size = if is_static { 0 } else { 1 };
+  let arguments_size = parameter_types.iter().map(Type::size).sum::<u16>() + this_size;
+
+  Ok((arguments_size, return_type.size()))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::types::compute_method_descriptor_sizes;
+  use crate::{
+    error::KapiError,
+    types::{
+      compute_method_descriptor_sizes,
+      parse_field_descriptor,
+      parse_method_descriptor,
+      Type,
+    },
+  };
 
   #[test]
   fn test_computing_method_descriptor_size() {
-      assert_eq!(compute_method_descriptor_sizes("(JDJ)V", false), (6, 0));
-      assert_eq!(compute_method_descriptor_sizes("([[J[[I)V", false), (2, 0));
-      assert_eq!(compute_method_descriptor_sizes("([[Ljava/lang/String;I)V", false), (2, 0));
-      assert_eq!(compute_method_descriptor_sizes("(Ljava/lang/String;Ljava/lang/Class;)V", false), (2, 0));
-      assert_eq!(compute_method_descriptor_sizes("()V", true), (1, 0));
-      assert_eq!(compute_method_descriptor_sizes("(I)V", true), (2, 0));
-      assert_eq!(compute_method_descriptor_sizes("()Z", true), (1, 1));
-      assert_eq!(compute_method_descriptor_sizes("(J)Z", true), (3, 1));
+    assert_eq!(compute_method_descriptor_sizes("(JDJ)V", false), Ok((7, 0)));
+    assert_eq!(
+      compute_method_descriptor_sizes("([[J[[I)V", false),
+      Ok((3, 0))
+    );
+    assert_eq!(
+      compute_method_descriptor_sizes("([[Ljava/lang/String;I)V", false),
+      Ok((3, 0))
+    );
+    assert_eq!(
+      compute_method_descriptor_sizes("(Ljava/lang/String;Ljava/lang/Class;)V", false),
+      Ok((3, 0))
+    );
+    assert_eq!(compute_method_descriptor_sizes("()V", true), Ok((0, 0)));
+    assert_eq!(compute_method_descriptor_sizes("(I)V", true), Ok((1, 0)));
+    assert_eq!(compute_method_descriptor_sizes("()Z", true), Ok((0, 1)));
+    assert_eq!(compute_method_descriptor_sizes("(J)Z", true), Ok((2, 1)));
+
+    for descriptor in ["(", "(I", "([)V", "(Ljava/lang/String)V", "()"] {
+      assert!(
+        matches!(
+          compute_method_descriptor_sizes(descriptor, true),
+          Err(KapiError::InvalidDescriptor { .. })
+        ),
+        "{descriptor}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_type_descriptor() {
+    assert_eq!(Type::Int.descriptor(), "I");
+    assert_eq!(
+      Type::Array(Box::new(Type::Array(Box::new(Type::Object(String::from(
+        "java/lang/String"
+      ))))))
+      .descriptor(),
+      "[[Ljava/lang/String;"
+    );
+    assert!(Type::Boolean.is_primitive());
+    assert!(!Type::Void.is_primitive());
+  }
+
+  #[test]
+  fn test_parse_field_descriptor() {
+    let string = || Type::Object(String::from("java/lang/String"));
+
+    for (descriptor, typ, name) in [
+      ("Z", Type::Boolean, "boolean"),
+      ("C", Type::Char, "char"),
+      ("B", Type::Byte, "byte"),
+      ("S", Type::Short, "short"),
+      ("I", Type::Int, "int"),
+      ("F", Type::Float, "float"),
+      ("J", Type::Long, "long"),
+      ("D", Type::Double, "double"),
+      ("Ljava/lang/String;", string(), "java.lang.String"),
+      (
+        "[[Ljava/lang/String;",
+        Type::Array(Box::new(Type::Array(Box::new(string())))),
+        "java.lang.String[][]",
+      ),
+      ("[J", Type::Array(Box::new(Type::Long)), "long[]"),
+    ] {
+      let parsed = parse_field_descriptor(descriptor).unwrap();
+
+      assert_eq!(parsed, typ);
+      assert_eq!(parsed.descriptor(), descriptor);
+      assert_eq!(parsed.to_string(), name);
+    }
+
+    let array = parse_field_descriptor("[[[I").unwrap();
+
+    assert_eq!(array.dimensions(), 3);
+    assert_eq!(array.element_type(), &Type::Int);
+    assert_eq!(array.internal_name(), Some(String::from("[[[I")));
+    assert_eq!(array.size(), 1);
+    assert_eq!(
+      string().internal_name(),
+      Some(String::from("java/lang/String"))
+    );
+    assert_eq!(Type::Double.internal_name(), None);
+    assert_eq!(Type::Double.size(), 2);
+    assert_eq!(Type::Void.size(), 0);
+  }
+
+  #[test]
+  fn test_parse_method_descriptor() {
+    assert_eq!(
+      parse_method_descriptor("(JD[[ILjava/lang/Object;)V").unwrap(),
+      (
+        vec![
+          Type::Long,
+          Type::Double,
+          Type::Array(Box::new(Type::Array(Box::new(Type::Int)))),
+          Type::Object(String::from("java/lang/Object")),
+        ],
+        Type::Void,
+      )
+    );
+    assert_eq!(
+      parse_method_descriptor("()[J").unwrap(),
+      (Vec::new(), Type::Array(Box::new(Type::Long)))
+    );
+  }
+
+  #[test]
+  fn test_parse_malformed_descriptor() {
+    for descriptor in ["", "V", "[V", "Ljava/lang/String", "L;", "II", "Q"] {
+      assert!(
+        matches!(
+          parse_field_descriptor(descriptor),
+          Err(KapiError::InvalidDescriptor { .. })
+        ),
+        "{descriptor}"
+      );
+    }
+
+    let deepest = format!("{}I", "[".repeat(255));
+    let too_deep = format!("{}I", "[".repeat(256));
+
+    assert_eq!(parse_field_descriptor(&deepest).unwrap().dimensions(), 255);
+    assert_eq!(
+      parse_field_descriptor(&too_deep),
+      Err(KapiError::InvalidDescriptor {
+        descriptor: too_deep.clone(),
+        reason: String::from("array of 256 dimensions at 0 exceeds the limit of 255"),
+      })
+    );
+    assert!(matches!(
+      parse_method_descriptor(&format!("(I{too_deep})V")),
+      Err(KapiError::InvalidDescriptor { reason, .. }) if reason.ends_with("at 2 exceeds the limit of 255")
+    ));
+    // Never recurses into dimensions, even far beyond the limit
+    assert!(parse_field_descriptor(&"[".repeat(1_000_000)).is_err());
+
+    for descriptor in ["", "I", "(I", "(V)V", "()", "()VV", "(Ljava/lang/String)V"] {
+      assert!(
+        matches!(
+          parse_method_descriptor(descriptor),
+          Err(KapiError::InvalidDescriptor { .. })
+        ),
+        "{descriptor}"
+      );
+    }
   }
 }
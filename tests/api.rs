@@ -0,0 +1,10 @@
+//! Downstream usage programs which must keep compiling against the public
+//! API. A failure here means a change breaks existing users, so either keep
+//! the old API around or bump the version accordingly.
+
+#[test]
+fn test_public_api() {
+  let t = trybuild::TestCases::new();
+
+  t.pass("tests/api/*.rs");
+}
@@ -0,0 +1,78 @@
+use ka_pi::{
+  access_flag::{
+    ClassAccessFlag,
+    MethodAccessFlag,
+  },
+  class::{
+    ClassVisitor,
+    ClassWriter,
+    JavaVersion,
+  },
+  error::KapiResult,
+  opcodes,
+};
+
+/// Adapter renaming every visited class before delegating to the wrapped
+/// visitor, the way transformations are chained.
+struct Renamer<V: ClassVisitor> {
+  inner: V,
+  name: &'static str,
+}
+
+impl<V: ClassVisitor> ClassVisitor for Renamer<V> {
+  fn inner(&mut self) -> Option<&mut dyn ClassVisitor> {
+    Some(&mut self.inner)
+  }
+
+  fn visit(
+    &mut self,
+    version: JavaVersion,
+    access: ClassAccessFlag,
+    _name: &str,
+    signature: Option<&str>,
+    super_name: &str,
+    interfaces: &[&str],
+  ) -> KapiResult<()> {
+    self.inner.visit(
+      version,
+      access,
+      self.name,
+      signature,
+      super_name,
+      interfaces,
+    )
+  }
+}
+
+fn emit(visitor: &mut dyn ClassVisitor) -> KapiResult<()> {
+  visitor.visit(
+    JavaVersion::V11,
+    ClassAccessFlag::Super,
+    "Original",
+    None,
+    "java/lang/Object",
+    &["java/lang/Runnable"],
+  )?;
+
+  if let Some(mv) = visitor.visit_method(MethodAccessFlag::Public, "run", "()V", None, &[])? {
+    mv.visit_code();
+    mv.visit_simple_inst(opcodes::RETURN)?;
+    mv.visit_maxs(0, 1);
+  }
+
+  visitor.visit_end();
+
+  Ok(())
+}
+
+fn main() {
+  let mut renamer = Renamer {
+    inner: ClassWriter::new(),
+    name: "Renamed",
+  };
+
+  emit(&mut renamer).unwrap();
+
+  assert_eq!(renamer.inner.class_name(), Some("Renamed"));
+  assert!(renamer.inner.to_bytes().is_ok());
+}
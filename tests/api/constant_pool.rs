@@ -0,0 +1,66 @@
+use ka_pi::{
+  access_flag::{
+    ClassAccessFlag,
+    MethodAccessFlag,
+  },
+  class::{
+    ClassVisitor,
+    ClassWriter,
+    JavaVersion,
+  },
+  constant::{
+    Constant,
+    ConstantTag,
+  },
+  error::KapiResult,
+  opcodes,
+};
+
+fn main() -> KapiResult<()> {
+  let mut writer = ClassWriter::new();
+
+  writer.visit(
+    JavaVersion::V17,
+    ClassAccessFlag::Super | ClassAccessFlag::Public,
+    "Main",
+    None,
+    "java/lang/Object",
+    &[],
+  )?;
+
+  let mw = writer
+    .visit_method(
+      MethodAccessFlag::Public | MethodAccessFlag::Static,
+      "main",
+      "([Ljava/lang/String;)V",
+      None,
+      &[],
+    )?
+    .expect("ClassWriter always returns a method visitor");
+
+  mw.visit_code();
+  mw.visit_field_inst(
+    opcodes::GETSTATIC,
+    "java/lang/System",
+    "out",
+    "Ljava/io/PrintStream;",
+  )?;
+
+  let pool = writer.constant_pool();
+  let this_class = writer.this_class_index().unwrap();
+  let (field_index, _) = pool.constants_of(ConstantTag::FieldRef).next().unwrap();
+  let field = pool.resolve_field_ref(field_index).unwrap();
+
+  assert_eq!(pool.resolve_class_name(this_class), Some("Main"));
+  assert_eq!(pool.resolve_utf8(this_class), None);
+  assert_eq!(field.owner, "java/lang/System");
+  assert_eq!(field.name, "out");
+  assert_eq!(field.descriptor, "Ljava/io/PrintStream;");
+
+  assert!(pool.contains_tag(ConstantTag::Utf8));
+  assert!(!pool.contains_tag(ConstantTag::Long));
+  assert!(pool.indices().eq(pool.iter().map(|(index, _)| index)));
+  assert!(matches!(pool.get(this_class), Some(Constant::Class(_))));
+
+  Ok(())
+}
@@ -0,0 +1,66 @@
+use ka_pi::{
+  access_flag::{
+    ClassAccessFlag,
+    MethodAccessFlag,
+  },
+  class::{
+    ClassVisitor,
+    ClassWriter,
+    ComputeOption,
+    JavaVersion,
+  },
+  error::KapiResult,
+  method::LdcConstant,
+  opcodes,
+};
+
+fn generate() -> KapiResult<Vec<u8>> {
+  let mut writer = ClassWriter::new().compute(ComputeOption::Maxs);
+
+  writer.visit(
+    JavaVersion::V17,
+    ClassAccessFlag::Super | ClassAccessFlag::Public,
+    "Main",
+    None,
+    "java/lang/Object",
+    &[],
+  )?;
+  writer.visit_source("Main.java");
+
+  let mw = writer
+    .visit_method(
+      MethodAccessFlag::Public | MethodAccessFlag::Static,
+      "main",
+      "([Ljava/lang/String;)V",
+      None,
+      &[],
+    )?
+    .expect("ClassWriter always returns a method visitor");
+
+  mw.visit_code();
+  mw.visit_field_inst(
+    opcodes::GETSTATIC,
+    "java/lang/System",
+    "out",
+    "Ljava/io/PrintStream;",
+  )?;
+  mw.visit_ldc_inst(LdcConstant::String(String::from("Hello, World!")))?;
+  mw.visit_method_inst(
+    opcodes::INVOKEVIRTUAL,
+    "java/io/PrintStream",
+    "println",
+    "(Ljava/lang/String;)V",
+    false,
+  )?;
+  mw.visit_simple_inst(opcodes::RETURN)?;
+  mw.visit_maxs(0, 0);
+
+  writer.visit_end();
+  writer.to_bytes()
+}
+
+fn main() {
+  let bytes = generate().unwrap();
+
+  assert_eq!(bytes[..4], [0xCA, 0xFE, 0xBA, 0xBE]);
+}
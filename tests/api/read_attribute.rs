@@ -0,0 +1,34 @@
+use ka_pi::{
+  byte_reader::ByteReader,
+  error::{
+    KapiError,
+    KapiResult,
+  },
+};
+
+/// Info of a custom attribute holding a version and a list of names.
+struct Info {
+  version: u16,
+  names: Vec<String>,
+}
+
+fn parse(bytes: &[u8]) -> KapiResult<Info> {
+  let mut reader = ByteReader::new(bytes);
+  let version = reader.read_u16()?;
+  let names = (0..reader.read_u16()?)
+    .map(|_| reader.read_utf8_with_len())
+    .collect::<KapiResult<Vec<_>>>()?;
+
+  Ok(Info { version, names })
+}
+
+fn main() {
+  let info = parse(&[0, 1, 0, 2, 0, 1, b'a', 0, 2, b'b', b'c']).unwrap();
+
+  assert_eq!(info.version, 1);
+  assert_eq!(info.names, ["a", "bc"]);
+  assert!(matches!(
+    parse(&[0, 1, 0, 1]),
+    Err(KapiError::UnexpectedEnd { offset: 4, .. })
+  ));
+}